@@ -0,0 +1,69 @@
+/*
+    Times each program in benches/programs with threaded, direct, switch
+    and token dispatch at every level of optimization, taking the best of
+    a few runs of each so that the numbers are steady, and then on a
+    sparse tape, to show what looking up each cell in a map costs. Output
+    goes to a sink, so that writing it does not dominate. The programs
+    are primes.bf, which is mostly arithmetic, and Daniel Cristofani's
+    bsort.bf, which moves bytes about the tape as it bubble sorts the 400
+    in bsort.in. Any other `.bf` file dropped into that folder is timed
+    too, and one that reads input gets the matching `.in` file.
+
+    Usage: dispatch [RUNS]
+*/
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use brainfuck::{sparse, Compiler, Dispatch, Engine, Program, MEMORY_SIZE};
+
+const PROGRAMS: &str = "benches/programs";
+
+fn time( program: &Program, input: &[ u8 ], dispatch: Dispatch ) -> Duration {
+    let mut engine = Engine::< i8 >::new( program, MEMORY_SIZE ).with_io( input, std::io::sink() );
+    let start = Instant::now();
+    let result = match dispatch {
+        Dispatch::Threaded => engine.run(),
+        Dispatch::Direct => engine.run_direct(),
+        Dispatch::Switch => engine.run_switch(),
+        Dispatch::Token => engine.run_token(),
+    };
+    let elapsed = start.elapsed();
+    result.unwrap();
+    elapsed
+}
+
+fn time_sparse( program: &Program, input: &[ u8 ] ) -> Duration {
+    let start = Instant::now();
+    sparse::run_sparse::< i8 >( program, input, std::io::sink() ).unwrap();
+    start.elapsed()
+}
+
+fn main() {
+    let runs = std::env::args().nth( 1 ).and_then( | n | n.parse().ok() ).unwrap_or( 3 );
+    let mut files: Vec< PathBuf > = fs::read_dir( PROGRAMS ).unwrap()
+        .map( | entry | entry.unwrap().path() )
+        .filter( | path | path.extension().is_some_and( | ext | ext == "bf" ) )
+        .collect();
+    files.sort();
+    println!( "{:<16} {:<10} {:>5} {:>10}", "program", "dispatch", "opt", "best (ms)" );
+    for file in files {
+        let src = fs::read_to_string( &file ).unwrap();
+        let input = fs::read( file.with_extension( "in" ) ).unwrap_or_default();
+        let name = file.file_stem().unwrap().to_string_lossy();
+        for dispatch in [ Dispatch::Threaded, Dispatch::Direct, Dispatch::Switch, Dispatch::Token ] {
+            for level in 0..=3 {
+                let program = Compiler::new().opt_level( level ).debug_op( false ).compile( &src ).unwrap();
+                let best = ( 0..runs ).map( | _ | time( &program, &input, dispatch ) ).min().unwrap_or_default();
+                let dispatch = format!( "{:?}", dispatch ).to_lowercase();
+                println!( "{:<16} {:<10} {:>5} {:>10.1}", name, dispatch, level, best.as_secs_f64() * 1000.0 );
+            }
+        }
+        for level in 0..=3 {
+            let program = Compiler::new().opt_level( level ).debug_op( false ).compile( &src ).unwrap();
+            let best = ( 0..runs ).map( | _ | time_sparse( &program, &input ) ).min().unwrap_or_default();
+            println!( "{:<16} {:<10} {:>5} {:>10.1}", name, "sparse", level, best.as_secs_f64() * 1000.0 );
+        }
+    }
+}
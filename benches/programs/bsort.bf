@@ -0,0 +1,14 @@
+[bsort.b -- bubble sort
+(c) 2016 Daniel B. Cristofani
+http://brainfuck.org/]
+
+>>,[>>,]<<[
+[<<]>>>>[
+<<[>+<<+>-]
+>>[>+<<<<[->]>[<]>>-]
+<<<[[-]>>[>+<-]>>[<<<+>>>-]]
+>>[[<+>-]>>]<
+]<<[>>+<<-]<<
+]>>>>[.>>]
+
+[This program sorts the bytes of its input by bubble sort.]
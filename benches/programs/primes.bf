@@ -0,0 +1,15 @@
+primes
+
+Prints P or a dot for each number from 2 to 200 according to whether
+it is prime or not and then a newline
+It tests each number by trial division using the divmod loop from
+brainfuck dot org so the inner loop runs about n squared times
+
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
++++++++++++++++++++++++++++++++++++++++++++>++<[>>>>+<++<<[->+>>>+<<<<]>>>>[-<
+<<<+>>>>]<<<--[<[->>>>>+<+<<<<]>>>>[-<<<<+>>>>]<<[->>>>+<<+<<]>>[-<<+>>]>[->-[
+>+>>]>[+[-<+>]>+>>]<<<<<]>[-]>>[-]<<<<+>>>[<<<[-]>>>[-]]<<<[<[-]>[-]]<<+<-]>[-
+]>>>>>>>>>++++++++++++++++++++++++++++++++++++++++++++++<<<<<<<<[>>>>>>>>+++++
++++++++++++++++++++++++++++++<<<<<<<<[-]]>>>>>>>>.[-]<<<<<<<<<<<+<-]>>>>>>>>>>
+>>++++++++++.
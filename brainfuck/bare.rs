@@ -0,0 +1,221 @@
+/*
+    A small engine that needs only `core` and `alloc`, for trying threaded
+    code on targets without `std`. It compiles the eight commands, folding
+    runs of `+`, `-`, `>` and `<`, into the same parallel arrays of function
+    pointers and operands as the full engine, and runs them on a tape of
+    wrapping 8-bit cells. Input and output are closures rather than `Read`
+    and `Write`, so the caller decides where the bytes come from and go.
+
+    Building the library with `--cfg 'feature="no_std"'` leaves out
+    everything else, which needs `std`.
+*/
+
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Why a bare program failed to compile or run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BareError {
+    /// `position` is a character offset into the source.
+    UnmatchedOpen { position: usize },
+    UnmatchedClose { position: usize },
+    PointerUnderflow { pc: usize },
+    PointerOverflow { pc: usize },
+}
+
+impl fmt::Display for BareError {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            BareError::UnmatchedOpen { position } => write!( f, "Unmatched opening bracket at {}", position ),
+            BareError::UnmatchedClose { position } => write!( f, "Unmatched closing bracket at {}", position ),
+            BareError::PointerUnderflow { pc } => write!( f, "Data pointer moved left of cell 0 (pc={})", pc ),
+            BareError::PointerOverflow { pc } => write!( f, "Data pointer moved past the last cell (pc={})", pc ),
+        }
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl std::error::Error for BareError {}
+
+type OpCode = fn( &mut Machine ) -> Result< (), BareError >;
+
+/// A compiled program, ending in HALT.
+pub struct Program {
+    program : Vec< OpCode >,
+    operands : Vec< usize >,
+}
+
+impl Program {
+
+    /// The number of opcodes, counting the final HALT.
+    pub fn len( &self ) -> usize {
+        self.program.len()
+    }
+
+    pub fn is_empty( &self ) -> bool {
+        self.program.is_empty()
+    }
+
+}
+
+/// Compiles `src`, ignoring every character that is not a command.
+///
+/// ```
+/// assert_eq!( brainfuck::bare::compile( "+++[>++<-]" ).unwrap().len(), 8 );
+/// assert!( brainfuck::bare::compile( "+]" ).is_err() );
+/// ```
+pub fn compile( src: &str ) -> Result< Program, BareError > {
+    let mut program = Program { program: Vec::new(), operands: Vec::new() };
+    let mut opens = Vec::< ( usize, usize ) >::new();
+    let mut last = None;
+    for ( position, ch ) in src.chars().enumerate() {
+        let opc: OpCode = match ch {
+            '+' => INCR_N,
+            '-' => DECR_N,
+            '>' => RIGHT_N,
+            '<' => LEFT_N,
+            '[' => OPEN,
+            ']' => CLOSE,
+            '.' => PUT,
+            ',' => GET,
+            _ => continue,
+        };
+        let top = program.program.len();
+        let operand = match ch {
+            //  A run is folded into the count of its first opcode.
+            '+' | '-' | '>' | '<' if last == Some( ch ) => {
+                program.operands[ top - 1 ] += 1;
+                continue;
+            },
+            '[' => {
+                opens.push( ( top, position ) );
+                0
+            },
+            //  A jump goes to the instruction after the matching bracket.
+            ']' => {
+                let ( start, _ ) = opens.pop().ok_or( BareError::UnmatchedClose { position } )?;
+                program.operands[ start ] = top + 1;
+                start + 1
+            },
+            _ => 1,
+        };
+        last = Some( ch );
+        program.program.push( opc );
+        program.operands.push( operand );
+    }
+    if let Some( &( _, position ) ) = opens.first() {
+        return Err( BareError::UnmatchedOpen { position } );
+    }
+    program.program.push( HALT );
+    program.operands.push( 0 );
+    Ok( program )
+}
+
+/// Runs `program` on a tape of `cells` cells, calling `input` for each `,`
+/// and `output` for each `.`, and returns the tape. When `input` gives
+/// `None` the cell is left unchanged, so a program that expects 0 at the
+/// end of its input needs `input` to give that instead.
+///
+/// ```
+/// let program = brainfuck::bare::compile( ",[.,]" ).unwrap();
+/// let mut input = b"echo".iter().copied();
+/// let mut output = Vec::new();
+/// brainfuck::bare::run( &program, 1, || Some( input.next().unwrap_or( 0 ) ), | byte | output.push( byte ) ).unwrap();
+/// assert_eq!( output, b"echo" );
+/// ```
+pub fn run( program: &Program, cells: usize, mut input: impl FnMut() -> Option< u8 >, mut output: impl FnMut( u8 ) ) -> Result< Vec< u8 >, BareError > {
+    let mut machine = Machine {
+        operands: &program.operands,
+        pc: 0,
+        memory: alloc::vec![ 0; cells.max( 1 ) ],
+        loc: 0,
+        halted: false,
+        input: &mut input,
+        output: &mut output,
+    };
+    while !machine.halted {
+        let opc = program.program[ machine.pc ];
+        opc( &mut machine )?;
+    }
+    Ok( machine.memory )
+}
+
+//  The state of a run, which the opcodes share.
+struct Machine< 'a > {
+    operands : &'a [ usize ],
+    pc : usize,
+    memory : Vec< u8 >,
+    loc : usize,
+    halted : bool,
+    input : &'a mut dyn FnMut() -> Option< u8 >,
+    output : &'a mut dyn FnMut( u8 ),
+}
+
+//  Truncating a count to a byte wraps it as the cells do.
+#[allow(non_snake_case)]
+fn INCR_N( m : &mut Machine ) -> Result< (), BareError > {
+    m.memory[ m.loc ] = m.memory[ m.loc ].wrapping_add( m.operands[ m.pc ] as u8 );
+    m.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+fn DECR_N( m : &mut Machine ) -> Result< (), BareError > {
+    m.memory[ m.loc ] = m.memory[ m.loc ].wrapping_sub( m.operands[ m.pc ] as u8 );
+    m.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+fn RIGHT_N( m : &mut Machine ) -> Result< (), BareError > {
+    match m.loc.checked_add( m.operands[ m.pc ] ).filter( | &loc | loc < m.memory.len() ) {
+        Some( loc ) => m.loc = loc,
+        None => return Err( BareError::PointerOverflow { pc: m.pc } ),
+    }
+    m.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+fn LEFT_N( m : &mut Machine ) -> Result< (), BareError > {
+    match m.loc.checked_sub( m.operands[ m.pc ] ) {
+        Some( loc ) => m.loc = loc,
+        None => return Err( BareError::PointerUnderflow { pc: m.pc } ),
+    }
+    m.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+fn OPEN( m : &mut Machine ) -> Result< (), BareError > {
+    m.pc = if m.memory[ m.loc ] == 0 { m.operands[ m.pc ] } else { m.pc + 1 };
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+fn CLOSE( m : &mut Machine ) -> Result< (), BareError > {
+    m.pc = if m.memory[ m.loc ] != 0 { m.operands[ m.pc ] } else { m.pc + 1 };
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+fn PUT( m : &mut Machine ) -> Result< (), BareError > {
+    ( m.output )( m.memory[ m.loc ] );
+    m.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+fn GET( m : &mut Machine ) -> Result< (), BareError > {
+    if let Some( byte ) = ( m.input )() {
+        m.memory[ m.loc ] = byte;
+    }
+    m.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+fn HALT( m : &mut Machine ) -> Result< (), BareError > {
+    m.halted = true;
+    Ok(())
+}
@@ -0,0 +1,177 @@
+/*
+    A compact binary form of a compiled program, which is quicker to load
+    than the JSON form and shows the threaded layout directly. After a
+    magic number and a version byte, each instruction up to the final HALT
+    is written as an opcode id byte followed by its operand as an unsigned
+    LEB128 varint. Then come the targets of the MULADDs and ADD_ATs: the
+    number of them, and for each the number of its targets followed by an
+    offset and a factor for each, in the same varints as the operands, with
+    negative numbers in two's complement. Source positions are not kept by
+    this form, but `save` adds them after it, for caching a program without
+    losing the positions that errors are reported at.
+*/
+
+use std::io::{Read, Write};
+
+use crate::compiler::{Program, SourcePos};
+use crate::engine::{Op, Slot};
+use crate::error::BfError;
+
+const MAGIC: &[ u8; 4 ] = b"BFC\0";
+const VERSION: u8 = 2;
+
+impl Program {
+
+    pub fn to_bytes( &self ) -> Vec< u8 > {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push( VERSION );
+        for slot in &self.code[ ..self.len() ] {
+            bytes.push( Op::ALL.iter().position( | &op | op == slot.op ).unwrap_or( 0 ) as u8 );
+            push_varint( &mut bytes, slot.operand );
+        }
+        push_varint( &mut bytes, self.muladds.len() );
+        for targets in &self.muladds {
+            push_varint( &mut bytes, targets.len() );
+            for &( offset, factor ) in targets {
+                push_varint( &mut bytes, offset as usize );
+                push_varint( &mut bytes, factor as usize );
+            }
+        }
+        bytes
+    }
+
+    /// Reads back the output of `to_bytes`, checking that the jumps and
+    /// operands stay within the program.
+    pub fn from_bytes( bytes: &[ u8 ] ) -> Result< Program, BfError > {
+        let body = bytes.strip_prefix( MAGIC.as_slice() ).ok_or_else( || invalid( "not a compiled Brainfuck program" ) )?;
+        match body.split_first() {
+            Some( ( &VERSION, _ ) ) => {},
+            Some( ( version, _ ) ) => return Err( invalid( &format!( "unsupported version {}", version ) ) ),
+            None => return Err( invalid( "missing version" ) ),
+        }
+        let mut code = Vec::new();
+        let mut rest = &body[ 1.. ];
+        loop {
+            let ( &id, tail ) = rest.split_first().ok_or_else( || invalid( "missing final HALT" ) )?;
+            let op = *Op::ALL.get( id as usize ).ok_or_else( || invalid( &format!( "unknown opcode id {}", id ) ) )?;
+            rest = tail;
+            let operand = read_varint( &mut rest )?;
+            code.push( Slot { op, operand } );
+            if op == Op::Halt && operand == 0 {
+                break;
+            }
+        }
+        let mut muladds = Vec::new();
+        for _ in 0..read_varint( &mut rest )? {
+            let mut targets = Vec::new();
+            for _ in 0..read_varint( &mut rest )? {
+                let offset = read_varint( &mut rest )? as isize;
+                let factor = read_varint( &mut rest )? as isize;
+                targets.push( ( offset, factor ) );
+            }
+            muladds.push( targets );
+        }
+        if !rest.is_empty() {
+            return Err( invalid( "bytes after the program" ) );
+        }
+        let program = Program { code, muladds, source_map: Vec::new(), description: None, customs: Vec::new() };
+        program.check()?;
+        Ok( program )
+    }
+
+    /// Writes the binary form of the program followed by its source map,
+    /// which `load` reads back.
+    pub fn save( &self, mut out: impl Write ) -> Result< (), BfError > {
+        let code = self.to_bytes();
+        let mut bytes = Vec::new();
+        push_varint( &mut bytes, code.len() );
+        bytes.extend( code );
+        push_varint( &mut bytes, self.source_map.len() );
+        for pos in &self.source_map {
+            push_varint( &mut bytes, pos.line );
+            push_varint( &mut bytes, pos.column );
+        }
+        out.write_all( &bytes )?;
+        Ok(())
+    }
+
+    /// Reads a program written by `save`, checking it as `from_bytes` does.
+    pub fn load( mut input: impl Read ) -> Result< Program, BfError > {
+        let mut bytes = Vec::new();
+        input.read_to_end( &mut bytes )?;
+        let mut rest = bytes.as_slice();
+        let len = read_varint( &mut rest )?;
+        if len > rest.len() {
+            return Err( invalid( "truncated program" ) );
+        }
+        let ( code, mut rest ) = rest.split_at( len );
+        let mut program = Program::from_bytes( code )?;
+        let count = read_varint( &mut rest )?;
+        if count > program.code.len() {
+            return Err( invalid( "source map longer than the program" ) );
+        }
+        for _ in 0..count {
+            let line = read_varint( &mut rest )?;
+            let column = read_varint( &mut rest )?;
+            program.source_map.push( SourcePos { line, column } );
+        }
+        if !rest.is_empty() {
+            return Err( invalid( "bytes after the source map" ) );
+        }
+        Ok( program )
+    }
+
+    //  Makes sure that the engine cannot index outside the program.
+    pub(crate) fn check( &self ) -> Result< (), BfError > {
+        for ( pc, slot ) in self.code.iter().enumerate() {
+            match slot.op {
+                Op::Open | Op::Close if slot.operand >= self.code.len() =>
+                    return Err( BfError::CorruptJumpTable { pc } ),
+                Op::MulAdd if slot.operand >= self.muladds.len() =>
+                    return Err( invalid( &format!( "missing targets for MULADD at {}", pc ) ) ),
+                Op::Move if self.muladds.get( slot.operand ).is_none_or( | targets | targets.len() != 1 ) =>
+                    return Err( invalid( &format!( "missing target for MOVE at {}", pc ) ) ),
+                Op::AddAt if self.muladds.get( slot.operand ).is_none_or( | targets | targets.len() != 1 ) =>
+                    return Err( invalid( &format!( "missing target for ADD_AT at {}", pc ) ) ),
+                //  The opcode itself cannot be saved, so only a program
+                //  threaded in this process can hold one.
+                Op::Custom if slot.operand >= self.customs.len() =>
+                    return Err( invalid( &format!( "unregistered CUSTOM at {}", pc ) ) ),
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+}
+
+//  Appends `n` as an unsigned LEB128 varint.
+fn push_varint( bytes: &mut Vec< u8 >, mut n: usize ) {
+    while n >= 0x80 {
+        bytes.push( ( n & 0x7f ) as u8 | 0x80 );
+        n >>= 7;
+    }
+    bytes.push( n as u8 );
+}
+
+//  Reads a varint from the front of `bytes`, advancing past it.
+fn read_varint( bytes: &mut &[ u8 ] ) -> Result< usize, BfError > {
+    let mut n: usize = 0;
+    let mut shift = 0;
+    loop {
+        let ( &byte, tail ) = bytes.split_first().ok_or_else( || invalid( "truncated number" ) )?;
+        *bytes = tail;
+        if shift >= usize::BITS {
+            return Err( invalid( "number too large" ) );
+        }
+        n |= ( ( byte & 0x7f ) as usize ) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok( n );
+        }
+    }
+}
+
+fn invalid( message: &str ) -> BfError {
+    BfError::InvalidBinary { message: message.to_string() }
+}
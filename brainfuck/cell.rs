@@ -0,0 +1,183 @@
+/*
+    The types that a tape cell can be. Classic Brainfuck uses 8-bit cells,
+    but some programs expect wider ones, so the engine is generic over a
+    `Cell` and this provides the 8, 16 and 32-bit versions, along with an
+    unsigned 8-bit one for programs that expect cells from 0 to 255.
+*/
+
+use std::fmt;
+
+/// A tape cell. Counts are taken as `usize`, since that is how the
+/// program stores them, and wrap or saturate at the width of the cell.
+pub trait Cell: Copy + PartialEq + fmt::Debug + fmt::Display + 'static {
+    const ZERO: Self;
+
+    /// The cell for a byte read by `,`, which is the byte's bit pattern
+    /// in an 8-bit cell and its unsigned value in wider ones.
+    fn from_byte( byte: u8 ) -> Self;
+    /// The low byte of the cell, which is what `.` writes.
+    fn to_byte( self ) -> u8;
+    /// Converts exactly, failing if `n` does not fit.
+    fn from_i64( n: i64 ) -> Option< Self >;
+    fn to_i64( self ) -> i64;
+    fn wrapping_add_n( self, n: usize ) -> Self;
+    fn wrapping_sub_n( self, n: usize ) -> Self;
+    fn saturating_add_n( self, n: usize ) -> Self;
+    fn saturating_sub_n( self, n: usize ) -> Self;
+    /// Adds or subtracts `n`, failing if the result does not fit.
+    fn checked_add_n( self, n: usize ) -> Option< Self >;
+    fn checked_sub_n( self, n: usize ) -> Option< Self >;
+    /// Adds `factor` times `v`, wrapping.
+    fn wrapping_mul_add( self, v: Self, factor: isize ) -> Self;
+    /// Adds `v`, wrapping, which is `wrapping_mul_add` by 1 without the
+    /// multiplication.
+    fn wrapping_add_cell( self, v: Self ) -> Self {
+        self.wrapping_mul_add( v, 1 )
+    }
+
+    /// Every byte of the cell, in the order given by `endian`.
+    fn to_bytes( self, endian: Endian ) -> Vec< u8 > {
+        let mut bytes = ( self.to_i64() as u64 ).to_le_bytes()[ ..std::mem::size_of::< Self >() ].to_vec();
+        if endian == Endian::Big {
+            bytes.reverse();
+        }
+        bytes
+    }
+
+    /// The cell whose bytes, in the order given by `endian`, are `bytes`,
+    /// which should be as many as the cell has.
+    ///
+    /// ```
+    /// use brainfuck::{Cell, Endian};
+    ///
+    /// assert_eq!( i16::from_bytes( &[ 0x12, 0x34 ], Endian::Big ), 0x1234 );
+    /// assert_eq!( 0x1234i16.to_bytes( Endian::Little ), [ 0x34, 0x12 ] );
+    /// ```
+    fn from_bytes( bytes: &[ u8 ], endian: Endian ) -> Self {
+        let mut n: u64 = 0;
+        let mut add = | byte: &u8 | n = n << 8 | *byte as u64;
+        match endian {
+            Endian::Little => bytes.iter().rev().for_each( &mut add ),
+            Endian::Big => bytes.iter().for_each( &mut add ),
+        }
+        Self::ZERO.wrapping_add_n( n as usize )
+    }
+}
+
+/// The order of the bytes of a wide cell, when `.` and `,` write and read
+/// all of them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl std::str::FromStr for Endian {
+    type Err = String;
+
+    fn from_str( s: &str ) -> Result< Self, Self::Err > {
+        match s {
+            "little" => Ok( Endian::Little ),
+            "big" => Ok( Endian::Big ),
+            _ => Err( format!( "Unknown byte order '{}' (expected little or big)", s ) ),
+        }
+    }
+}
+
+/// Formats a cell as the signed number it holds or, if `unsigned`, as the
+/// same bits read as unsigned, so that an 8-bit cell of all ones shows as
+/// 255 rather than -1. Padding and alignment are honoured.
+///
+/// ```
+/// use brainfuck::Shown;
+///
+/// assert_eq!( format!( "{} {:>4}", Shown( -1i8, false ), Shown( -1i8, true ) ), "-1  255" );
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Shown< C >( pub C, pub bool );
+
+impl< C: Cell > Shown< C > {
+
+    /// The number that is shown.
+    pub fn value( &self ) -> i64 {
+        let Shown( cell, unsigned ) = *self;
+        if unsigned {
+            let bits = std::mem::size_of::< C >() * 8;
+            ( cell.to_i64() as u64 & u64::MAX >> ( 64 - bits ) ) as i64
+        } else {
+            cell.to_i64()
+        }
+    }
+
+}
+
+impl< C: Cell > fmt::Display for Shown< C > {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        fmt::Display::fmt( &self.value(), f )
+    }
+}
+
+macro_rules! impl_cell {
+    ( $t:ty ) => {
+        impl Cell for $t {
+            const ZERO: Self = 0;
+
+            fn from_byte( byte: u8 ) -> Self {
+                byte as $t
+            }
+
+            fn to_byte( self ) -> u8 {
+                self as u8
+            }
+
+            fn from_i64( n: i64 ) -> Option< Self > {
+                <$t>::try_from( n ).ok()
+            }
+
+            fn to_i64( self ) -> i64 {
+                self as i64
+            }
+
+            fn wrapping_add_n( self, n: usize ) -> Self {
+                self.wrapping_add( n as $t )
+            }
+
+            fn wrapping_sub_n( self, n: usize ) -> Self {
+                self.wrapping_sub( n as $t )
+            }
+
+            //  Any count beyond u32::MAX saturates every width of cell, so
+            //  the arithmetic fits in an i64.
+            fn saturating_add_n( self, n: usize ) -> Self {
+                let n = n.min( u32::MAX as usize ) as i64;
+                ( self as i64 + n ).min( <$t>::MAX as i64 ) as $t
+            }
+
+            fn saturating_sub_n( self, n: usize ) -> Self {
+                let n = n.min( u32::MAX as usize ) as i64;
+                ( self as i64 - n ).max( <$t>::MIN as i64 ) as $t
+            }
+
+            fn checked_add_n( self, n: usize ) -> Option< Self > {
+                <$t>::try_from( n ).ok().and_then( | n | self.checked_add( n ) )
+            }
+
+            fn checked_sub_n( self, n: usize ) -> Option< Self > {
+                <$t>::try_from( n ).ok().and_then( | n | self.checked_sub( n ) )
+            }
+
+            fn wrapping_mul_add( self, v: Self, factor: isize ) -> Self {
+                self.wrapping_add( v.wrapping_mul( factor as $t ) )
+            }
+
+            fn wrapping_add_cell( self, v: Self ) -> Self {
+                self.wrapping_add( v )
+            }
+        }
+    };
+}
+
+impl_cell!( i8 );
+impl_cell!( u8 );
+impl_cell!( i16 );
+impl_cell!( i32 );
@@ -0,0 +1,332 @@
+/*
+    A summary of a program, found by parsing it but not running it, for
+    the driver's --check mode: a quick lint for Brainfuck authors. Also
+    the warnings about likely mistakes that --warn prints, and the counts
+    of each command that --count-only prints.
+*/
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::compiler::{Compiler, SourcePos};
+use crate::error::BfError;
+use crate::ir::{Instr, Located};
+use crate::json::Json;
+
+/// What `Compiler::check` finds out about a program whose brackets
+/// balance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of commands, before any optimization.
+    pub instructions: usize,
+    /// How deeply the loops nest, which is 0 if there are none.
+    pub depth: usize,
+    pub reads_input: bool,
+    pub writes_output: bool,
+    /// The first and last command of each stretch that follows an `@`
+    /// in the same loop, or at the top level, and so can never run.
+    pub unreachable: Vec< ( SourcePos, SourcePos ) >,
+    /// The prose before the first command, under `Compiler::doc_comments`.
+    pub description: Option< String >,
+}
+
+/// The counts that `Compiler::statistics` makes of a program's commands,
+/// before any optimization.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statistics {
+    pub instructions: usize,
+    /// How many times each command appears, by its character.
+    pub commands: BTreeMap< char, usize >,
+    pub loops: usize,
+    /// How deeply the loops nest, which is 0 if there are none.
+    pub depth: usize,
+}
+
+/// Something in a program that is probably a mistake, found by
+/// `Compiler::lint`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub pos: Option< SourcePos >,
+    pub message: &'static str,
+}
+
+impl fmt::Display for Warning {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        match self.pos {
+            Some( pos ) => write!( f, "{}:{}: warning: {}", pos.line, pos.column, self.message ),
+            None => write!( f, "warning: {}", self.message ),
+        }
+    }
+}
+
+impl Compiler {
+
+    /// Parses `src` and summarizes it, failing as `parse` does when the
+    /// brackets do not balance.
+    ///
+    /// ```
+    /// let summary = brainfuck::Compiler::new().check( "+[>[-]<.]" ).unwrap();
+    /// assert_eq!( ( summary.instructions, summary.depth, summary.reads_input, summary.writes_output ), ( 9, 2, false, true ) );
+    /// ```
+    pub fn check( &self, src: impl AsRef< [ u8 ] > ) -> Result< Summary, BfError > {
+        let src = src.as_ref();
+        let located = self.parse_source( src )?;
+        let instrs: Vec< Instr > = located.iter().map( | l | l.instr.clone() ).collect();
+        let mut depth = 0;
+        let mut deepest = 0;
+        for instr in &instrs {
+            match instr {
+                Instr::Open => {
+                    depth += 1;
+                    deepest = deepest.max( depth );
+                },
+                Instr::Close => depth -= 1,
+                _ => {},
+            }
+        }
+        Ok( Summary {
+            instructions: instrs.len(),
+            depth: deepest,
+            reads_input: instrs.contains( &Instr::Get ),
+            writes_output: instrs.contains( &Instr::Put ),
+            unreachable: unreachable( &located ).into_iter().filter_map( | stretch | {
+                Some( ( stretch.first()?.pos?, stretch.last()?.pos? ) )
+            } ).collect(),
+            description: self.description_of( src ),
+        } )
+    }
+
+    /// Counts the commands of `src` and its loops, in one pass over the
+    /// parsed program.
+    ///
+    /// ```
+    /// let stats = brainfuck::Compiler::new().statistics( "+[>[-]<.]" ).unwrap();
+    /// assert_eq!( ( stats.instructions, stats.commands[ &'-' ], stats.loops, stats.depth ), ( 9, 1, 2, 2 ) );
+    /// ```
+    pub fn statistics( &self, src: impl AsRef< [ u8 ] > ) -> Result< Statistics, BfError > {
+        let instrs = self.parse( src )?;
+        let mut stats = Statistics { instructions: instrs.len(), commands: BTreeMap::new(), loops: 0, depth: 0 };
+        let mut depth = 0;
+        for instr in &instrs {
+            match instr {
+                Instr::Open => {
+                    stats.loops += 1;
+                    depth += 1;
+                    stats.depth = stats.depth.max( depth );
+                },
+                Instr::Close => depth -= 1,
+                _ => {},
+            }
+            for command in crate::ir::to_source( std::slice::from_ref( instr ) ).chars() {
+                *stats.commands.entry( command ).or_default() += 1;
+            }
+        }
+        Ok( stats )
+    }
+
+    /// Looks for loops that can never end once entered, for moves left of
+    /// cell 0 before the first loop, where the pointer is known, and for
+    /// commands after an `@` that can never run.
+    ///
+    /// ```
+    /// let warnings = brainfuck::Compiler::new().lint( "+[]" ).unwrap();
+    /// assert_eq!( warnings[ 0 ].to_string(), "1:2: warning: empty loop, an infinite loop if entered" );
+    /// ```
+    pub fn lint( &self, src: impl AsRef< [ u8 ] > ) -> Result< Vec< Warning >, BfError > {
+        let instrs = self.parse_source( src.as_ref() )?;
+        let mut warnings = Vec::new();
+        let mut at = Some( 0 );
+        for ( n, located ) in instrs.iter().enumerate() {
+            match located.instr {
+                Instr::Open => {
+                    if let Some( message ) = endless_loop( &instrs[ n + 1.. ] ) {
+                        warnings.push( Warning { pos: located.pos, message } );
+                    }
+                    at = None;
+                },
+                Instr::Right => at = at.map( | a | a + 1 ),
+                Instr::Left => {
+                    at = at.map( | a | a - 1 );
+                    if at == Some( -1 ) {
+                        warnings.push( Warning { pos: located.pos, message: "moves left of cell 0, which always fails" } );
+                        at = None;
+                    }
+                },
+                _ => {},
+            }
+        }
+        for stretch in unreachable( &instrs ) {
+            warnings.push( Warning { pos: stretch[ 0 ].pos, message: "unreachable, after an explicit HALT" } );
+        }
+        warnings.sort_by_key( | warning | warning.pos.map( | pos | ( pos.line, pos.column ) ) );
+        Ok( warnings )
+    }
+
+}
+
+//  The stretches of commands after each `@`, up to the `]` of the loop
+//  that it is in or the end of the program, which nothing jumps into. An
+//  `@` inside a stretch is part of it.
+fn unreachable( instrs: &[ Located ] ) -> Vec< &[ Located ] > {
+    let mut stretches = Vec::new();
+    let mut n = 0;
+    while n < instrs.len() {
+        n += 1;
+        if instrs[ n - 1 ].instr != Instr::Halt {
+            continue;
+        }
+        let start = n;
+        let mut depth = 0;
+        while n < instrs.len() {
+            match instrs[ n ].instr {
+                Instr::Open => depth += 1,
+                Instr::Close if depth == 0 => break,
+                Instr::Close => depth -= 1,
+                _ => {},
+            }
+            n += 1;
+        }
+        if n > start {
+            stretches.push( &instrs[ start..n ] );
+        }
+    }
+    stretches
+}
+
+//  Why the loop whose body starts at `body` never ends once entered, if
+//  it is a simple loop that leaves its own cell unchanged. A change that
+//  is a multiple of the cell's range is not noticed.
+fn endless_loop( body: &[ Located ] ) -> Option< &'static str > {
+    if body.first().map( | l | &l.instr ) == Some( &Instr::Close ) {
+        return Some( "empty loop, an infinite loop if entered" );
+    }
+    let mut offset = 0;
+    let mut change = 0;
+    for located in body {
+        match located.instr {
+            Instr::Incr if offset == 0 => change += 1,
+            Instr::Decr if offset == 0 => change -= 1,
+            Instr::Incr | Instr::Decr => {},
+            Instr::Right => offset += 1,
+            Instr::Left => offset -= 1,
+            Instr::Close if offset == 0 && change == 0 => return Some( "loop leaves its cell unchanged, an infinite loop if entered" ),
+            _ => return None,
+        }
+    }
+    None
+}
+
+impl fmt::Display for Summary {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        let yes_no = | b | if b { "yes" } else { "no" };
+        if let Some( description ) = &self.description {
+            for line in description.lines() {
+                writeln!( f, "{}", line.trim_end() )?;
+            }
+        }
+        writeln!( f, "Brackets balance" )?;
+        writeln!( f, "Instructions: {}", self.instructions )?;
+        writeln!( f, "Loop depth: {}", self.depth )?;
+        writeln!( f, "Reads input: {}", yes_no( self.reads_input ) )?;
+        writeln!( f, "Writes output: {}", yes_no( self.writes_output ) )?;
+        for ( first, last ) in &self.unreachable {
+            writeln!( f, "Unreachable: {} to {}", first, last )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        writeln!( f, "Instructions: {}", self.instructions )?;
+        writeln!( f, "Loops: {}", self.loops )?;
+        writeln!( f, "Loop depth: {}", self.depth )?;
+        for ( command, n ) in &self.commands {
+            writeln!( f, "  {}  {:>8}", command, n )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that the brackets of `src` balance, in a single pass that builds
+/// nothing else. It fails as `Compiler::parse` does, with the first `]`
+/// that closes nothing or else the first `[` that is never closed, but
+/// counts every bracket, even those after a comment character.
+///
+/// ```
+/// use brainfuck::{check, BfError};
+///
+/// assert!( check::check_balanced( "+[>[-]<]" ).is_ok() );
+/// assert!( matches!( check::check_balanced( "[[]" ), Err( BfError::UnmatchedOpen { position: 0, .. } ) ) );
+/// ```
+pub fn check_balanced( src: &str ) -> Result< (), BfError > {
+    let mut opens = Vec::<( usize, SourcePos )>::new();
+    let mut pos = SourcePos { line: 1, column: 1 };
+    for ( position, ch ) in src.chars().enumerate() {
+        match ch {
+            '[' => opens.push( ( position, pos ) ),
+            ']' if opens.pop().is_none() => return Err( BfError::UnmatchedClose { position, source: Some( pos ) } ),
+            _ => {},
+        }
+        if ch == '\n' {
+            pos = SourcePos { line: pos.line + 1, column: 1 };
+        } else {
+            pos.column += 1;
+        }
+    }
+    match opens.first() {
+        Some( &( position, pos ) ) => Err( BfError::UnmatchedOpen { position, source: Some( pos ) } ),
+        None => Ok(()),
+    }
+}
+
+/// Writes `Statistics` as a JSON object on one line, with the count of
+/// each command in an object of its own.
+///
+/// ```
+/// let stats = brainfuck::Compiler::new().statistics( "++." ).unwrap();
+/// assert_eq!( brainfuck::check::statistics_json( &stats ), "{\"commands\":{\"+\":2,\".\":1},\"depth\":0,\"instructions\":3,\"loops\":0}\n" );
+/// ```
+pub fn statistics_json( stats: &Statistics ) -> String {
+    let commands = Json::Object( stats.commands.iter().map( | ( command, &n ) | ( command.to_string(), n.into() ) ).collect() );
+    let json = Json::object( [
+        ( "instructions", stats.instructions.into() ),
+        ( "commands", commands ),
+        ( "loops", stats.loops.into() ),
+        ( "depth", stats.depth.into() ),
+    ] );
+    format!( "{}\n", json )
+}
+
+/// Writes the result of `Compiler::check` as a JSON object on one line.
+/// Its `balanced` field says whether the brackets balance; if not, the
+/// error and its source position are given instead of the summary. Each
+/// unreachable stretch is an object giving the line and column where it
+/// starts and the `end_line` and `end_column` of its last command. The
+/// `description` is null unless `Compiler::doc_comments` found one.
+pub fn to_json( result: &Result< Summary, BfError > ) -> String {
+    let json = match result {
+        Ok( summary ) => Json::object( [
+            ( "balanced", true.into() ),
+            ( "instructions", summary.instructions.into() ),
+            ( "depth", summary.depth.into() ),
+            ( "reads_input", summary.reads_input.into() ),
+            ( "writes_output", summary.writes_output.into() ),
+            ( "unreachable", Json::Array( summary.unreachable.iter().map( | ( first, last ) | Json::object( [
+                ( "line", first.line.into() ),
+                ( "column", first.column.into() ),
+                ( "end_line", last.line.into() ),
+                ( "end_column", last.column.into() ),
+            ] ) ).collect() ) ),
+            ( "description", summary.description.as_deref().map_or( Json::Null, Json::from ) ),
+        ] ),
+        Err( error @ ( BfError::UnmatchedOpen { source, .. } | BfError::UnmatchedClose { source, .. } ) ) => Json::object( [
+            ( "balanced", false.into() ),
+            ( "error", error.to_string().as_str().into() ),
+            ( "line", source.map_or( Json::Null, | pos | pos.line.into() ) ),
+            ( "column", source.map_or( Json::Null, | pos | pos.column.into() ) ),
+        ] ),
+        Err( error ) => Json::object( [ ( "error", error.to_string().as_str().into() ) ] ),
+    };
+    format!( "{}\n", json )
+}
@@ -0,0 +1,705 @@
+/*
+    Translates Brainfuck source into the threaded program that the engine
+    runs. This happens in three stages: parsing turns the source into a
+    list of `Instr`, optimization rewrites that list, and threading turns
+    it into opcodes. Each `Instr` becomes a single opcode, whose operand
+    holds its jump target or count if it has one.
+*/
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write;
+use std::io::Read;
+use std::sync::Arc;
+
+use crate::cell::Cell;
+use crate::engine::{Op, OpCode, Slot, MEMORY_SIZE};
+use crate::error::BfError;
+use crate::ir::{Instr, Located};
+use crate::optimize::{OptReport, Optimizer};
+
+/// A 1-based line and column in the Brainfuck source.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SourcePos {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for SourcePos {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        write!( f, "line {}, column {}", self.line, self.column )
+    }
+}
+
+pub struct Program {
+    pub(crate) code : Vec< Slot >,
+    //  The (offset, factor) pairs of each MULADD, which its operand indexes,
+    //  and the single (offset, amount) pair of each ADD_AT and (offset, 1)
+    //  pair of each MOVE.
+    pub(crate) muladds : Vec< Vec< ( isize, isize ) > >,
+    //  The source position of each instruction of `code`, or empty if the
+    //  program was not compiled from source.
+    pub(crate) source_map : Vec< SourcePos >,
+    pub(crate) description : Option< String >,
+    //  The command and opcode of each CUSTOM, which its operand indexes.
+    pub(crate) customs : Vec< ( char, CustomOp ) >,
+}
+
+//  An `OpCode< C >` registered with `Compiler::register_op`, for whichever
+//  type of cell it was written for.
+pub(crate) type CustomOp = Arc< dyn Any + Send + Sync >;
+
+impl Program {
+
+    /// The number of instructions up to and including the final HALT,
+    /// which unlike one written as `@` has an operand of 0. The slots after
+    /// it are unused.
+    pub fn len( &self ) -> usize {
+        self.code.iter().position( | slot | slot.op == Op::Halt && slot.operand == 0 ).map_or( self.code.len(), | pc | pc + 1 )
+    }
+
+    /// The prose at the top of the source, before its first command, when
+    /// compiled with `Compiler::doc_comments`.
+    pub fn description( &self ) -> Option< &str > {
+        self.description.as_deref()
+    }
+
+    /// Always false, since every program ends in HALT.
+    pub fn is_empty( &self ) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the program has a `,`, and so needs any input.
+    ///
+    /// ```
+    /// let program = brainfuck::compile( "+[,.]" ).unwrap();
+    /// assert!( program.reads_input() && program.writes_output() );
+    /// assert!( !brainfuck::compile( "+.#" ).unwrap().reads_input() );
+    /// ```
+    pub fn reads_input( &self ) -> bool {
+        self.code[ ..self.len() ].iter().any( | slot | slot.op == Op::Get )
+    }
+
+    /// Whether the program writes any output, with `.` or, if enabled,
+    /// `:`. The dumps of `#` go elsewhere, and do not count.
+    pub fn writes_output( &self ) -> bool {
+        self.code[ ..self.len() ].iter().any( | slot | matches!( slot.op, Op::Put | Op::PutNum ) )
+    }
+
+    /// The source position of the instruction at `pc`, if known.
+    pub fn source_pos( &self, pc: usize ) -> Option< SourcePos > {
+        self.source_map.get( pc ).copied()
+    }
+
+    /// Lists the opcodes up to the final HALT, one per line, with their
+    /// index and operands. Jumps show the index they jump to.
+    pub fn disassemble( &self ) -> String {
+        let mut text = String::new();
+        for ( pc, &Slot { op, operand } ) in self.code.iter().enumerate() {
+            let operands = match op {
+                Op::Open | Op::Close => format!( "-> {}", operand ),
+                Op::IncrN | Op::DecrN | Op::IncrNSat | Op::DecrNSat | Op::IncrNChk | Op::DecrNChk | Op::SetConst | Op::Assert |
+                Op::RightN | Op::LeftN | Op::RightNGrow | Op::LeftNGrow => operand.to_string(),
+                Op::MulAdd => {
+                    let targets: Vec< String > = self.muladds.get( operand ).into_iter().flatten().map( | ( offset, factor ) | {
+                        format!( "{:+}*{}", offset, factor )
+                    } ).collect();
+                    targets.join( " " )
+                },
+                Op::AddAt => match self.muladds.get( operand ).and_then( | targets | targets.first() ) {
+                    Some( ( offset, amount ) ) => format!( "{:+} {:+}", offset, amount ),
+                    None => String::new(),
+                },
+                Op::Move => match self.muladds.get( operand ).and_then( | targets | targets.first() ) {
+                    Some( ( offset, _ ) ) => format!( "{:+}", offset ),
+                    None => String::new(),
+                },
+                Op::Custom => self.customs.get( operand ).map_or( String::new(), | ( ch, _ ) | format!( "'{}'", ch ) ),
+                _ => String::new(),
+            };
+            let line = format!( "{:>6}  {:<12} {}", pc, op.name(), operands );
+            let _ = writeln!( text, "{}", line.trim_end() );
+            if op == Op::Halt && operand == 0 {
+                break;
+            }
+        }
+        text
+    }
+
+}
+
+/// What `+` and `-` do to a cell that is already as high or as low as
+/// its type allows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Overflow {
+    /// Wrap around to the other end of the range, as classic Brainfuck does.
+    Wrap,
+    /// Stay at the end of the range.
+    Saturate,
+    /// Fail with `BfError::CellOverflow`.
+    Error,
+}
+
+impl std::str::FromStr for Overflow {
+    type Err = String;
+
+    fn from_str( s: &str ) -> Result< Self, Self::Err > {
+        match s {
+            "wrap" => Ok( Overflow::Wrap ),
+            "saturate" => Ok( Overflow::Saturate ),
+            "error" => Ok( Overflow::Error ),
+            _ => Err( format!( "Unknown overflow '{}' (expected wrap, saturate or error)", s ) ),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Compiler {
+    opcode_map : BTreeMap< char, Instr >,
+    //  Starts a comment that runs to the end of the line, when set.
+    comment_char : Option< char >,
+    //  Rejects characters that are neither commands nor whitespace.
+    strict : bool,
+    //  Keeps the text before the first command as the description.
+    doc_comments : bool,
+    overflow : Overflow,
+    opt_level : u8,
+    //  Replaces the passes chosen by `opt_level` when set.
+    optimizer : Option< Optimizer >,
+    //  The commands added by `register_op`, with their opcodes.
+    customs : Vec< ( char, CustomOp ) >,
+    program_size : usize,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+
+    pub fn new() -> Self {
+        let compiler = Compiler {
+            opcode_map: BTreeMap::from( [
+                ( '+', Instr::Incr ),
+                ( '-', Instr::Decr ),
+                ( '>', Instr::Right ),
+                ( '<', Instr::Left ),
+                ( '[', Instr::Open ),
+                ( ']', Instr::Close ),
+                ( '.', Instr::Put ),
+                ( ',', Instr::Get )
+            ] ),
+            comment_char: None,
+            strict: false,
+            doc_comments: false,
+            overflow: Overflow::Wrap,
+            opt_level: 3,
+            optimizer: None,
+            customs: Vec::new(),
+            program_size: MEMORY_SIZE,
+        };
+        compiler.debug_op( cfg!( debug_assertions ) )
+    }
+
+    /// Makes `#` print the cells around the pointer, rather than being
+    /// ignored like other comments. This is on by default in debug builds.
+    pub fn debug_op( mut self, enabled: bool ) -> Self {
+        if enabled {
+            self.opcode_map.insert( '#', Instr::Dump );
+        } else {
+            self.opcode_map.remove( &'#' );
+        }
+        self
+    }
+
+    /// Makes `?` store a random byte in the current cell, as some
+    /// extensions of Brainfuck do, rather than being ignored. The engine's
+    /// `seed` makes the bytes repeatable.
+    pub fn rand_op( mut self, enabled: bool ) -> Self {
+        if enabled {
+            self.opcode_map.insert( '?', Instr::Rand );
+        } else {
+            self.opcode_map.remove( &'?' );
+        }
+        self
+    }
+
+    /// Makes `:` write the current cell as a decimal number followed by a
+    /// space, rather than being ignored, which helps when debugging
+    /// arithmetic whose results are not printable.
+    pub fn num_op( mut self, enabled: bool ) -> Self {
+        if enabled {
+            self.opcode_map.insert( ':', Instr::PutNum );
+        } else {
+            self.opcode_map.remove( &':' );
+        }
+        self
+    }
+
+    /// Makes `@` halt the program, rather than being ignored, so that it
+    /// can stop from inside a loop. The program still ends in a HALT.
+    pub fn halt_op( mut self, enabled: bool ) -> Self {
+        if enabled {
+            self.opcode_map.insert( '@', Instr::Halt );
+        } else {
+            self.opcode_map.remove( &'@' );
+        }
+        self
+    }
+
+    /// Makes `%` switch between two independent tapes, each with its own
+    /// pointer, rather than being ignored, so that a program can keep
+    /// scratch cells apart from its data.
+    pub fn tape_op( mut self, enabled: bool ) -> Self {
+        if enabled {
+            self.opcode_map.insert( '%', Instr::SwitchTape );
+        } else {
+            self.opcode_map.remove( &'%' );
+        }
+        self
+    }
+
+    /// Makes `=` followed by a decimal number check that the current cell
+    /// holds that number, failing with `AssertionFailed` if not, so that a
+    /// test program can check its own progress. A `=` with no digits after
+    /// it checks for zero. Otherwise `=` and the digits are ignored.
+    ///
+    /// ```
+    /// use brainfuck::{BfError, Compiler, Engine};
+    ///
+    /// let program = Compiler::new().assert_op( true ).compile( "+++=3+=5" ).unwrap();
+    /// let mut engine = Engine::< i8 >::new( &program, 4 ).with_io( std::io::empty(), std::io::sink() );
+    /// assert!( matches!( engine.run(), Err( BfError::AssertionFailed { expected: 5, actual: 4, .. } ) ) );
+    /// ```
+    pub fn assert_op( mut self, enabled: bool ) -> Self {
+        if enabled {
+            self.opcode_map.insert( '=', Instr::Assert( 0 ) );
+        } else {
+            self.opcode_map.remove( &'=' );
+        }
+        self
+    }
+
+    /// Makes `ch` start a comment that runs to the end of the line, so that
+    /// commands in the comment are ignored too, as in dialects that use
+    /// `;` this way. By default only the characters that are not commands
+    /// are ignored.
+    ///
+    /// ```
+    /// use brainfuck::{Compiler, Instr};
+    ///
+    /// let instrs = Compiler::new().comment_char( ';' ).parse( "+ ; this adds one, and no more.\n." ).unwrap();
+    /// assert_eq!( instrs, [ Instr::Incr, Instr::Put ] );
+    /// ```
+    pub fn comment_char( mut self, ch: char ) -> Self {
+        self.comment_char = Some( ch );
+        self
+    }
+
+    /// Rejects any character that is not a command, whitespace or part of
+    /// a `comment_char` comment with `UnexpectedCharacter`, and any byte
+    /// that is not valid UTF-8 with `UnexpectedByte`, rather than ignoring
+    /// them, to catch typos and garbled source.
+    ///
+    /// ```
+    /// use brainfuck::{BfError, Compiler};
+    ///
+    /// assert!( Compiler::new().compile( "++x" ).is_ok() );
+    /// let result = Compiler::new().strict( true ).compile( "++x" );
+    /// assert!( matches!( result, Err( BfError::UnexpectedCharacter { ch: 'x', position: 2, .. } ) ) );
+    /// let result = Compiler::new().strict( true ).compile( b"++\xff" );
+    /// assert!( matches!( result, Err( BfError::UnexpectedByte { byte: 0xff, position: 2, .. } ) ) );
+    /// ```
+    pub fn strict( mut self, strict: bool ) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Keeps the text at the top of the source, up to its first command, as
+    /// the program's `description`, since authors often start a program
+    /// with a line of prose saying what it does.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().doc_comments( true ).compile( "Adds two numbers\n[->+<]" ).unwrap();
+    /// assert_eq!( program.description(), Some( "Adds two numbers" ) );
+    /// ```
+    pub fn doc_comments( mut self, enabled: bool ) -> Self {
+        self.doc_comments = enabled;
+        self
+    }
+
+    //  The text before the first command of `src`, without the whitespace
+    //  around it, if `doc_comments` is set and there is any.
+    pub(crate) fn description_of( &self, src: &[ u8 ] ) -> Option< String > {
+        let text: String = decode( src ).map( | item | item.unwrap_or( char::REPLACEMENT_CHARACTER ) ).take_while( | ch | !self.opcode_map.contains_key( ch ) ).collect();
+        Some( text.trim().to_string() ).filter( | text | self.doc_comments && !text.is_empty() )
+    }
+
+    /// Selects wrapping (the default) or saturating cell arithmetic.
+    pub fn wrap( self, wrap: bool ) -> Self {
+        self.overflow( if wrap { Overflow::Wrap } else { Overflow::Saturate } )
+    }
+
+    /// Selects what `+` and `-` do at the ends of a cell's range, by
+    /// choosing which version of their opcodes to install. An `i8` cell
+    /// overflows going up from 127 and down from -128, and a `u8` one
+    /// going up from 255 and down from 0.
+    ///
+    /// ```
+    /// use brainfuck::{BfError, Compiler, Engine, Overflow};
+    ///
+    /// let program = Compiler::new().overflow( Overflow::Error ).compile( "-" ).unwrap();
+    /// let mut engine = Engine::< i8 >::new( &program, 4 ).init_tape( &[ 0x80 ] ).with_io( std::io::empty(), std::io::sink() );
+    /// assert!( matches!( engine.run(), Err( BfError::CellOverflow { pc: 0, loc: 0, .. } ) ) );
+    /// ```
+    pub fn overflow( mut self, overflow: Overflow ) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Selects the standard optimization passes for `level`, from 0 for
+    /// none to 3 (the default) for all of them; see `Optimizer::level`.
+    pub fn opt_level( mut self, level: u8 ) -> Self {
+        self.opt_level = level;
+        self
+    }
+
+    /// Runs the given passes instead of those chosen by `opt_level`.
+    pub fn optimizer( mut self, optimizer: Optimizer ) -> Self {
+        self.optimizer = Some( optimizer );
+        self
+    }
+
+    /// Makes `ch` a command that runs `op`, for a dialect with commands of
+    /// its own, replacing any extension that `ch` was. The opcode must move
+    /// the pc on itself, with `Engine::advance`, unless it fails, and must
+    /// only read and write the current cell, leaving the data pointer where
+    /// it is, since that is the cell that `Engine::protect` guards and that
+    /// the hooks on writes are told of. Only an engine with cells of type
+    /// `C` can run it, and on any other it fails as an illegal instruction.
+    /// The eight core commands cannot be replaced.
+    ///
+    /// ```
+    /// use brainfuck::{BfError, Compiler, Engine};
+    ///
+    /// fn double( e: &mut Engine< i8 > ) -> Result< (), BfError > {
+    ///     e.set_current_cell( e.current_cell().wrapping_mul( 2 ) );
+    ///     e.advance();
+    ///     Ok(())
+    /// }
+    ///
+    /// let program = Compiler::new().register_op( '*', double ).unwrap().compile( "+++**." ).unwrap();
+    /// let mut output = Vec::new();
+    /// Engine::< i8 >::new( &program, 4 ).with_io( std::io::empty(), &mut output ).run().unwrap();
+    /// assert_eq!( output, [ 12 ] );
+    /// ```
+    pub fn register_op< C: Cell >( mut self, ch: char, op: OpCode< C > ) -> Result< Self, BfError > {
+        if "+-<>[].,".contains( ch ) {
+            return Err( BfError::InvalidArgument { message: format!( "'{}' is a core command and cannot be registered", ch ) } );
+        }
+        self.customs.retain( | &( c, _ ) | c != ch );
+        self.customs.push( ( ch, Arc::new( op ) ) );
+        self.opcode_map.insert( ch, Instr::Custom( ch ) );
+        Ok( self )
+    }
+
+    pub fn program_size( mut self, program_size: usize ) -> Self {
+        self.program_size = program_size;
+        self
+    }
+
+    /// Translates the commands in `src` into instructions, ignoring all
+    /// other characters, and checks that the brackets balance. Jumps are
+    /// resolved later, by `thread`. The source need not be UTF-8, and any
+    /// bytes that are not are ignored too, or rejected under `strict`.
+    ///
+    /// ```
+    /// use brainfuck::{Compiler, Instr};
+    ///
+    /// let instrs = Compiler::new().parse( "[+]., comment" ).unwrap();
+    /// assert_eq!( instrs, [ Instr::Open, Instr::Incr, Instr::Close, Instr::Put, Instr::Get ] );
+    /// ```
+    pub fn parse( &self, src: impl AsRef< [ u8 ] > ) -> Result< Vec< Instr >, BfError > {
+        Ok( self.parse_source( src.as_ref() )?.into_iter().map( | l | l.instr ).collect() )
+    }
+
+    //  Parses `src`, keeping the source position of each instruction.
+    pub(crate) fn parse_source( &self, src: &[ u8 ] ) -> Result< Vec< Located >, BfError > {
+        let mut instrs = Vec::new();
+        let mut indexes = Vec::<( usize, SourcePos )>::new();
+        let mut pos = SourcePos { line: 1, column: 1 };
+        let mut in_comment = false;
+        let mut chars = decode( src ).enumerate().peekable();
+        while let Some( ( position, item ) ) = chars.next() {
+            //  A byte that is not UTF-8 is never a command, nor the end of
+            //  a comment.
+            let ch = match item {
+                Ok( ch ) => ch,
+                Err( byte ) if self.strict && !in_comment => return Err( BfError::UnexpectedByte { byte, position, source: Some( pos ) } ),
+                Err( _ ) => {
+                    pos.column += 1;
+                    continue;
+                },
+            };
+            in_comment = ( in_comment || Some( ch ) == self.comment_char ) && ch != '\n';
+            if in_comment {
+                pos.column += 1;
+                continue;
+            }
+            if let Some( instr ) = self.opcode_map.get( &ch ) {
+                match instr {
+                    Instr::Open => indexes.push( ( position, pos ) ),
+                    Instr::Close => {
+                        indexes.pop().ok_or( BfError::UnmatchedClose { position, source: Some( pos ) } )?;
+                    },
+                    _ => {},
+                }
+                let ( mut instr, at ) = ( instr.clone(), pos );
+                //  The number after `=` is part of the command.
+                if let Instr::Assert( n ) = &mut instr {
+                    while let Some( digit ) = chars.peek().and_then( | &( _, d ) | d.ok()?.to_digit( 10 ) ) {
+                        *n = n.saturating_mul( 10 ).saturating_add( digit as usize );
+                        pos.column += 1;
+                        chars.next();
+                    }
+                }
+                instrs.push( Located { instr, pos: Some( at ) } );
+            } else if self.strict && !ch.is_whitespace() {
+                return Err( BfError::UnexpectedCharacter { ch, position, source: Some( pos ) } );
+            }
+            if ch == '\n' {
+                pos = SourcePos { line: pos.line + 1, column: 1 };
+            } else {
+                pos.column += 1;
+            }
+        }
+        match indexes.first() {
+            Some( &( position, pos ) ) => Err( BfError::UnmatchedOpen { position, source: Some( pos ) } ),
+            None => Ok( instrs ),
+        }
+    }
+
+    /// Lays out the instructions as opcodes, resolving the jump targets.
+    pub fn thread( &self, instrs: &[ Instr ] ) -> Result< Program, BfError > {
+        let located: Vec< Located > = instrs.iter().map( | instr | Located { instr: instr.clone(), pos: None } ).collect();
+        self.thread_located( &located )
+    }
+
+    //  Threads the instructions, recording their source positions in the
+    //  program's source map when they are known.
+    fn thread_located( &self, instrs: &[ Located ] ) -> Result< Program, BfError > {
+        //  Check the size first, counting HALT, so that a program that does
+        //  not fit fails cleanly.
+        let needed = instrs.len() + 1;
+        if needed > self.program_size {
+            return Err( BfError::ProgramTooLarge { needed, capacity: self.program_size } );
+        }
+        let mut program = vec![ Slot::EMPTY; self.program_size ];
+        let mut source_map = Vec::new();
+        let mut muladds = Vec::new();
+        let mut top: usize = 0;
+        let mut indexes = Vec::<usize>::new();
+        let mut starts = Vec::<usize>::new();
+        for ( n, Located { instr, pos } ) in instrs.iter().enumerate() {
+            let pos = *pos;
+            program[ top ].op = match instr {
+                Instr::Incr => match self.overflow {
+                    Overflow::Wrap => Op::Incr,
+                    Overflow::Saturate => Op::IncrSat,
+                    Overflow::Error => Op::IncrChk,
+                },
+                Instr::Decr => match self.overflow {
+                    Overflow::Wrap => Op::Decr,
+                    Overflow::Saturate => Op::DecrSat,
+                    Overflow::Error => Op::DecrChk,
+                },
+                Instr::Right => Op::Right,
+                Instr::Left => Op::Left,
+                Instr::Open => Op::Open,
+                Instr::Close => Op::Close,
+                Instr::Put => Op::Put,
+                Instr::Get => Op::Get,
+                Instr::IncrN( _ ) => match self.overflow {
+                    Overflow::Wrap => Op::IncrN,
+                    Overflow::Saturate => Op::IncrNSat,
+                    Overflow::Error => Op::IncrNChk,
+                },
+                Instr::DecrN( _ ) => match self.overflow {
+                    Overflow::Wrap => Op::DecrN,
+                    Overflow::Saturate => Op::DecrNSat,
+                    Overflow::Error => Op::DecrNChk,
+                },
+                Instr::RightN( _ ) => Op::RightN,
+                Instr::LeftN( _ ) => Op::LeftN,
+                Instr::SetZero => Op::SetZero,
+                Instr::SetConst( _ ) => Op::SetConst,
+                Instr::MulAdd( _ ) => Op::MulAdd,
+                Instr::AddAt( .. ) => Op::AddAt,
+                Instr::Move( _ ) => Op::Move,
+                Instr::ScanRight => Op::ScanRight,
+                Instr::ScanLeft => Op::ScanLeft,
+                Instr::Dump => Op::Dump,
+                Instr::Rand => Op::Rand,
+                Instr::PutNum => Op::PutNum,
+                Instr::SwitchTape => Op::SwitchTape,
+                Instr::Halt => Op::Halt,
+                Instr::Assert( _ ) => Op::Assert,
+                Instr::Custom( _ ) => Op::Custom,
+            };
+            source_map.extend( pos );
+            //  A jump goes to the instruction after the matching bracket.
+            match instr {
+                Instr::Open => {
+                    indexes.push( top );
+                    starts.push( n );
+                },
+                Instr::Close => {
+                    let start = indexes.pop().ok_or( BfError::UnmatchedClose { position: n, source: pos } )?;
+                    starts.pop();
+                    program[ start ].operand = top + 1;
+                    program[ top ].operand = start + 1;
+                },
+                Instr::MulAdd( targets ) => {
+                    program[ top ].operand = muladds.len();
+                    muladds.push( targets.clone() );
+                },
+                &Instr::AddAt( offset, amount ) => {
+                    program[ top ].operand = muladds.len();
+                    muladds.push( vec![ ( offset, amount ) ] );
+                },
+                &Instr::Move( offset ) => {
+                    program[ top ].operand = muladds.len();
+                    muladds.push( vec![ ( offset, 1 ) ] );
+                },
+                &Instr::Custom( ch ) => {
+                    program[ top ].operand = self.customs.iter().position( | &( c, _ ) | c == ch ).ok_or_else( || BfError::InvalidArgument {
+                        message: format!( "'{}' is not a registered command", ch ),
+                    } )?;
+                },
+                //  Only the final HALT has an operand of 0, which marks the
+                //  end of the program.
+                Instr::Halt => program[ top ].operand = 1,
+                _ => if let Some( count ) = instr.count() {
+                    program[ top ].operand = count;
+                },
+            }
+            top += 1;
+        }
+        if let Some( &position ) = starts.first() {
+            return Err( BfError::UnmatchedOpen { position, source: instrs[ position ].pos } );
+        }
+        program[ top ].op = Op::Halt;
+        let program = Program { code: program, muladds, source_map, description: None, customs: self.customs.clone() };
+        debug_assert!( program.check().is_ok(), "threading produced a bad jump or operand" );
+        Ok( program )
+    }
+
+    /// Reads the whole of `reader` and compiles it, so it need not be
+    /// UTF-8.
+    pub fn load( &self, mut reader: impl Read ) -> Result< Program, BfError > {
+        let mut src = Vec::new();
+        reader.read_to_end( &mut src )?;
+        self.compile( src )
+    }
+
+    /// Parses, optimizes and threads `src`, which may be text or raw bytes,
+    /// as `parse` explains.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().compile( b"+\xff\xfe+\xc3." ).unwrap();
+    /// let mut output = Vec::new();
+    /// brainfuck::run( &program, std::io::empty(), &mut output ).unwrap();
+    /// assert_eq!( output, b"\x02" );
+    /// ```
+    pub fn compile( &self, src: impl AsRef< [ u8 ] > ) -> Result< Program, BfError > {
+        let src = src.as_ref();
+        let mut program = self.thread_located( &self.optimize_source( src )? )?;
+        program.description = self.description_of( src );
+        Ok( program )
+    }
+
+    /// Parses and optimizes `src`, giving the instructions that `compile`
+    /// would thread.
+    ///
+    /// ```
+    /// use brainfuck::{Compiler, Instr};
+    ///
+    /// let instrs = Compiler::new().optimize( "+++[->++<]" ).unwrap();
+    /// assert_eq!( instrs, [ Instr::IncrN( 3 ), Instr::MulAdd( vec![ ( 1, 2 ) ] ) ] );
+    /// ```
+    pub fn optimize( &self, src: impl AsRef< [ u8 ] > ) -> Result< Vec< Instr >, BfError > {
+        Ok( self.optimize_source( src.as_ref() )?.into_iter().map( | l | l.instr ).collect() )
+    }
+
+    fn optimize_source( &self, src: &[ u8 ] ) -> Result< Vec< Located >, BfError > {
+        Ok( self.optimize_report( src )?.0 )
+    }
+
+    /// Parses and optimizes `src` as `compile` would, reporting how many
+    /// instructions each pass was given and left.
+    ///
+    /// ```
+    /// let report = brainfuck::Compiler::new().opt_level( 2 ).opt_report( "+[-]+[-]" ).unwrap();
+    /// let clear = report.passes.iter().find( | pass | pass.name == "clear_loops" ).unwrap();
+    /// assert_eq!( ( clear.before, clear.after ), ( 8, 4 ) );
+    /// assert_eq!( ( report.before, report.after ), ( 8, 3 ) );
+    /// ```
+    pub fn opt_report( &self, src: impl AsRef< [ u8 ] > ) -> Result< OptReport, BfError > {
+        Ok( self.optimize_report( src.as_ref() )?.1 )
+    }
+
+    fn optimize_report( &self, src: &[ u8 ] ) -> Result< ( Vec< Located >, OptReport ), BfError > {
+        let instrs = self.parse_source( src )?;
+        Ok( match &self.optimizer {
+            Some( optimizer ) => optimizer.run_report( instrs ),
+            None => Optimizer::level( self.opt_level, self.overflow == Overflow::Wrap ).run_report( instrs ),
+        } )
+    }
+
+    /// Compiles `src` and lists each of its commands by position beside the
+    /// instruction compiled from it, with brackets showing the index of
+    /// their partner. A command folded into an earlier instruction, such as
+    /// the rest of a run, shows the index of that instruction in brackets.
+    ///
+    /// ```
+    /// assert_eq!( brainfuck::Compiler::new().opt_level( 0 ).listing( "[+]" ).unwrap(), concat!(
+    ///     "   1:1    [     0  OPEN     matches 2\n",
+    ///     "   1:2    +     1  INCR\n",
+    ///     "   1:3    ]     2  CLOSE    matches 0\n",
+    /// ) );
+    /// ```
+    pub fn listing( &self, src: impl AsRef< [ u8 ] > ) -> Result< String, BfError > {
+        let src = src.as_ref();
+        let program = self.compile( src )?;
+        let mut text = String::new();
+        let mut last = 0;
+        for Located { instr, pos } in self.parse_source( src )? {
+            let Some( pos ) = pos else { continue };
+            let command = crate::ir::to_source( &[ instr ] );
+            let line = match program.source_map.iter().position( | &p | p == pos ) {
+                Some( pc ) => {
+                    last = pc;
+                    let Slot { op, operand } = program.code[ pc ];
+                    let partner = match op {
+                        Op::Open | Op::Close => format!( "matches {}", operand - 1 ),
+                        _ => String::new(),
+                    };
+                    format!( "{:>4}:{:<4} {}  {:>4}  {:<8} {}", pos.line, pos.column, command, pc, op.name(), partner )
+                },
+                None => format!( "{:>4}:{:<4} {}  {:>4}", pos.line, pos.column, command, format!( "({})", last ) ),
+            };
+            let _ = writeln!( text, "{}", line.trim_end() );
+        }
+        Ok( text )
+    }
+
+}
+
+//  The characters of `src`, with each byte that is not part of valid UTF-8
+//  given as an error on its own.
+fn decode( src: &[ u8 ] ) -> impl Iterator< Item = Result< char, u8 > > + '_ {
+    src.utf8_chunks().flat_map( | chunk | chunk.valid().chars().map( Ok ).chain( chunk.invalid().iter().map( | &byte | Err( byte ) ) ) )
+}
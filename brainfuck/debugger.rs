@@ -0,0 +1,125 @@
+/*
+    A small command-line debugger that steps an engine one opcode at a time.
+    Commands are read one line at a time from `commands` and responses
+    written to `out`, so it can be driven by a script as well as
+    interactively.
+
+        step            execute one opcode
+        continue        run until the program halts or reaches a breakpoint
+        break PC        stop before the opcode at PC
+        break LINE:COL  stop before the command at that source position
+        watch ADDR      stop when a tape cell changes
+        mem ADDR        print a tape cell
+        pc              show the program counter and its opcode
+        quit            stop debugging
+*/
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::prelude::*;
+
+use crate::cell::Cell;
+use crate::compiler::SourcePos;
+use crate::engine::{Engine, StepResult};
+use crate::error::BfError;
+use crate::visualize::format_tape;
+
+pub fn debug< C: Cell >( e: &mut Engine< C >, mut commands: impl Iterator< Item = String >, mut out: impl Write ) -> Result< (), BfError > {
+    let mut breakpoints = HashSet::< usize >::new();
+    //  The last value seen in each watched cell, where a cell that the tape
+    //  has not grown to yet counts as zero.
+    let mut watches = BTreeMap::< isize, C >::new();
+    loop {
+        let _ = write!( out, "(bf) " );
+        let _ = out.flush();
+        let line = match commands.next() {
+            Some( line ) => line,
+            None => return Ok(()),
+        };
+        let words: Vec< &str > = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {},
+            [ "step" ] | [ "s" ] => {
+                if e.step()? == StepResult::Halted {
+                    let _ = writeln!( out, "Program halted" );
+                } else {
+                    show_pc( e, &mut out );
+                }
+            },
+            [ "continue" ] | [ "c" ] => loop {
+                let pc = e.pc();
+                if e.step()? == StepResult::Halted {
+                    let _ = writeln!( out, "Program halted" );
+                    break;
+                }
+                if check_watches( e, &mut watches, pc, &mut out ) {
+                    break;
+                }
+                if breakpoints.contains( &e.pc() ) {
+                    let _ = write!( out, "Breakpoint: " );
+                    show_pc( e, &mut out );
+                    let _ = writeln!( out, "{}", e.tape_window( 4 ) );
+                    break;
+                }
+            },
+            [ "break", at ] | [ "b", at ] => match breakpoint_pc( e, at ) {
+                Some( pc ) => {
+                    breakpoints.insert( pc );
+                    let _ = writeln!( out, "Breakpoint at pc={}", pc );
+                },
+                None => { let _ = writeln!( out, "No instruction at {}", at ); },
+            },
+            [ "watch", addr ] | [ "w", addr ] => match addr.parse::< isize >() {
+                Ok( addr ) => {
+                    watches.insert( addr, e.cell( addr ).unwrap_or( C::ZERO ) );
+                    let _ = writeln!( out, "Watching [{}]", addr );
+                },
+                Err( _ ) => { let _ = writeln!( out, "No such cell: {}", addr ); },
+            },
+            [ "mem", addr ] | [ "m", addr ] => match addr.parse::< isize >().ok().filter( | &a | e.cell( a ).is_some() ) {
+                //  The head is given as past any tape, so that the cell is
+                //  never bracketed.
+                Some( a ) => {
+                    let at = e.origin().saturating_add_signed( a );
+                    let _ = writeln!( out, "[{}] = {}", addr, format_tape( e.tape(), at..at + 1, e.dump_format, usize::MAX, e.unsigned ) );
+                },
+                None => { let _ = writeln!( out, "No such cell: {}", addr ); },
+            },
+            [ "pc" ] => show_pc( e, &mut out ),
+            [ "quit" ] | [ "q" ] => return Ok(()),
+            _ => { let _ = writeln!( out, "Commands: step, continue, break PC|LINE:COL, watch ADDR, mem ADDR, pc, quit" ); },
+        }
+    }
+}
+
+//  Resolves the argument of `break`, which is either a pc or a source
+//  position.
+fn breakpoint_pc< C: Cell >( e: &Engine< C >, at: &str ) -> Option< usize > {
+    match at.split_once( ':' ) {
+        Some( ( line, column ) ) => e.pc_at( SourcePos { line: line.parse().ok()?, column: column.parse().ok()? } ),
+        None => at.parse().ok().filter( | &pc | e.opcode_name_at( pc ).is_some() ),
+    }
+}
+
+//  Reports each watched cell that the opcode at `pc` changed, returning
+//  whether there were any.
+fn check_watches< C: Cell >( e: &Engine< C >, watches: &mut BTreeMap< isize, C >, pc: usize, out: &mut impl Write ) -> bool {
+    let mut changed = false;
+    for ( &addr, old ) in watches.iter_mut() {
+        let new = e.cell( addr ).unwrap_or( C::ZERO );
+        if new != *old {
+            let name = e.opcode_name_at( pc ).unwrap_or( "?" );
+            let _ = writeln!( out, "Watchpoint [{}]: {} -> {} at pc={} {}", addr, e.show( *old ), e.show( new ), pc, name );
+            *old = new;
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn show_pc< C: Cell >( e: &Engine< C >, out: &mut impl Write ) {
+    let name = e.opcode_name_at( e.pc() ).unwrap_or( "?" );
+    let _ = match e.source_pos() {
+        Some( pos ) => writeln!( out, "pc={} {} ({}) loc={}", e.pc(), name, pos, e.loc() ),
+        None => writeln!( out, "pc={} {} loc={}", e.pc(), name, e.loc() ),
+    };
+}
@@ -0,0 +1,123 @@
+/*
+    A direct-threaded alternative to the threaded engine. The threaded
+    engine is subroutine-threaded: a central loop fetches the function
+    pointer of each instruction in turn and calls it. Here each handler
+    finishes by fetching the handler of the next instruction itself and
+    handing it back, so nothing outside the handlers looks at the pc. Rust
+    does not promise tail calls, so a trampoline makes the call that a C
+    version would make with a computed goto. Each handler wraps the same
+    opcode function and the table of handlers is built from the same
+    compiled program, so only the style of dispatch differs.
+*/
+
+use std::io::prelude::*;
+
+use crate::cell::Cell;
+use crate::engine::*;
+use crate::error::BfError;
+
+//  A handler runs one instruction and returns the handler for the next,
+//  or `None` once the program has halted. The struct lets the function
+//  type name itself.
+struct Handler< C: Cell >( fn( &mut Engine< C >, &[ Handler< C > ] ) -> Next< C > );
+
+type Next< C > = Result< Option< Handler< C > >, BfError >;
+
+impl< C: Cell > Clone for Handler< C > {
+    fn clone( &self ) -> Self {
+        *self
+    }
+}
+
+impl< C: Cell > Copy for Handler< C > {}
+
+//  Defines a handler for each opcode function, each in a block of its own.
+macro_rules! handlers {
+    ( $( $op:ident => $opcode:ident ),* $(,)? ) => {
+        fn handler< C: Cell >( op: Op ) -> Handler< C > {
+            match op {
+                $( Op::$op => {
+                    fn handle< C: Cell >( e: &mut Engine< C >, table: &[ Handler< C > ] ) -> Next< C > {
+                        e.steps += 1;
+                        $opcode( e )?;
+                        Ok( if e.halted { None } else { Some( table[ e.pc ] ) } )
+                    }
+                    Handler( handle::< C > )
+                }, )*
+            }
+        }
+    };
+}
+
+handlers! {
+    Incr => INCR,
+    Decr => DECR,
+    IncrSat => INCR_SAT,
+    DecrSat => DECR_SAT,
+    IncrN => INCR_N,
+    DecrN => DECR_N,
+    IncrNSat => INCR_N_SAT,
+    DecrNSat => DECR_N_SAT,
+    IncrChk => INCR_CHK,
+    DecrChk => DECR_CHK,
+    IncrNChk => INCR_N_CHK,
+    DecrNChk => DECR_N_CHK,
+    SetZero => SET_ZERO,
+    SetConst => SET_CONST,
+    MulAdd => MULADD,
+    AddAt => ADD_AT,
+    Right => RIGHT,
+    Left => LEFT,
+    RightN => RIGHT_N,
+    LeftN => LEFT_N,
+    RightGrow => RIGHT_GROW,
+    RightNGrow => RIGHT_N_GROW,
+    LeftGrow => LEFT_GROW,
+    LeftNGrow => LEFT_N_GROW,
+    Open => OPEN,
+    Close => CLOSE,
+    Put => PUT,
+    Get => GET,
+    Halt => HALT,
+    Dump => DUMP,
+    Rand => RAND,
+    PutNum => PUT_NUM,
+    SwitchTape => SWITCH_TAPE,
+    Assert => ASSERT,
+    Move => MOVE,
+    Custom => CUSTOM,
+    Trap => TRAP,
+    ScanRight => SCAN_RIGHT,
+    ScanLeft => SCAN_LEFT,
+}
+
+impl<'io, C: Cell> Engine<'io, C> {
+
+    /// Runs the program like `run`, but with each opcode fetching the next
+    /// one, as in direct threading. Tracing, profiling, progress reports,
+    /// step limits, loop budgets, protected cells and the cost model are
+    /// not supported.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().compile( "++++++[>++++++++<-]>." ).unwrap();
+    /// let mut output = Vec::new();
+    /// brainfuck::Engine::< i8 >::new( &program, 4 ).with_io( std::io::empty(), &mut output ).run_direct().unwrap();
+    /// assert_eq!( output, b"0" );
+    /// ```
+    pub fn run_direct( &mut self ) -> Result< (), BfError > {
+        let result = self.dispatch_direct();
+        let flushed = self.output.flush();
+        result?;
+        flushed.map_err( | err | self.output_error( err ) )
+    }
+
+    fn dispatch_direct( &mut self ) -> Result< (), BfError > {
+        let table: Vec< Handler< C > > = self.ops.iter().map( | &op | handler( op ) ).collect();
+        let mut next = if self.halted { None } else { Some( table[ self.pc ] ) };
+        while let Some( Handler( handle ) ) = next {
+            next = handle( self, &table )?;
+        }
+        Ok(())
+    }
+
+}
@@ -0,0 +1,2123 @@
+/*
+    The subroutine-threaded engine. Each instruction is a function pointer
+    that updates the engine state, including advancing the program counter.
+*/
+
+use std::collections::BTreeMap;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::ops::{Range, RangeInclusive};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::cell::{Cell, Endian, Shown};
+use crate::compiler::{Program, SourcePos};
+use crate::error::BfError;
+use crate::json::Json;
+use crate::logging;
+use crate::visualize::{format_tape, DumpFormat};
+
+/// An instruction as the engine runs it: a function that updates the
+/// engine, including advancing its program counter.
+pub type OpCode< C > = fn( &mut Engine< C > ) -> Result< (), BfError >;
+
+//  Called with the steps taken and the pc by `Engine::progress`.
+type ProgressReport< 'io > = Box< dyn FnMut( u64, usize ) + 'io >;
+
+//  Called with the index and new value of each cell written, by
+//  `Engine::on_write`.
+type WriteHook< 'io, C > = Box< dyn FnMut( usize, C ) + 'io >;
+
+//  Called with the engine when the program halts, by `Engine::on_halt`.
+type HaltHook< 'io, C > = Box< dyn FnOnce( &Engine< 'io, C > ) + 'io >;
+
+/// The name of the opcode that `op` points to, found by comparing it, as
+/// an address, with each opcode in turn.
+///
+/// This is only as good as function pointer comparison, which is to say
+/// not very. The compiler may give one function several addresses, one per
+/// crate or codegen unit that instantiates it, or fold two functions with
+/// the same body into one address, so a pointer made in one crate may not
+/// be found from another. That is why the engine keeps an `Op` beside
+/// each pointer and names its own opcodes with `opcode_name_at`, and why
+/// there is no table built once and kept: it would need a static for each
+/// type of cell. A linear search of `Op::ALL` is cheap enough anyway.
+pub fn opcode_name< C: Cell >( op: OpCode< C > ) -> Option< &'static str > {
+    Op::ALL.iter().find( | known | known.opcode::< C >() as usize == op as usize ).map( | known | known.name() )
+}
+
+/// The opcodes, as the compiler lays them out. A `Program` is a list of
+/// these, which an engine turns into function pointers for its type of
+/// cell. Keeping them lets the engine name and rewrite its opcodes without
+/// comparing function pointers, which is unreliable. Each fits in a byte,
+/// which token dispatch uses to index its table of opcodes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Op {
+    Incr,
+    Decr,
+    IncrSat,
+    DecrSat,
+    IncrN,
+    DecrN,
+    IncrNSat,
+    DecrNSat,
+    IncrChk,
+    DecrChk,
+    IncrNChk,
+    DecrNChk,
+    SetZero,
+    SetConst,
+    MulAdd,
+    AddAt,
+    Right,
+    Left,
+    RightN,
+    LeftN,
+    RightGrow,
+    RightNGrow,
+    LeftGrow,
+    LeftNGrow,
+    Open,
+    Close,
+    Put,
+    Get,
+    Halt,
+    Dump,
+    ScanRight,
+    ScanLeft,
+    Rand,
+    PutNum,
+    SwitchTape,
+    Assert,
+    Move,
+    Custom,
+    //  Fills the slots after the final HALT, so that a stray jump fails.
+    Trap,
+}
+
+impl Op {
+
+    //  Every opcode, in the order that gives their ids in the binary format.
+    pub(crate) const ALL: [ Op; 39 ] = [
+        Op::Incr, Op::Decr, Op::IncrSat, Op::DecrSat,
+        Op::IncrN, Op::DecrN, Op::IncrNSat, Op::DecrNSat,
+        Op::SetZero, Op::MulAdd,
+        Op::Right, Op::Left, Op::RightN, Op::LeftN,
+        Op::RightGrow, Op::RightNGrow, Op::LeftGrow, Op::LeftNGrow,
+        Op::Open, Op::Close, Op::Put, Op::Get, Op::Halt, Op::Dump,
+        Op::ScanRight, Op::ScanLeft, Op::Rand, Op::Trap, Op::PutNum, Op::SwitchTape,
+        Op::SetConst, Op::AddAt,
+        Op::IncrChk, Op::DecrChk, Op::IncrNChk, Op::DecrNChk,
+        Op::Assert, Op::Move, Op::Custom,
+    ];
+
+    //  The name used in traces and listings.
+    pub(crate) fn name( self ) -> &'static str {
+        match self {
+            Op::Incr => "INCR",
+            Op::Decr => "DECR",
+            Op::IncrSat => "INCR_SAT",
+            Op::DecrSat => "DECR_SAT",
+            Op::IncrN => "INCR_N",
+            Op::DecrN => "DECR_N",
+            Op::IncrNSat => "INCR_N_SAT",
+            Op::DecrNSat => "DECR_N_SAT",
+            Op::IncrChk => "INCR_CHK",
+            Op::DecrChk => "DECR_CHK",
+            Op::IncrNChk => "INCR_N_CHK",
+            Op::DecrNChk => "DECR_N_CHK",
+            Op::SetZero => "SET_ZERO",
+            Op::SetConst => "SET_CONST",
+            Op::MulAdd => "MULADD",
+            Op::AddAt => "ADD_AT",
+            Op::Right => "RIGHT",
+            Op::Left => "LEFT",
+            Op::RightN => "RIGHT_N",
+            Op::LeftN => "LEFT_N",
+            Op::RightGrow => "RIGHT_GROW",
+            Op::RightNGrow => "RIGHT_N_GROW",
+            Op::LeftGrow => "LEFT_GROW",
+            Op::LeftNGrow => "LEFT_N_GROW",
+            Op::Open => "OPEN",
+            Op::Close => "CLOSE",
+            Op::Put => "PUT",
+            Op::Get => "GET",
+            Op::Halt => "HALT",
+            Op::Dump => "DUMP",
+            Op::ScanRight => "SCAN_RIGHT",
+            Op::ScanLeft => "SCAN_LEFT",
+            Op::Rand => "RAND",
+            Op::PutNum => "PUT_NUM",
+            Op::SwitchTape => "SWITCH_TAPE",
+            Op::Assert => "ASSERT",
+            Op::Move => "MOVE",
+            Op::Custom => "CUSTOM",
+            Op::Trap => "TRAP",
+        }
+    }
+
+    //  The weight of the opcode in the cost model, which charges the jumps
+    //  double, since they test a cell and may move the pc anywhere.
+    pub(crate) fn cost( self ) -> u64 {
+        match self {
+            Op::Open | Op::Close => 2,
+            _ => 1,
+        }
+    }
+
+    pub(crate) const fn opcode< C: Cell >( self ) -> OpCode< C > {
+        match self {
+            Op::Incr => INCR,
+            Op::Decr => DECR,
+            Op::IncrSat => INCR_SAT,
+            Op::DecrSat => DECR_SAT,
+            Op::IncrN => INCR_N,
+            Op::DecrN => DECR_N,
+            Op::IncrNSat => INCR_N_SAT,
+            Op::DecrNSat => DECR_N_SAT,
+            Op::IncrChk => INCR_CHK,
+            Op::DecrChk => DECR_CHK,
+            Op::IncrNChk => INCR_N_CHK,
+            Op::DecrNChk => DECR_N_CHK,
+            Op::SetZero => SET_ZERO,
+            Op::SetConst => SET_CONST,
+            Op::MulAdd => MULADD,
+            Op::AddAt => ADD_AT,
+            Op::Right => RIGHT,
+            Op::Left => LEFT,
+            Op::RightN => RIGHT_N,
+            Op::LeftN => LEFT_N,
+            Op::RightGrow => RIGHT_GROW,
+            Op::RightNGrow => RIGHT_N_GROW,
+            Op::LeftGrow => LEFT_GROW,
+            Op::LeftNGrow => LEFT_N_GROW,
+            Op::Open => OPEN,
+            Op::Close => CLOSE,
+            Op::Put => PUT,
+            Op::Get => GET,
+            Op::Halt => HALT,
+            Op::Dump => DUMP,
+            Op::ScanRight => SCAN_RIGHT,
+            Op::ScanLeft => SCAN_LEFT,
+            Op::Rand => RAND,
+            Op::PutNum => PUT_NUM,
+            Op::SwitchTape => SWITCH_TAPE,
+            Op::Assert => ASSERT,
+            Op::Move => MOVE,
+            Op::Custom => CUSTOM,
+            Op::Trap => TRAP,
+        }
+    }
+
+}
+
+/// An instruction of a compiled program. The operand is the jump target
+/// of OPEN and CLOSE, the count of a folded run, the index of MULADD's
+/// targets in the program's table of them, or that of a CUSTOM opcode in
+/// its table of those, and is otherwise 0.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Slot {
+    pub(crate) op: Op,
+    pub(crate) operand: usize,
+}
+
+impl Slot {
+    pub(crate) const EMPTY: Slot = Slot { op: Op::Trap, operand: 0 };
+}
+
+/// What `,` stores in the current cell at end of input.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EofPolicy {
+    NoChange,
+    Zero,
+    NegOne,
+}
+
+impl std::str::FromStr for EofPolicy {
+    type Err = String;
+
+    fn from_str( s: &str ) -> Result< Self, Self::Err > {
+        match s {
+            "nochange" => Ok( EofPolicy::NoChange ),
+            "zero" => Ok( EofPolicy::Zero ),
+            "neg-one" => Ok( EofPolicy::NegOne ),
+            _ => Err( format!( "Unknown EOF behaviour '{}' (expected nochange, zero or neg-one)", s ) ),
+        }
+    }
+}
+
+/// How the tape behaves when the data pointer runs off either end.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TapeMode {
+    /// A fixed number of cells; moving off either end is an error.
+    Bounded,
+    /// Grows to the right as needed; moving left of cell 0 is an error.
+    GrowRight,
+    /// Grows in both directions as needed.
+    Bidirectional,
+    /// A fixed number of cells, where moving off one end comes back on at
+    /// the other, as on a torus.
+    Wrap,
+}
+
+impl std::str::FromStr for TapeMode {
+    type Err = String;
+
+    fn from_str( s: &str ) -> Result< Self, Self::Err > {
+        match s {
+            "bounded" => Ok( TapeMode::Bounded ),
+            "grow-right" => Ok( TapeMode::GrowRight ),
+            "bidirectional" => Ok( TapeMode::Bidirectional ),
+            "wrap" => Ok( TapeMode::Wrap ),
+            _ => Err( format!( "Unknown tape '{}' (expected bounded, grow-right, bidirectional or wrap)", s ) ),
+        }
+    }
+}
+
+/// Whether the program can continue after a single `Engine::step`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StepResult {
+    Running,
+    Halted,
+}
+
+/// One opcode dispatched by `Engine::run_iter`: where it was and the
+/// data pointer and cell that it left.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExecutedStep< C > {
+    pub pc: usize,
+    pub opcode: &'static str,
+    pub loc: isize,
+    pub cell: C,
+}
+
+/// Why a run came to an end without an error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HaltReason {
+    /// The program ran to its end.
+    Halted,
+    /// The run was left part way through, as by the debugger's `quit`.
+    Stopped,
+    /// The run reached the `max_steps` limit.
+    StepLimit { limit: u64 },
+    /// The run saw the `interrupt` flag set.
+    Interrupted,
+}
+
+/// What `run_summary` reports about a run: the steps it took, where it
+/// ended and why.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RunSummary {
+    pub steps: u64,
+    pub pc: usize,
+    pub source: Option< SourcePos >,
+    pub reason: HaltReason,
+}
+
+impl RunSummary {
+
+    /// Treats a run cut short by a step limit or an interrupt as failing,
+    /// with the error that `run` gives for it.
+    pub fn into_result( self ) -> Result< RunSummary, BfError > {
+        let RunSummary { pc, source, .. } = self;
+        match self.reason {
+            HaltReason::StepLimit { limit } => Err( BfError::StepLimitExceeded { limit, pc, source } ),
+            HaltReason::Interrupted => Err( BfError::Interrupted { pc, source } ),
+            HaltReason::Halted | HaltReason::Stopped => Ok( self ),
+        }
+    }
+
+}
+
+/// The limits that `Engine::run_sandboxed` puts on a run all at once, for
+/// running a program from a source that is not trusted, such as a web
+/// form.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SandboxConfig {
+    /// The opcodes that the run may dispatch.
+    pub max_steps: u64,
+    /// The bytes that the run may write.
+    pub max_output: u64,
+    /// The cells that the tape may grow to, from the engine's own size or
+    /// this, whichever is smaller.
+    pub max_cells: usize,
+}
+
+impl Default for SandboxConfig {
+    /// Ten million steps, a megabyte of output and a tape of the usual
+    /// size.
+    fn default() -> Self {
+        SandboxConfig { max_steps: 10_000_000, max_output: 1 << 20, max_cells: MEMORY_SIZE }
+    }
+}
+
+/// Which instructions of the program a profiled run dispatched, from
+/// `Engine::coverage`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Coverage {
+    /// The instructions up to and including the final HALT.
+    pub instructions: usize,
+    /// The pc and source position of each of them that never ran.
+    pub uncovered: Vec< ( usize, Option< SourcePos > ) >,
+}
+
+impl Coverage {
+
+    /// The share of the instructions that ran, from 0 to 100.
+    pub fn percent( &self ) -> f64 {
+        let covered = self.instructions - self.uncovered.len();
+        100.0 * covered as f64 / self.instructions.max( 1 ) as f64
+    }
+
+}
+
+/// Default size of both the tape and the program array.
+pub const MEMORY_SIZE: usize = 30000;
+
+//  Where the random bytes of `?` start when no seed is given.
+pub(crate) const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+//  The program is kept as parallel arrays indexed by pc, with one entry for
+//  each instruction, so that an opcode finds its operand at the same index
+//  rather than in a slot of its own.
+pub struct Engine<'io, C: Cell = i8> {
+    pub(crate) program : Vec< OpCode< C > >,
+    pub(crate) operands : Vec< usize >,
+    pub(crate) ops : Vec< Op >,
+    pub(crate) muladds : Vec< Vec< ( isize, isize ) > >,
+    //  The opcode of each CUSTOM, or `None` if it was registered for cells
+    //  of another type.
+    pub(crate) customs : Vec< Option< OpCode< C > > >,
+    pub(crate) source_map : Vec< SourcePos >,
+    pub(crate) pc : usize,
+    pub(crate) memory : Vec< C >,
+    //  The physical index of the current cell. Cell 0 as the program sees
+    //  it is at `origin`, which moves right as cells are added on the left.
+    pub(crate) loc : usize,
+    pub(crate) origin : usize,
+    //  Where `reset` puts the data pointer, from `start_cell`.
+    pub(crate) start : usize,
+    //  The other tape that `%` switches to, with its `loc` and `origin`,
+    //  which is only allocated when first used. Switching swaps it with
+    //  the current tape, so the opcodes only ever see one.
+    pub(crate) spare : ( Vec< C >, usize, usize ),
+    pub(crate) tape_index : usize,
+    //  The most cells the tape may grow to; no growth when `None`.
+    pub(crate) max_cells : Option< usize >,
+    pub(crate) grow_left : bool,
+    //  Whether the pointer wraps around the ends of a fixed tape. Only the
+    //  moves that would otherwise fail look at this.
+    pub(crate) wraps : bool,
+    pub(crate) halted : bool,
+    pub(crate) steps : u64,
+    pub(crate) max_steps : Option< u64 >,
+    pub(crate) interrupt : Option< &'io AtomicBool >,
+    pub(crate) eof : EofPolicy,
+    pub(crate) strip_cr : bool,
+    pub(crate) ascii_only : bool,
+    //  The order in which `.` and `,` write and read every byte of a cell,
+    //  when they do.
+    pub(crate) wide_io : Option< Endian >,
+    //  Whether traces, dumps and the debugger show cells as unsigned.
+    pub(crate) unsigned : bool,
+    //  How `#`, `tape_window` and the debugger show cells.
+    pub(crate) dump_format : DumpFormat,
+    //  The xorshift state that `?` draws from, and where it starts again
+    //  on `reset`.
+    pub(crate) seed : u64,
+    pub(crate) rng : u64,
+    pub(crate) trace : Option< Box< dyn Write + 'io > >,
+    //  Whether the trace is written as JSON Lines rather than text.
+    pub(crate) trace_json : bool,
+    //  Where `#` writes its dump of the tape.
+    pub(crate) debug_output : Box< dyn Write + 'io >,
+    //  How many times each instruction has been dispatched, when profiling.
+    pub(crate) profile : Option< Vec< u64 > >,
+    //  The weighted count of the opcodes dispatched, if it is being kept.
+    pub(crate) cost : Option< u64 >,
+    //  The most iterations a loop may run, and the iterations of each loop
+    //  so far, indexed by the pc of the first instruction of its body.
+    pub(crate) loop_budget : Option< ( u64, Vec< u64 > ) >,
+    //  The most bytes that the program may write, and how many it has.
+    pub(crate) output_limit : Option< ( u64, u64 ) >,
+    //  A copy of the tape and its origin, taken by `mark_tape`.
+    pub(crate) marked : Option< ( Vec< C >, usize ) >,
+    //  The cells that the program may read but not write.
+    pub(crate) protect : Option< Range< usize > >,
+    //  The reads and writes of each cell by its address, for `heatmap`.
+    pub(crate) heatmap : Option< BTreeMap< isize, ( u64, u64 ) > >,
+    //  The lowest and highest cells read or written, for `extent`.
+    pub(crate) extent : Option< Option< ( isize, isize ) > >,
+    //  The interval and the report to make after that many steps.
+    pub(crate) progress : Option< ( u64, ProgressReport< 'io > ) >,
+    pub(crate) on_write : Option< WriteHook< 'io, C > >,
+    pub(crate) on_halt : Option< HaltHook< 'io, C > >,
+    //  Buffered, so that `,` is not a system call per byte.
+    pub(crate) input : BufReader< Box< dyn Read + 'io > >,
+    //  Buffered too, and flushed whenever something else is about to be
+    //  written or read, and at the end of a run.
+    pub(crate) output : BufWriter< Box< dyn Write + 'io > >,
+}
+
+//  The opcodes that `program` registered, as functions on cells of type `C`.
+fn custom_opcodes< C: Cell >( program: &Program ) -> Vec< Option< OpCode< C > > > {
+    program.customs.iter().map( | ( _, opcode ) | opcode.downcast_ref::< OpCode< C > >().copied() ).collect()
+}
+
+impl<'io, C: Cell> Engine<'io, C> {
+
+    /// Creates an engine with a zeroed tape of `cells` cells, ready to run
+    /// `program` against stdin and stdout.
+    pub fn new( program: &Program, cells: usize ) -> Self {
+        Engine {
+            program: program.code.iter().map( | slot | slot.op.opcode() ).collect(),
+            operands: program.code.iter().map( | slot | slot.operand ).collect(),
+            ops: program.code.iter().map( | slot | slot.op ).collect(),
+            muladds: program.muladds.clone(),
+            customs: custom_opcodes( program ),
+            source_map: program.source_map.clone(),
+            pc: 0,
+            memory: vec![ C::ZERO; cells ],
+            loc: 0,
+            origin: 0,
+            start: 0,
+            spare: ( Vec::new(), 0, 0 ),
+            tape_index: 0,
+            max_cells: None,
+            grow_left: false,
+            wraps: false,
+            halted: false,
+            steps: 0,
+            max_steps: None,
+            interrupt: None,
+            eof: EofPolicy::NoChange,
+            strip_cr: false,
+            ascii_only: false,
+            wide_io: None,
+            unsigned: false,
+            dump_format: DumpFormat::Dec,
+            seed: DEFAULT_SEED,
+            rng: DEFAULT_SEED,
+            trace: None,
+            trace_json: false,
+            debug_output: Box::new( std::io::stderr() ),
+            profile: None,
+            cost: None,
+            loop_budget: None,
+            output_limit: None,
+            marked: None,
+            protect: None,
+            heatmap: None,
+            extent: None,
+            progress: None,
+            on_write: None,
+            on_halt: None,
+            input: BufReader::new( Box::new( std::io::stdin() ) ),
+            output: BufWriter::new( Box::new( std::io::stdout() ) ),
+        }
+    }
+
+    /// Replaces stdin and stdout with the given streams, so that output
+    /// can be captured or input scripted.
+    pub fn with_io( mut self, input: impl Read + 'io, output: impl Write + 'io ) -> Self {
+        self.input = BufReader::with_capacity( self.input.capacity(), Box::new( input ) );
+        self.output = BufWriter::new( Box::new( output ) );
+        self
+    }
+
+    /// Sets the cells of the tape from cell 0 on to the bytes of `pattern`
+    /// in turn, repeating it to the end, rather than leaving them zero.
+    /// Any cells that a growing tape adds later are still zero.
+    ///
+    /// ```
+    /// let program = brainfuck::compile( "" ).unwrap();
+    /// let engine = brainfuck::Engine::< i8 >::new( &program, 5 ).fill( &[ 1, 2 ] );
+    /// assert_eq!( engine.tape(), [ 1, 2, 1, 2, 1 ] );
+    /// ```
+    pub fn fill( mut self, pattern: &[ u8 ] ) -> Self {
+        for ( cell, &byte ) in self.memory[ self.origin.. ].iter_mut().zip( pattern.iter().cycle() ) {
+            *cell = C::from_byte( byte );
+        }
+        self
+    }
+
+    /// Copies `bytes` onto the tape starting at cell 0, dropping any that
+    /// do not fit.
+    pub fn init_tape( mut self, bytes: &[ u8 ] ) -> Self {
+        for ( cell, &byte ) in self.memory[ self.origin.. ].iter_mut().zip( bytes ) {
+            *cell = C::from_byte( byte );
+        }
+        self
+    }
+
+    /// Starts the data pointer at `cell` rather than cell 0, here and after
+    /// each `reset`, such as over data loaded by `init_tape`. A cell past
+    /// the end of the tape is taken as the last one.
+    ///
+    /// ```
+    /// let program = brainfuck::compile( "+" ).unwrap();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 4 ).start_cell( 2 );
+    /// engine.run().unwrap();
+    /// assert_eq!( ( engine.tape(), engine.loc() ), ( &[ 0, 0, 1, 0 ][ .. ], 2 ) );
+    /// ```
+    pub fn start_cell( mut self, cell: usize ) -> Self {
+        self.start = cell.min( self.memory.len().saturating_sub( 1 ) );
+        self.loc = self.origin + self.start;
+        self
+    }
+
+    /// Lets the tape grow, doubling in size as needed up to `max_cells`,
+    /// instead of failing when the data pointer runs off its end, or with
+    /// `TapeMode::Wrap` lets the pointer wrap around a tape of fixed size.
+    ///
+    /// ```
+    /// use brainfuck::{Engine, TapeMode};
+    ///
+    /// let program = brainfuck::compile( "<+>>+" ).unwrap();
+    /// let mut engine = Engine::< i8 >::new( &program, 4 ).tape_mode( TapeMode::Wrap, 0 );
+    /// engine.run().unwrap();
+    /// assert_eq!( ( engine.loc(), engine.tape() ), ( 1, &[ 0, 1, 0, 1 ][ .. ] ) );
+    /// ```
+    pub fn tape_mode( mut self, mode: TapeMode, max_cells: usize ) -> Self {
+        if mode == TapeMode::Bounded {
+            return self;
+        }
+        if mode == TapeMode::Wrap {
+            self.wraps = true;
+            return self;
+        }
+        self.max_cells = Some( max_cells );
+        self.grow_left = mode == TapeMode::Bidirectional;
+        self.install_growth();
+        self
+    }
+
+    //  Only the moves need a growth check, so swap them for checking
+    //  versions and leave the other opcodes untouched.
+    fn install_growth( &mut self ) {
+        if self.max_cells.is_none() {
+            return;
+        }
+        for ( pc, op ) in self.ops.iter_mut().enumerate() {
+            let grown = match *op {
+                Op::Right => Op::RightGrow,
+                Op::RightN => Op::RightNGrow,
+                Op::Left if self.grow_left => Op::LeftGrow,
+                Op::LeftN if self.grow_left => Op::LeftNGrow,
+                _ => continue,
+            };
+            *op = grown;
+            self.program[ pc ] = grown.opcode();
+        }
+    }
+
+    /// Replaces the program, keeping the settings and reusing the arrays.
+    /// The tape and registers are left alone, so call `reset` too to start
+    /// the new program afresh.
+    pub fn load( &mut self, program: &Program ) {
+        self.program.clear();
+        self.program.extend( program.code.iter().map( | slot | slot.op.opcode::< C >() ) );
+        self.operands.clear();
+        self.operands.extend( program.code.iter().map( | slot | slot.operand ) );
+        self.ops.clear();
+        self.ops.extend( program.code.iter().map( | slot | slot.op ) );
+        self.muladds.clone_from( &program.muladds );
+        self.customs = custom_opcodes( program );
+        self.source_map.clone_from( &program.source_map );
+        self.install_growth();
+        if let Some( profile ) = &mut self.profile {
+            profile.clear();
+            profile.resize( self.program.len(), 0 );
+        }
+        if let Some( ( _, iterations ) ) = &mut self.loop_budget {
+            iterations.clear();
+            iterations.resize( self.program.len() + 1, 0 );
+        }
+    }
+
+    /// Zeroes the tape, moves the pc and the data pointer back to 0, and
+    /// clears the step count and profile, ready to run again. A tape that
+    /// has grown keeps its size.
+    ///
+    /// ```
+    /// use brainfuck::Engine;
+    ///
+    /// let mut engine = Engine::< i8 >::new( &brainfuck::compile( "+>++" ).unwrap(), 4 ).with_io( std::io::empty(), std::io::sink() );
+    /// engine.run().unwrap();
+    /// engine.load( &brainfuck::compile( "+" ).unwrap() );
+    /// engine.reset();
+    /// engine.run().unwrap();
+    /// assert_eq!( engine.tape(), &[ 1, 0, 0, 0 ] );
+    /// ```
+    pub fn reset( &mut self ) {
+        if self.tape_index == 1 {
+            std::mem::swap( &mut self.memory, &mut self.spare.0 );
+        }
+        self.tape_index = 0;
+        self.spare = ( Vec::new(), 0, 0 );
+        self.memory.fill( C::ZERO );
+        self.pc = 0;
+        self.loc = self.start;
+        self.origin = 0;
+        self.halted = false;
+        self.steps = 0;
+        self.rng = self.seed;
+        if let Some( profile ) = &mut self.profile {
+            profile.fill( 0 );
+        }
+        if let Some( cost ) = &mut self.cost {
+            *cost = 0;
+        }
+        if let Some( ( _, iterations ) ) = &mut self.loop_budget {
+            iterations.fill( 0 );
+        }
+        if let Some( ( _, written ) ) = &mut self.output_limit {
+            *written = 0;
+        }
+        self.marked = None;
+        if let Some( extent ) = &mut self.extent {
+            *extent = None;
+        }
+        if let Some( heatmap ) = &mut self.heatmap {
+            heatmap.clear();
+        }
+    }
+
+    /// Limits the number of opcodes a run may dispatch, so that runaway
+    /// programs fail with `StepLimitExceeded` rather than hanging.
+    pub fn max_steps( mut self, limit: u64 ) -> Self {
+        self.max_steps = Some( limit );
+        self
+    }
+
+    /// Limits the number of bytes that `.` and the other output commands
+    /// may write, counting each escape of `ascii_only` in full. A command
+    /// that would go past the limit writes nothing and fails with
+    /// `OutputLimitExceeded`.
+    ///
+    /// ```
+    /// let program = brainfuck::compile( "+[.]" ).unwrap();
+    /// let mut output = Vec::new();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 4 ).max_output( 3 ).with_io( std::io::empty(), &mut output );
+    /// assert!( matches!( engine.run(), Err( brainfuck::BfError::OutputLimitExceeded { limit: 3, .. } ) ) );
+    /// drop( engine );
+    /// assert_eq!( output, [ 1, 1, 1 ] );
+    /// ```
+    pub fn max_output( mut self, limit: u64 ) -> Self {
+        self.output_limit = Some( ( limit, 0 ) );
+        self
+    }
+
+    //  Counts `n` more bytes of output, failing if that is past the limit.
+    fn count_output( &mut self, n: usize ) -> Result< (), BfError > {
+        if let Some( ( limit, written ) ) = &mut self.output_limit {
+            if *written + n as u64 > *limit {
+                return Err( BfError::OutputLimitExceeded { limit: *limit, pc: self.pc, source: self.source_pos() } );
+            }
+            *written += n as u64;
+        }
+        Ok(())
+    }
+
+    /// Limits the iterations of each loop, each time it is entered, so that
+    /// a loop that runs too long fails with `LoopBudgetExceeded` at its `[`.
+    ///
+    /// ```
+    /// use brainfuck::{BfError, Engine};
+    ///
+    /// let program = brainfuck::compile( "++++[>++++[-]<-]" ).unwrap();
+    /// let mut engine = Engine::< i8 >::new( &program, 4 ).loop_budget( 4 );
+    /// engine.run().unwrap();
+    /// let mut engine = Engine::< i8 >::new( &program, 4 ).loop_budget( 3 );
+    /// assert!( matches!( engine.run(), Err( BfError::LoopBudgetExceeded { budget: 3, pc: 1, .. } ) ) );
+    /// ```
+    pub fn loop_budget( mut self, budget: u64 ) -> Self {
+        self.loop_budget = Some( ( budget, vec![ 0; self.program.len() + 1 ] ) );
+        self
+    }
+
+    /// Makes the cells in `range` read-only, counting from cell 0 as
+    /// `Engine::loc` does, so that an opcode that would write to one of
+    /// them fails with `WriteProtected` instead. The program can still
+    /// read them, which suits constant data loaded by `init_tape`.
+    ///
+    /// ```
+    /// use brainfuck::{BfError, Engine};
+    ///
+    /// let program = brainfuck::compile( "[->+<]>+" ).unwrap();
+    /// let mut engine = Engine::< i8 >::new( &program, 4 ).init_tape( &[ 3 ] ).protect( 0..1 );
+    /// assert!( matches!( engine.run(), Err( BfError::WriteProtected { addr: 0, pc: 0, .. } ) ) );
+    /// ```
+    pub fn protect( mut self, range: Range< usize > ) -> Self {
+        self.protect = Some( range );
+        self
+    }
+
+    /// Stops the run with `BfError::Interrupted` soon after `flag` is set,
+    /// for example by a signal handler. The flag is only looked at every
+    /// few thousand steps, so that checking it costs little.
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use brainfuck::{BfError, Engine};
+    ///
+    /// let program = brainfuck::compile( "+[]" ).unwrap();
+    /// let interrupted = AtomicBool::new( false );
+    /// let mut engine = Engine::< i8 >::new( &program, 10 ).interrupt( &interrupted );
+    /// std::thread::scope( | s | {
+    ///     s.spawn( || {
+    ///         std::thread::sleep( std::time::Duration::from_millis( 10 ) );
+    ///         interrupted.store( true, Ordering::Relaxed );
+    ///     } );
+    ///     assert!( matches!( engine.run(), Err( BfError::Interrupted { .. } ) ) );
+    /// } );
+    /// assert_eq!( engine.tape()[ 0 ], 1 );
+    /// ```
+    pub fn interrupt( mut self, flag: &'io AtomicBool ) -> Self {
+        self.interrupt = Some( flag );
+        self
+    }
+
+    /// Selects what `,` does at end of input; by default the cell is left
+    /// unchanged.
+    pub fn eof( mut self, policy: EofPolicy ) -> Self {
+        self.eof = policy;
+        self
+    }
+
+    /// Sets the size of the buffer that `,` reads through, where 0 reads
+    /// straight from the input. Without a buffer `,` leaves the rest of the
+    /// input unread, so that a reader such as stdin can be shared with
+    /// something else. Any input already buffered is dropped; call this
+    /// before running.
+    pub fn input_buffer( mut self, capacity: usize ) -> Self {
+        self.input = BufReader::with_capacity( capacity, self.input.into_inner() );
+        self
+    }
+
+    /// Seeds the random bytes that `?` stores, when the compiler's
+    /// `rand_op` is on, so that a run can be repeated. Without this the
+    /// seed is a fixed value.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().rand_op( true ).compile( "?>?>?" ).unwrap();
+    /// let run = | seed | {
+    ///     let mut engine = brainfuck::Engine::< i8 >::new( &program, 3 ).seed( seed );
+    ///     engine.run().unwrap();
+    ///     engine.tape().to_vec()
+    /// };
+    /// assert_eq!( run( 7 ), run( 7 ) );
+    /// assert_ne!( run( 7 ), run( 8 ) );
+    /// ```
+    pub fn seed( mut self, seed: u64 ) -> Self {
+        //  Scramble the seed with a round of splitmix64, since xorshift
+        //  takes a while to mix seeds that are small or close together,
+        //  and is stuck at zero.
+        let mut z = seed.wrapping_add( 0x9e37_79b9_7f4a_7c15 );
+        z = ( z ^ ( z >> 30 ) ).wrapping_mul( 0xbf58_476d_1ce4_e5b9 );
+        z = ( z ^ ( z >> 27 ) ).wrapping_mul( 0x94d0_49bb_1331_11eb );
+        z ^= z >> 31;
+        self.seed = if z == 0 { DEFAULT_SEED } else { z };
+        self.rng = self.seed;
+        self
+    }
+
+    /// Makes `,` skip carriage returns, so that programs reading lines
+    /// see the same input whether lines end in `\r\n` or `\n`.
+    pub fn strip_cr( mut self, enabled: bool ) -> Self {
+        self.strip_cr = enabled;
+        self
+    }
+
+    /// Makes `.` write any byte that is not printable ASCII, a newline or
+    /// a tab as an escape such as `\xC8`, so that stray bytes cannot upset
+    /// a terminal.
+    pub fn ascii_only( mut self, enabled: bool ) -> Self {
+        self.ascii_only = enabled;
+        self
+    }
+
+    /// Makes `.` write every byte of the cell, rather than its low byte,
+    /// and `,` read as many bytes into it, in the order given by `endian`.
+    /// An 8-bit cell is unaffected. End of input part way through a cell
+    /// counts as end of input, losing the bytes read.
+    ///
+    /// ```
+    /// use brainfuck::{Endian, Engine};
+    ///
+    /// let program = brainfuck::compile( ",+." ).unwrap();
+    /// let mut output = Vec::new();
+    /// Engine::< i16 >::new( &program, 4 ).wide_io( Endian::Big ).with_io( &[ 0x01, 0xff ][ .. ], &mut output ).run().unwrap();
+    /// assert_eq!( output, [ 0x02, 0x00 ] );
+    /// ```
+    pub fn wide_io( mut self, endian: Endian ) -> Self {
+        self.wide_io = Some( endian );
+        self
+    }
+
+    /// Shows cells as unsigned numbers in traces, dumps and the debugger,
+    /// leaving what the program reads and writes just the same.
+    pub fn unsigned_cells( mut self, enabled: bool ) -> Self {
+        self.unsigned = enabled;
+        self
+    }
+
+    /// Shows cells in `format` in the dumps of `#`, `tape_window` and the
+    /// debugger, which show them in decimal by default.
+    ///
+    /// ```
+    /// use brainfuck::{DumpFormat, Engine};
+    ///
+    /// let program = brainfuck::compile( "++++++++[>++++++++<-]>+" ).unwrap();
+    /// let mut engine = Engine::< i8 >::new( &program, 4 ).dump_format( DumpFormat::Ascii );
+    /// engine.run().unwrap();
+    /// assert_eq!( engine.tape_window( 2 ), "loc=1: . [A] . ." );
+    /// ```
+    pub fn dump_format( mut self, format: DumpFormat ) -> Self {
+        self.dump_format = format;
+        self
+    }
+
+    /// `cell` formatted as traces, dumps and the debugger show it.
+    pub fn show( &self, cell: C ) -> Shown< C > {
+        Shown( cell, self.unsigned )
+    }
+
+    /// Writes a line to `out` describing each opcode before it is
+    /// dispatched.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().opt_level( 0 ).compile( "+." ).unwrap();
+    /// let mut trace = Vec::new();
+    /// brainfuck::Engine::< i8 >::new( &program, 4 ).with_io( std::io::empty(), std::io::sink() ).trace( &mut trace ).run().unwrap();
+    /// assert_eq!( String::from_utf8( trace ).unwrap(), concat!(
+    ///     "pc=0      INCR       loc=0      cell=0\n",
+    ///     "pc=1      PUT        loc=0      cell=1\n",
+    ///     "pc=2      HALT       loc=0      cell=1\n",
+    /// ) );
+    /// ```
+    pub fn trace( mut self, out: impl Write + 'io ) -> Self {
+        self.trace = Some( Box::new( out ) );
+        self
+    }
+
+    /// Like `trace`, but writes each opcode as a line of JSON giving the
+    /// step, counting from 1, the pc, the opcode's name, the head and the
+    /// cell under it. The lines are buffered, and flushed when the run ends.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().opt_level( 0 ).compile( "+" ).unwrap();
+    /// let mut trace = Vec::new();
+    /// brainfuck::Engine::< i8 >::new( &program, 4 ).trace_json( &mut trace ).run().unwrap();
+    /// assert_eq!( String::from_utf8( trace ).unwrap().lines().next(), Some( r#"{"cell":0,"loc":0,"op":"INCR","pc":0,"step":1}"# ) );
+    /// ```
+    pub fn trace_json( mut self, out: impl Write + 'io ) -> Self {
+        self.trace = Some( Box::new( BufWriter::new( out ) ) );
+        self.trace_json = true;
+        self
+    }
+
+    /// Calls `report` with the number of steps taken and the pc after
+    /// every `interval` steps, so that a long run can show it is alive.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().opt_level( 0 ).compile( "++++++++++" ).unwrap();
+    /// let mut reports = Vec::new();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 10 ).progress( 3, | steps, pc | reports.push( ( steps, pc ) ) );
+    /// engine.run().unwrap();
+    /// drop( engine );
+    /// assert_eq!( reports, [ ( 3, 3 ), ( 6, 6 ), ( 9, 9 ) ] );
+    /// ```
+    pub fn progress( mut self, interval: u64, report: impl FnMut( u64, usize ) + 'io ) -> Self {
+        self.progress = Some( ( interval, Box::new( report ) ) );
+        self
+    }
+
+    /// Calls `hook` with the index into `tape` and the new value of each
+    /// cell that the program writes, for tools such as coverage maps. A
+    /// run folded into one opcode is one write, and so is `,` at the end
+    /// of input, even if it leaves the cell alone.
+    ///
+    /// ```
+    /// let program = brainfuck::compile( "+>++" ).unwrap();
+    /// let mut writes = Vec::new();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 4 ).on_write( | at, cell | writes.push( ( at, cell ) ) );
+    /// engine.run().unwrap();
+    /// drop( engine );
+    /// assert_eq!( writes, [ ( 0, 1 ), ( 1, 2 ) ] );
+    /// ```
+    pub fn on_write( mut self, hook: impl FnMut( usize, C ) + 'io ) -> Self {
+        self.on_write = Some( Box::new( hook ) );
+        self
+    }
+
+    /// Calls `hook` once, with the engine in its final state, when the
+    /// program halts, at its end or at an `@`, after flushing its output.
+    /// A run that fails or is cut short does not call it, and nor does a
+    /// later run unless the hook is set again.
+    ///
+    /// ```
+    /// let program = brainfuck::compile( "+++>++" ).unwrap();
+    /// let mut last = None;
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 4 ).on_halt( | e | last = e.cell( e.loc() ) );
+    /// engine.run().unwrap();
+    /// drop( engine );
+    /// assert_eq!( last, Some( 2 ) );
+    /// ```
+    pub fn on_halt( mut self, hook: impl FnOnce( &Engine< 'io, C > ) + 'io ) -> Self {
+        self.on_halt = Some( Box::new( hook ) );
+        self
+    }
+
+    /// Sends the output of the `#` command to `out` rather than stderr.
+    pub fn debug_output( mut self, out: impl Write + 'io ) -> Self {
+        self.debug_output = Box::new( out );
+        self
+    }
+
+    /// Counts how many times each opcode is dispatched, for `opcode_counts`.
+    pub fn profile( mut self ) -> Self {
+        self.profile = Some( vec![ 0; self.program.len() ] );
+        self
+    }
+
+    /// Keeps a cost for the run, for `cost`: the number of opcodes
+    /// dispatched, with OPEN and CLOSE counting twice. Unlike a time, it is
+    /// the same on every machine, so it can catch a change to the
+    /// optimizer that makes programs slower.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().opt_level( 0 ).compile( "++[>+<-]" ).unwrap();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 4 ).cost_model();
+    /// engine.run().unwrap();
+    /// assert_eq!( ( engine.steps(), engine.cost() ), ( 14, Some( 17 ) ) );
+    /// ```
+    pub fn cost_model( mut self ) -> Self {
+        self.cost = Some( 0 );
+        self
+    }
+
+    /// The cost of the run so far, or `None` if the engine is not keeping
+    /// one.
+    pub fn cost( &self ) -> Option< u64 > {
+        self.cost
+    }
+
+    /// How many times the body of each loop that ran has reached its `]`,
+    /// with the pc and source position of its `[`, hottest loop first, or
+    /// `None` if the engine is not profiling. A loop that the optimizer
+    /// turned into something else is not a loop here.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().opt_level( 0 ).compile( "++[>+++[>+<-]<-]" ).unwrap();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 4 ).profile();
+    /// engine.run().unwrap();
+    /// let counts: Vec< _ > = engine.loop_counts().unwrap().into_iter().map( | ( pc, _, n ) | ( pc, n ) ).collect();
+    /// assert_eq!( counts, [ ( 7, 6 ), ( 2, 2 ) ] );
+    /// ```
+    pub fn loop_counts( &self ) -> Option< Vec< ( usize, Option< SourcePos >, u64 ) > > {
+        let profile = self.profile.as_ref()?;
+        let mut counts: Vec< _ > = profile.iter().enumerate().filter( | &( pc, &n ) | self.ops[ pc ] == Op::Close && n > 0 ).map( | ( pc, &n ) | {
+            let open = self.operands[ pc ] - 1;
+            ( open, self.source_map.get( open ).copied(), n )
+        } ).collect();
+        counts.sort_by_key( | &( pc, _, n ) | ( std::cmp::Reverse( n ), pc ) );
+        Some( counts )
+    }
+
+    /// Which instructions have been dispatched at least once, or `None` if
+    /// the engine is not profiling. An instruction that was folded from a
+    /// run of commands is uncovered at the first of them.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().opt_level( 0 ).compile( "[+.]+" ).unwrap();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 4 ).profile();
+    /// engine.run().unwrap();
+    /// let coverage = engine.coverage().unwrap();
+    /// let pcs: Vec< usize > = coverage.uncovered.iter().map( | &( pc, _ ) | pc ).collect();
+    /// assert_eq!( ( coverage.instructions, pcs ), ( 6, vec![ 1, 2, 3 ] ) );
+    /// assert_eq!( coverage.percent(), 50.0 );
+    /// ```
+    pub fn coverage( &self ) -> Option< Coverage > {
+        let profile = self.profile.as_ref()?;
+        let instructions = self.ops.iter().zip( &self.operands ).position( | ( &op, &operand ) | op == Op::Halt && operand == 0 ).map_or( self.ops.len(), | pc | pc + 1 );
+        let uncovered = ( 0..instructions ).filter( | &pc | profile[ pc ] == 0 ).map( | pc | ( pc, self.source_map.get( pc ).copied() ) ).collect();
+        Some( Coverage { instructions, uncovered } )
+    }
+
+    /// The number of times each opcode has been dispatched, most frequent
+    /// first, or `None` if the engine is not profiling.
+    pub fn opcode_counts( &self ) -> Option< Vec< ( &'static str, u64 ) > > {
+        let profile = self.profile.as_ref()?;
+        let mut counts = BTreeMap::< &'static str, u64 >::new();
+        for ( pc, &n ) in profile.iter().enumerate() {
+            if n > 0 {
+                *counts.entry( self.ops[ pc ].name() ).or_insert( 0 ) += n;
+            }
+        }
+        let mut counts: Vec< _ > = counts.into_iter().collect();
+        counts.sort_by_key( | &( _, n ) | std::cmp::Reverse( n ) );
+        Some( counts )
+    }
+
+    /// Counts the reads and writes of each cell, for `cell_accesses`.
+    pub fn heatmap( mut self ) -> Self {
+        self.heatmap = Some( BTreeMap::new() );
+        self
+    }
+
+    /// The cells that have been read or written, as their address with
+    /// the number of reads and of writes, most used first, or `None` if
+    /// the engine is not counting them. A command such as `+` both reads
+    /// and writes its cell, and a scan reads each cell it passes.
+    ///
+    /// ```
+    /// let program = brainfuck::Compiler::new().opt_level( 0 ).compile( "++++[->+<]" ).unwrap();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 4 ).heatmap();
+    /// engine.run().unwrap();
+    /// assert_eq!( engine.cell_accesses().unwrap(), [ ( 0, 13, 8 ), ( 1, 4, 4 ) ] );
+    /// ```
+    pub fn cell_accesses( &self ) -> Option< Vec< ( isize, u64, u64 ) > > {
+        let mut accesses: Vec< _ > = self.heatmap.as_ref()?.iter().map( | ( &at, &( reads, writes ) ) | ( at, reads, writes ) ).collect();
+        accesses.sort_by_key( | &( at, reads, writes ) | ( std::cmp::Reverse( reads + writes ), at ) );
+        Some( accesses )
+    }
+
+    /// Tracks the lowest and highest cells read or written, for `extent`.
+    pub fn track_extent( mut self ) -> Self {
+        self.extent = Some( None );
+        self
+    }
+
+    /// The cells from the lowest to the highest that have been read or
+    /// written, as `cell_accesses` counts them, or `None` if the engine is
+    /// not tracking them or no cell has been touched. This is how much of
+    /// the tape a program needs; moving the pointer alone touches nothing.
+    ///
+    /// ```
+    /// let program = brainfuck::compile( ">+>>+>>" ).unwrap();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 8 ).track_extent();
+    /// engine.run().unwrap();
+    /// assert_eq!( engine.extent(), Some( 1..=3 ) );
+    /// ```
+    pub fn extent( &self ) -> Option< RangeInclusive< isize > > {
+        self.extent.flatten().map( | ( low, high ) | low..=high )
+    }
+
+    pub fn pc( &self ) -> usize {
+        self.pc
+    }
+
+    /// The position of the data pointer, which is only negative on a
+    /// bidirectional tape.
+    pub fn loc( &self ) -> isize {
+        self.loc as isize - self.origin as isize
+    }
+
+    /// The cell at position `loc`, counting as `Engine::loc` does.
+    pub fn cell( &self, loc: isize ) -> Option< C > {
+        let physical = usize::try_from( self.origin as isize + loc ).ok()?;
+        self.memory.get( physical ).copied()
+    }
+
+    /// The cell under the data pointer, for a custom opcode.
+    pub fn current_cell( &self ) -> C {
+        self.memory[ self.loc ]
+    }
+
+    /// Sets the cell under the data pointer, for a custom opcode, which is
+    /// taken to write that cell and no other.
+    pub fn set_current_cell( &mut self, cell: C ) {
+        self.memory[ self.loc ] = cell;
+    }
+
+    /// Moves the pc on to the next instruction, as a custom opcode must
+    /// unless it fails.
+    pub fn advance( &mut self ) {
+        self.pc += 1;
+    }
+
+    /// All the cells allocated so far. Cell 0 is at `origin`.
+    pub fn tape( &self ) -> &[ C ] {
+        &self.memory
+    }
+
+    pub fn origin( &self ) -> usize {
+        self.origin
+    }
+
+    /// A 64-bit FNV-1a hash of the current tape, for checking that two
+    /// runs left the same cells behind. It covers the cells from the first
+    /// to the last that is not zero and where they start, relative to cell
+    /// 0, so it does not depend on how far a growing tape happened to grow.
+    ///
+    /// ```
+    /// use brainfuck::{Compiler, Engine};
+    ///
+    /// let checksum = | src: &str, cells: usize | {
+    ///     let mut engine = Engine::< i8 >::new( &Compiler::new().compile( src ).unwrap(), cells );
+    ///     engine.run().unwrap();
+    ///     engine.tape_checksum()
+    /// };
+    /// assert_eq!( checksum( ">++", 4 ), checksum( "+[->++<]", 100 ) );
+    /// assert_ne!( checksum( ">++", 4 ), checksum( "++", 4 ) );
+    /// ```
+    pub fn tape_checksum( &self ) -> u64 {
+        let used = self.memory.iter().position( | &cell | cell != C::ZERO ).zip( self.memory.iter().rposition( | &cell | cell != C::ZERO ) );
+        let ( start, cells ) = match used {
+            Some( ( first, last ) ) => ( first as i64 - self.origin as i64, &self.memory[ first..=last ] ),
+            None => ( 0, &self.memory[ ..0 ] ),
+        };
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for value in std::iter::once( start ).chain( cells.iter().map( | cell | cell.to_i64() ) ) {
+            for byte in value.to_le_bytes() {
+                hash = ( hash ^ byte as u64 ).wrapping_mul( 0x0100_0000_01b3 );
+            }
+        }
+        hash
+    }
+
+    /// Keeps a copy of the tape as it is now, for `tape_diff` to compare
+    /// with later.
+    pub fn mark_tape( &mut self ) {
+        self.marked = Some( ( self.memory.clone(), self.origin ) );
+    }
+
+    /// The cells that differ from the copy kept by `mark_tape`, or from
+    /// zero if there is none, as each one's position, old value and new
+    /// value, counting positions as `Engine::loc` does. Cells that a
+    /// growing tape has added since count as having been zero.
+    ///
+    /// ```
+    /// let program = brainfuck::compile( "+>>>>>+" ).unwrap();
+    /// let mut engine = brainfuck::Engine::< i8 >::new( &program, 8 ).fill( &[ 3 ] );
+    /// engine.mark_tape();
+    /// engine.run().unwrap();
+    /// assert_eq!( engine.tape_diff(), [ ( 0, 3, 4 ), ( 5, 3, 4 ) ] );
+    /// ```
+    pub fn tape_diff( &self ) -> Vec< ( isize, C, C ) > {
+        let ( before, origin ) = match &self.marked {
+            Some( ( memory, origin ) ) => ( &memory[ .. ], *origin ),
+            None => ( &self.memory[ ..0 ], 0 ),
+        };
+        let old = | loc: isize | usize::try_from( origin as isize + loc ).ok().and_then( | at | before.get( at ) ).copied().unwrap_or( C::ZERO );
+        let start = -( origin.max( self.origin ) as isize );
+        let end = ( before.len() as isize - origin as isize ).max( self.memory.len() as isize - self.origin as isize );
+        ( start..end ).filter_map( | loc | {
+            let new = self.cell( loc ).unwrap_or( C::ZERO );
+            ( old( loc ) != new ).then_some( ( loc, old( loc ), new ) )
+        } ).collect()
+    }
+
+    /// Which of the two tapes that `%` switches between is current, 0 or 1.
+    /// `tape`, `loc` and the rest always describe the current one.
+    pub fn active_tape( &self ) -> usize {
+        self.tape_index
+    }
+
+    pub fn is_halted( &self ) -> bool {
+        self.halted
+    }
+
+    /// The name of the opcode at `pc`, for display.
+    pub fn opcode_name_at( &self, pc: usize ) -> Option< &'static str > {
+        self.ops.get( pc ).map( | op | op.name() )
+    }
+
+    /// The function pointer at `pc`, which `opcode_name` can name.
+    pub fn opcode_at( &self, pc: usize ) -> Option< OpCode< C > > {
+        self.program.get( pc ).copied()
+    }
+
+    /// The number of opcodes dispatched so far.
+    pub fn steps( &self ) -> u64 {
+        self.steps
+    }
+
+    /// The source position of the current instruction, if known.
+    pub fn source_pos( &self ) -> Option< SourcePos > {
+        self.source_map.get( self.pc ).copied()
+    }
+
+    //  A failure to write the program's output, where a closed pipe means
+    //  that nothing is reading it any more.
+    pub(crate) fn output_error( &self, err: std::io::Error ) -> BfError {
+        if err.kind() == std::io::ErrorKind::BrokenPipe {
+            BfError::OutputClosed { pc: self.pc, source: self.source_pos() }
+        } else {
+            BfError::Io( err )
+        }
+    }
+
+    /// The index of the first instruction compiled from the command at
+    /// `pos`, if any. A command folded into a run has none of its own.
+    pub fn pc_at( &self, pos: SourcePos ) -> Option< usize > {
+        self.source_map.iter().position( | &p | p == pos )
+    }
+
+    /// Runs the program until it halts or fails, flushing the output either way.
+    pub fn run( &mut self ) -> Result< (), BfError > {
+        let result = self.dispatch();
+        let flushed = self.output.flush();
+        self.flush_trace();
+        result?;
+        flushed.map_err( | err | self.output_error( err ) )
+    }
+
+    /// Runs the program like `run`, but reports reaching the step limit or
+    /// being interrupted in the summary rather than as an error.
+    ///
+    /// ```
+    /// use brainfuck::{Compiler, Engine, HaltReason};
+    ///
+    /// let program = Compiler::new().opt_level( 0 ).compile( "+++" ).unwrap();
+    /// let summary = Engine::< i8 >::new( &program, 4 ).run_summary().unwrap();
+    /// assert_eq!( ( summary.steps, summary.pc, summary.reason ), ( 4, 3, HaltReason::Halted ) );
+    /// let summary = Engine::< i8 >::new( &program, 4 ).max_steps( 2 ).run_summary().unwrap();
+    /// assert_eq!( ( summary.steps, summary.pc, summary.reason ), ( 2, 2, HaltReason::StepLimit { limit: 2 } ) );
+    /// ```
+    pub fn run_summary( &mut self ) -> Result< RunSummary, BfError > {
+        let result = self.run();
+        self.summarize( result )
+    }
+
+    /// Loads `program` and runs it afresh under every limit of `config`,
+    /// failing with `StepLimitExceeded`, `OutputLimitExceeded` or
+    /// `TapeLimitExceeded` for whichever of them it reaches first. The
+    /// tape grows to the right as needed, up to its limit, and a move off
+    /// its left end fails as usual. The limits stay set afterwards.
+    ///
+    /// ```
+    /// use brainfuck::{BfError, Engine, SandboxConfig};
+    ///
+    /// let program = brainfuck::compile( "+[>+]" ).unwrap();
+    /// let mut engine = Engine::< i8 >::new( &program, 16 );
+    /// let config = SandboxConfig { max_cells: 100, ..SandboxConfig::default() };
+    /// assert!( matches!( engine.run_sandboxed( &program, &config ), Err( BfError::TapeLimitExceeded { limit: 100, .. } ) ) );
+    /// ```
+    pub fn run_sandboxed( &mut self, program: &Program, config: &SandboxConfig ) -> Result< RunSummary, BfError > {
+        self.max_steps = Some( config.max_steps );
+        self.output_limit = Some( ( config.max_output, 0 ) );
+        self.max_cells = Some( config.max_cells.max( 1 ) );
+        self.grow_left = false;
+        self.wraps = false;
+        self.load( program );
+        self.reset();
+        self.memory.truncate( config.max_cells.max( 1 ) );
+        self.start = self.start.min( self.memory.len() - 1 );
+        self.loc = self.start;
+        let result = self.run().map_err( | err | match err {
+            //  Off the end of a tape that can grow no further.
+            BfError::PointerOverflow { pc, source, .. } if self.memory.len() >= config.max_cells =>
+                BfError::TapeLimitExceeded { limit: config.max_cells, pc, source },
+            err => err,
+        } );
+        self.summarize( result )?.into_result()
+    }
+
+    /// Turns the result of a run, by whichever means, into a summary of
+    /// where the engine now is. Errors other than reaching the step limit
+    /// and being interrupted are passed on.
+    pub fn summarize( &self, result: Result< (), BfError > ) -> Result< RunSummary, BfError > {
+        let reason = match result {
+            Ok(()) if self.halted => HaltReason::Halted,
+            Ok(()) => HaltReason::Stopped,
+            Err( BfError::StepLimitExceeded { limit, .. } ) => HaltReason::StepLimit { limit },
+            Err( BfError::Interrupted { .. } ) => HaltReason::Interrupted,
+            Err( e ) => return Err( e ),
+        };
+        Ok( RunSummary { steps: self.steps, pc: self.pc, source: self.source_pos(), reason } )
+    }
+
+    //  Limits and tracing are only checked in a separate copy of the loop,
+    //  keeping the plain loop as tight as possible. That includes logging
+    //  each step, which is only asked about once a run.
+    fn dispatch( &mut self ) -> Result< (), BfError > {
+        if logging::tracing() || self.max_steps.is_some() || self.interrupt.is_some() || self.trace.is_some() || self.profile.is_some() || self.progress.is_some() || self.on_write.is_some() || self.heatmap.is_some() || self.extent.is_some() || self.loop_budget.is_some() || self.protect.is_some() || self.cost.is_some() {
+            return self.dispatch_checked();
+        }
+        while !self.halted {
+            self.steps += 1;
+            let opc: OpCode< C > = self.program[ self.pc ];
+            opc( self )?;
+        }
+        Ok(())
+    }
+
+    fn dispatch_checked( &mut self ) -> Result< (), BfError > {
+        while !self.halted {
+            self.step_checked()?;
+        }
+        Ok(())
+    }
+
+    //  Dispatches a single opcode, honouring the limits and instrumentation.
+    fn step_checked( &mut self ) -> Result< (), BfError > {
+        if let Some( limit ) = self.max_steps {
+            if self.steps >= limit {
+                return Err( BfError::StepLimitExceeded { limit, pc: self.pc, source: self.source_pos() } );
+            }
+        }
+        if let Some( flag ) = self.interrupt {
+            if self.steps.is_multiple_of( 4096 ) && flag.load( Ordering::Relaxed ) {
+                return Err( BfError::Interrupted { pc: self.pc, source: self.source_pos() } );
+            }
+        }
+        if self.trace.is_some() {
+            self.trace_step();
+        }
+        if logging::tracing() {
+            log_trace!( "pc={} {} loc={} cell={}", self.pc, self.ops[ self.pc ].name(), self.loc(), self.show( self.memory[ self.loc ] ) );
+        }
+        if let Some( profile ) = &mut self.profile {
+            profile[ self.pc ] += 1;
+        }
+        if let Some( cost ) = &mut self.cost {
+            *cost += self.ops[ self.pc ].cost();
+        }
+        if self.loop_budget.is_some() {
+            self.count_iteration()?;
+        }
+        self.check_protected()?;
+        self.steps += 1;
+        let watched = self.on_write.is_some() || self.heatmap.is_some() || self.extent.is_some();
+        let before = watched.then( || ( self.ops[ self.pc ], self.operands[ self.pc ], self.memory[ self.loc ], self.loc() ) );
+        let opc: OpCode< C > = self.program[ self.pc ];
+        let result = opc( self );
+        if let ( Some( ( op, operand, cell, from ) ), Ok(()) ) = ( before, &result ) {
+            self.record_access( op, operand, cell, from );
+        }
+        if let Some( ( interval, report ) ) = &mut self.progress {
+            if self.steps.is_multiple_of( *interval ) {
+                report( self.steps, self.pc );
+            }
+        }
+        result
+    }
+
+    //  Counts the iteration that the OPEN or CLOSE at the pc is about to
+    //  start, failing if that is one more than the loop budget allows.
+    fn count_iteration( &mut self ) -> Result< (), BfError > {
+        let Some( ( budget, iterations ) ) = &mut self.loop_budget else { return Ok(()) };
+        if self.memory[ self.loc ] == C::ZERO {
+            return Ok(());
+        }
+        let body = match self.ops[ self.pc ] {
+            Op::Open => {
+                iterations[ self.pc + 1 ] = 0;
+                self.pc + 1
+            },
+            Op::Close => self.operands[ self.pc ],
+            _ => return Ok(()),
+        };
+        iterations[ body ] += 1;
+        if iterations[ body ] > *budget {
+            let open = body.saturating_sub( 1 );
+            return Err( BfError::LoopBudgetExceeded { budget: *budget, pc: open, source: self.source_map.get( open ).copied() } );
+        }
+        Ok(())
+    }
+
+    /// Dispatches a single opcode, unless the program has already halted.
+    pub fn step( &mut self ) -> Result< StepResult, BfError > {
+        if !self.halted {
+            let result = self.step_checked();
+            if let Err( e ) = self.output.flush() {
+                log_warn!( "could not flush the output after a step: {}", e );
+            }
+            if self.halted {
+                self.flush_trace();
+            }
+            result?;
+        }
+        Ok( if self.halted { StepResult::Halted } else { StepResult::Running } )
+    }
+
+    /// Dispatches at most `max_steps` opcodes, stopping early if the
+    /// program halts, so that a host can share a thread between a program
+    /// and other work by calling it again until it reports `Halted`. The
+    /// output is flushed before it returns, and the limits, tracing and
+    /// profiling all carry on across calls as in a single run.
+    ///
+    /// ```
+    /// use brainfuck::{Engine, StepResult};
+    ///
+    /// let mut engine = Engine::< i8 >::new( &brainfuck::compile( "+[]" ).unwrap(), 4 );
+    /// assert_eq!( engine.run_budgeted( 100 ).unwrap(), StepResult::Running );
+    /// assert_eq!( engine.steps(), 100 );
+    /// ```
+    pub fn run_budgeted( &mut self, max_steps: usize ) -> Result< StepResult, BfError > {
+        let mut result = Ok(());
+        for _ in 0..max_steps {
+            if self.halted {
+                break;
+            }
+            result = self.step_checked();
+            if result.is_err() {
+                break;
+            }
+        }
+        let flushed = self.output.flush();
+        if self.halted {
+            self.flush_trace();
+        }
+        result?;
+        flushed.map_err( | err | self.output_error( err ) )?;
+        Ok( if self.halted { StepResult::Halted } else { StepResult::Running } )
+    }
+
+    /// Runs the program one opcode at a time as the iterator is consumed,
+    /// so that a tool can stop whenever it likes. The iteration ends after
+    /// HALT, or after the first error.
+    ///
+    /// ```
+    /// use brainfuck::Engine;
+    ///
+    /// let mut engine = Engine::< i8 >::new( &brainfuck::compile( "+>-" ).unwrap(), 4 ).with_io( std::io::empty(), std::io::sink() );
+    /// let names: Vec< &str > = engine.run_iter().map( | step | step.unwrap().opcode ).collect();
+    /// assert_eq!( names, [ "INCR", "RIGHT", "DECR", "HALT" ] );
+    /// ```
+    pub fn run_iter( &mut self ) -> impl Iterator< Item = Result< ExecutedStep< C >, BfError > > + use< '_, 'io, C > {
+        let mut failed = false;
+        std::iter::from_fn( move || {
+            if self.halted || failed {
+                return None;
+            }
+            let pc = self.pc;
+            let opcode = self.ops[ pc ].name();
+            let result = self.step().map( | _ | ExecutedStep { pc, opcode, loc: self.loc(), cell: self.memory[ self.loc ] } );
+            failed = result.is_err();
+            Some( result )
+        } )
+    }
+
+    //  Extends the tape so that `loc` is on it, returning false if that
+    //  would take it past `max_cells`.
+    fn reach( &mut self, loc: usize ) -> bool {
+        match self.max_cells {
+            Some( max ) if loc < max => {
+                if loc >= self.memory.len() {
+                    let len = ( self.memory.len() * 2 ).max( loc + 1 ).min( max );
+                    self.memory.resize( len, C::ZERO );
+                }
+                true
+            },
+            _ => loc < self.memory.len(),
+        }
+    }
+
+    //  Adds cells on the left so that the pointer can move `n` cells left,
+    //  returning false if that is not allowed.
+    fn reach_left( &mut self, n: usize ) -> bool {
+        if n <= self.loc {
+            return true;
+        }
+        let max = match self.max_cells {
+            Some( max ) if self.grow_left => max,
+            _ => return false,
+        };
+        let needed = n - self.loc;
+        if needed > max.saturating_sub( self.memory.len() ) {
+            return false;
+        }
+        let extra = self.memory.len().max( needed ).min( max - self.memory.len() );
+        self.memory.splice( 0..0, std::iter::repeat_n( C::ZERO, extra ) );
+        self.loc += extra;
+        self.origin += extra;
+        true
+    }
+
+    /// The cells within `radius` of the data pointer, on one line such as
+    /// `loc=2: 0 0 [65] 0 0`, with the current cell in brackets.
+    pub fn tape_window( &self, radius: isize ) -> String {
+        let radius = radius.unsigned_abs();
+        let start = self.loc.saturating_sub( radius );
+        let end = self.loc.saturating_add( radius ).saturating_add( 1 ).min( self.memory.len() );
+        format!( "loc={}: {}", self.loc(), format_tape( &self.memory, start..end, self.dump_format, self.loc, self.unsigned ) )
+    }
+
+    //  Tells `on_write`, the heatmap and the extent about the cells that `op`
+    //  has just read and written, given the current cell and its address before.
+    fn record_access( &mut self, op: Op, operand: usize, cell: C, from: isize ) {
+        let writes = self.writes( op, operand, cell, from );
+        let mut reads = Vec::new();
+        match op {
+            Op::MulAdd | Op::Move => {
+                reads.push( from );
+                reads.extend( writes.iter().filter( | &&at | at != from ) );
+            },
+            Op::AddAt => reads.extend( &writes ),
+            Op::Incr | Op::Decr | Op::IncrSat | Op::DecrSat | Op::IncrN | Op::DecrN | Op::IncrNSat | Op::DecrNSat |
+            Op::IncrChk | Op::DecrChk | Op::IncrNChk | Op::DecrNChk |
+            Op::Open | Op::Close | Op::Put | Op::PutNum | Op::Assert | Op::Custom => reads.push( from ),
+            Op::ScanRight => reads.extend( from..=self.loc() ),
+            Op::ScanLeft => reads.extend( self.loc()..=from ),
+            _ => {},
+        }
+        if let Some( extent ) = &mut self.extent {
+            for &at in reads.iter().chain( &writes ) {
+                let ( low, high ) = extent.get_or_insert( ( at, at ) );
+                *low = ( *low ).min( at );
+                *high = ( *high ).max( at );
+            }
+        }
+        if let Some( heatmap ) = &mut self.heatmap {
+            for &at in &reads {
+                heatmap.entry( at ).or_default().0 += 1;
+            }
+            for &at in &writes {
+                heatmap.entry( at ).or_default().1 += 1;
+            }
+        }
+        if let Some( hook ) = &mut self.on_write {
+            for &at in &writes {
+                let t = self.origin.saturating_add_signed( at );
+                hook( t, self.memory[ t ] );
+            }
+        }
+    }
+
+    //  The cells that `op` is about to write, which are known before it
+    //  runs from the current cell and its address.
+    fn writes( &self, op: Op, operand: usize, cell: C, from: isize ) -> Vec< isize > {
+        match op {
+            Op::MulAdd | Op::Move if cell != C::ZERO => {
+                let mut writes: Vec< isize > = self.muladds[ operand ].iter().map( | &( offset, _ ) | from + offset ).collect();
+                writes.push( from );
+                writes
+            },
+            Op::Incr | Op::Decr | Op::IncrSat | Op::DecrSat | Op::IncrN | Op::DecrN | Op::IncrNSat | Op::DecrNSat |
+            Op::IncrChk | Op::DecrChk | Op::IncrNChk | Op::DecrNChk |
+            Op::SetZero | Op::SetConst | Op::Get | Op::Rand | Op::Custom => vec![ from ],
+            Op::AddAt => vec![ from + self.muladds[ operand ][ 0 ].0 ],
+            _ => Vec::new(),
+        }
+    }
+
+    //  Fails if the opcode at the pc would write to a protected cell.
+    fn check_protected( &self ) -> Result< (), BfError > {
+        let Some( range ) = &self.protect else { return Ok(()) };
+        let writes = self.writes( self.ops[ self.pc ], self.operands[ self.pc ], self.memory[ self.loc ], self.loc() );
+        match writes.into_iter().filter_map( | at | usize::try_from( at ).ok() ).find( | at | range.contains( at ) ) {
+            Some( addr ) => Err( BfError::WriteProtected { addr, pc: self.pc, source: self.source_pos() } ),
+            None => Ok(()),
+        }
+    }
+
+    fn trace_step( &mut self ) {
+        let name = self.ops[ self.pc ].name();
+        let cell = self.show( self.memory[ self.loc ] );
+        let line = if self.trace_json {
+            let fields = Json::object( [
+                ( "step", ( self.steps + 1 ).into() ),
+                ( "pc", self.pc.into() ),
+                ( "op", name.into() ),
+                ( "loc", Json::Number( self.loc() as f64 ) ),
+                ( "cell", cell.value().into() ),
+            ] );
+            format!( "{}\n", fields )
+        } else {
+            format!( "pc={:<6} {:<10} loc={:<6} cell={}\n", self.pc, name, self.loc(), cell )
+        };
+        //  Keep the program's output in step with the trace.
+        let _ = self.output.flush();
+        if let Some( trace ) = &mut self.trace {
+            if let Err( e ) = trace.write_all( line.as_bytes() ) {
+                log_warn!( "could not write the trace: {}", e );
+            }
+        }
+    }
+
+    fn flush_trace( &mut self ) {
+        if let Some( trace ) = &mut self.trace {
+            if let Err( e ) = trace.flush() {
+                log_warn!( "could not flush the trace: {}", e );
+            }
+        }
+    }
+
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn INCR< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.memory[ e.loc ] = e.memory[ e.loc ].wrapping_add_n( 1 );
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn DECR< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.memory[ e.loc ] = e.memory[ e.loc ].wrapping_sub_n( 1 );
+    e.pc += 1;
+    Ok(())
+}
+
+//  Saturating alternatives to INCR/DECR, installed by --no-wrap.
+#[allow(non_snake_case)]
+pub(crate) fn INCR_SAT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.memory[ e.loc ] = e.memory[ e.loc ].saturating_add_n( 1 );
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn DECR_SAT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.memory[ e.loc ] = e.memory[ e.loc ].saturating_sub_n( 1 );
+    e.pc += 1;
+    Ok(())
+}
+
+//  Checked alternatives to INCR/DECR, installed by --overflow=error.
+#[allow(non_snake_case)]
+pub(crate) fn INCR_CHK< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.memory[ e.loc ] = e.memory[ e.loc ].checked_add_n( 1 ).ok_or( BfError::CellOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } )?;
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn DECR_CHK< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.memory[ e.loc ] = e.memory[ e.loc ].checked_sub_n( 1 ).ok_or( BfError::CellOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } )?;
+    e.pc += 1;
+    Ok(())
+}
+
+//  The folded forms of INCR and DECR take their count from their operand.
+#[allow(non_snake_case)]
+pub(crate) fn INCR_N< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    e.memory[ e.loc ] = e.memory[ e.loc ].wrapping_add_n( n );
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn DECR_N< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    e.memory[ e.loc ] = e.memory[ e.loc ].wrapping_sub_n( n );
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn INCR_N_SAT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    e.memory[ e.loc ] = e.memory[ e.loc ].saturating_add_n( n );
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn DECR_N_SAT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    e.memory[ e.loc ] = e.memory[ e.loc ].saturating_sub_n( n );
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn INCR_N_CHK< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    e.memory[ e.loc ] = e.memory[ e.loc ].checked_add_n( n ).ok_or( BfError::CellOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } )?;
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn DECR_N_CHK< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    e.memory[ e.loc ] = e.memory[ e.loc ].checked_sub_n( n ).ok_or( BfError::CellOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } )?;
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn SET_ZERO< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.memory[ e.loc ] = C::ZERO;
+    e.pc += 1;
+    Ok(())
+}
+
+//  Stores its operand, wrapped to the width of the cell.
+#[allow(non_snake_case)]
+pub(crate) fn SET_CONST< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.memory[ e.loc ] = C::ZERO.wrapping_add_n( e.operands[ e.pc ] );
+    e.pc += 1;
+    Ok(())
+}
+
+//  The physical index of the cell `offset` cells from the current one,
+//  growing or wrapping around the tape as a move would, but without
+//  moving the pointer.
+fn offset_cell< C: Cell >( e : &mut Engine< C >, offset: isize ) -> Result< usize, BfError > {
+    if offset < 0 && !e.reach_left( offset.unsigned_abs() ) && !e.wraps {
+        return Err( BfError::PointerUnderflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+    }
+    let target = e.loc as isize + offset;
+    if target >= 0 && e.reach( target as usize ) {
+        Ok( target as usize )
+    } else if e.wraps {
+        Ok( target.rem_euclid( e.memory.len() as isize ) as usize )
+    } else {
+        Err( BfError::PointerOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } )
+    }
+}
+
+//  The operand indexes the (offset, factor) pairs of the targets.
+#[allow(non_snake_case)]
+pub(crate) fn MULADD< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let k = e.operands[ e.pc ];
+    let v = e.memory[ e.loc ];
+    if v != C::ZERO {
+        for i in 0..e.muladds[ k ].len() {
+            let ( offset, factor ) = e.muladds[ k ][ i ];
+            let t = offset_cell( e, offset )?;
+            e.memory[ t ] = e.memory[ t ].wrapping_mul_add( v, factor );
+        }
+        e.memory[ e.loc ] = C::ZERO;
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+//  A MULADD with a single target and a factor of 1, such as `[->+<]`,
+//  which moves the current cell onto the target. The operand indexes the
+//  (offset, 1) pair in the table of MULADD targets.
+#[allow(non_snake_case)]
+pub(crate) fn MOVE< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let v = e.memory[ e.loc ];
+    if v != C::ZERO {
+        let t = offset_cell( e, e.muladds[ e.operands[ e.pc ] ][ 0 ].0 )?;
+        e.memory[ t ] = e.memory[ t ].wrapping_add_cell( v );
+        e.memory[ e.loc ] = C::ZERO;
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+//  The operand indexes a single (offset, amount) pair, kept in the same
+//  table as the targets of the MULADDs.
+#[allow(non_snake_case)]
+pub(crate) fn ADD_AT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let ( offset, amount ) = e.muladds[ e.operands[ e.pc ] ][ 0 ];
+    let t = offset_cell( e, offset )?;
+    e.memory[ t ] = e.memory[ t ].wrapping_mul_add( C::from_byte( 1 ), amount );
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn RIGHT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    if e.loc + 1 >= e.memory.len() {
+        if !e.wraps {
+            return Err( BfError::PointerOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+        }
+        e.loc = 0;
+    } else {
+        e.loc += 1;
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn LEFT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    if e.loc == 0 {
+        if !e.wraps {
+            return Err( BfError::PointerUnderflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+        }
+        e.loc = e.memory.len();
+    }
+    e.loc -= 1;
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn RIGHT_N< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    if n >= e.memory.len() - e.loc {
+        if !e.wraps {
+            return Err( BfError::PointerOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+        }
+        e.loc = ( e.loc + n % e.memory.len() ) % e.memory.len();
+    } else {
+        e.loc += n;
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn LEFT_N< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    if n > e.loc {
+        if !e.wraps {
+            return Err( BfError::PointerUnderflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+        }
+        e.loc = ( e.loc + e.memory.len() - n % e.memory.len() ) % e.memory.len();
+    } else {
+        e.loc -= n;
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+//  Versions of the moves for a growing tape, installed by
+//  `Engine::tape_mode`.
+#[allow(non_snake_case)]
+pub(crate) fn RIGHT_GROW< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    if !e.reach( e.loc + 1 ) {
+        return Err( BfError::PointerOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+    }
+    e.loc += 1;
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn RIGHT_N_GROW< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    if !e.reach( e.loc.saturating_add( n ) ) {
+        return Err( BfError::PointerOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+    }
+    e.loc += n;
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn LEFT_GROW< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    if !e.reach_left( 1 ) {
+        return Err( BfError::PointerUnderflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+    }
+    e.loc -= 1;
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn LEFT_N_GROW< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let n = e.operands[ e.pc ];
+    if !e.reach_left( n ) {
+        return Err( BfError::PointerUnderflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+    }
+    e.loc -= n;
+    e.pc += 1;
+    Ok(())
+}
+
+//  The scans search the tape directly rather than stepping a cell at a
+//  time. Any cells that a growing tape has yet to add are zero, so a scan
+//  that runs off the end stops on the first cell past it. On a wrapping
+//  tape the search goes on from the other end, and if no cell is zero the
+//  scan stays where it is, as the loop it replaces would never end.
+#[allow(non_snake_case)]
+pub(crate) fn SCAN_RIGHT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    match e.memory[ e.loc.. ].iter().position( | &cell | cell == C::ZERO ) {
+        Some( n ) => e.loc += n,
+        None if e.wraps => match e.memory[ ..e.loc ].iter().position( | &cell | cell == C::ZERO ) {
+            Some( n ) => e.loc = n,
+            None => return Ok(()),
+        },
+        None => {
+            let end = e.memory.len();
+            if !e.reach( end ) {
+                return Err( BfError::PointerOverflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+            }
+            e.loc = end;
+        },
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn SCAN_LEFT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    match e.memory[ ..=e.loc ].iter().rposition( | &cell | cell == C::ZERO ) {
+        Some( n ) => e.loc = n,
+        None if e.wraps => match e.memory[ e.loc + 1.. ].iter().rposition( | &cell | cell == C::ZERO ) {
+            Some( n ) => e.loc += 1 + n,
+            None => return Ok(()),
+        },
+        None => {
+            let n = e.loc + 1;
+            if !e.reach_left( n ) {
+                return Err( BfError::PointerUnderflow { pc: e.pc, loc: e.loc, source: e.source_pos() } );
+            }
+            e.loc -= n;
+        },
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+//  The operand of the jump at the pc, which loading and compiling both
+//  check is inside the program. Checking again costs a compare on each
+//  jump taken, and turns a bug that would corrupt the pc into an error.
+fn jump_target< C: Cell >( e : &Engine< C > ) -> Result< usize, BfError > {
+    let target = e.operands[ e.pc ];
+    if target < e.program.len() { Ok( target ) } else { Err( BfError::CorruptJumpTable { pc: e.pc } ) }
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn OPEN< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    if e.memory[ e.loc ] == C::ZERO {
+        e.pc = jump_target( e )?;
+    } else {
+        e.pc += 1;
+    }
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn CLOSE< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    if e.memory[ e.loc ] != C::ZERO {
+        e.pc = jump_target( e )?;
+    } else {
+        e.pc += 1;
+    }
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn PUT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let byte = e.memory[ e.loc ].to_byte();
+    if let Some( endian ) = e.wide_io {
+        let bytes = e.memory[ e.loc ].to_bytes( endian );
+        e.count_output( bytes.len() )?;
+        e.output.write_all( &bytes ).map_err( | err | e.output_error( err ) )?;
+    } else if e.ascii_only && !matches!( byte, b'\n' | b'\t' | 0x20..=0x7e ) {
+        e.count_output( 4 )?;
+        write!( e.output, "\\x{:02X}", byte ).map_err( | err | e.output_error( err ) )?;
+    } else {
+        e.count_output( 1 )?;
+        e.output.write_all( &[ byte ] ).map_err( | err | e.output_error( err ) )?;
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+//  Writes the cell in decimal, read as unsigned if the engine is, and a
+//  space to keep the numbers apart.
+#[allow(non_snake_case)]
+pub(crate) fn PUT_NUM< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let text = format!( "{} ", e.show( e.memory[ e.loc ] ) );
+    e.count_output( text.len() )?;
+    e.output.write_all( text.as_bytes() ).map_err( | err | e.output_error( err ) )?;
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn GET< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    //  So that a prompt is seen before the program waits for its answer.
+    if !e.output.buffer().is_empty() {
+        e.output.flush().map_err( | err | e.output_error( err ) )?;
+    }
+    if let Some( endian ) = e.wide_io {
+        return get_wide( e, endian );
+    }
+    let mut buf = [0; 1];
+    let mut read = e.input.read_exact( &mut buf );
+    while e.strip_cr && buf[ 0 ] == b'\r' && read.is_ok() {
+        read = e.input.read_exact( &mut buf );
+    }
+    store_input( e, read.map( | () | C::from_byte( buf[ 0 ] ) ) )
+}
+
+//  Reads every byte of the cell for `,` under `Engine::wide_io`.
+fn get_wide< C: Cell >( e : &mut Engine< C >, endian: Endian ) -> Result< (), BfError > {
+    let mut buf = vec![ 0; std::mem::size_of::< C >() ];
+    let read = e.input.read_exact( &mut buf );
+    store_input( e, read.map( | () | C::from_bytes( &buf, endian ) ) )
+}
+
+//  Stores what `,` read, or what the EOF policy says at end of input.
+fn store_input< C: Cell >( e : &mut Engine< C >, read: std::io::Result< C > ) -> Result< (), BfError > {
+    match read {
+        Ok( cell ) => e.memory[ e.loc ] = cell,
+        Err( err ) if err.kind() == std::io::ErrorKind::UnexpectedEof => match e.eof {
+            EofPolicy::NoChange => {},
+            EofPolicy::Zero => e.memory[ e.loc ] = C::ZERO,
+            EofPolicy::NegOne => e.memory[ e.loc ] = C::ZERO.wrapping_sub_n( 1 ),
+        },
+        Err( err ) => return Err( err.into() ),
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn RAND< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.rng ^= e.rng << 13;
+    e.rng ^= e.rng >> 7;
+    e.rng ^= e.rng << 17;
+    e.memory[ e.loc ] = C::from_byte( ( e.rng >> 56 ) as u8 );
+    e.pc += 1;
+    Ok(())
+}
+
+//  Compares the cell with the operand as SET_CONST would store it, so that
+//  `=255` matches an 8-bit cell holding -1.
+#[allow(non_snake_case)]
+pub(crate) fn ASSERT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let expected = C::ZERO.wrapping_add_n( e.operands[ e.pc ] );
+    let actual = e.memory[ e.loc ];
+    if actual != expected {
+        return Err( BfError::AssertionFailed { pc: e.pc, expected: expected.to_i64(), actual: actual.to_i64(), source: e.source_pos() } );
+    }
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn SWITCH_TAPE< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    if e.spare.0.is_empty() {
+        e.spare.0 = vec![ C::ZERO; e.memory.len() ];
+    }
+    std::mem::swap( &mut e.memory, &mut e.spare.0 );
+    std::mem::swap( &mut e.loc, &mut e.spare.1 );
+    std::mem::swap( &mut e.origin, &mut e.spare.2 );
+    e.tape_index ^= 1;
+    e.pc += 1;
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn HALT< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    e.halted = true;
+    if let Some( hook ) = e.on_halt.take() {
+        e.output.flush().map_err( | err | e.output_error( err ) )?;
+        hook( e );
+    }
+    Ok(())
+}
+
+//  Runs the opcode registered with `Compiler::register_op` that the
+//  operand indexes, if it was registered for cells of this type.
+#[allow(non_snake_case)]
+pub(crate) fn CUSTOM< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    match e.customs.get( e.operands[ e.pc ] ).copied().flatten() {
+        Some( opcode ) => opcode( e ),
+        None => Err( BfError::IllegalInstruction { pc: e.pc } ),
+    }
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn TRAP< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    Err( BfError::IllegalInstruction { pc: e.pc } )
+}
+
+//  Prints the cells within four of the pointer, with the current one in
+//  brackets, for example `loc=2: 0 0 [65] 0 0 0 0`.
+#[allow(non_snake_case)]
+pub(crate) fn DUMP< C: Cell >( e : &mut Engine< C > ) -> Result< (), BfError > {
+    let mut line = e.tape_window( 4 );
+    line.push( '\n' );
+    //  Keep the program's output in step with the dump.
+    e.output.flush().map_err( | err | e.output_error( err ) )?;
+    e.debug_output.write_all( line.as_bytes() )?;
+    e.pc += 1;
+    Ok(())
+}
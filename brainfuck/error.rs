@@ -0,0 +1,171 @@
+/*
+    The errors that compiling or running a Brainfuck program can produce.
+*/
+
+use std::fmt;
+
+use crate::compiler::SourcePos;
+
+#[derive(Debug)]
+pub enum BfError {
+    /// `position` is a character offset into the source, or an instruction
+    /// index for instructions that did not come from source. In the latter
+    /// case `source` is `None`.
+    UnmatchedOpen { position: usize, source: Option< SourcePos > },
+    UnmatchedClose { position: usize, source: Option< SourcePos > },
+    /// A character that is not a command, in a strict compile.
+    UnexpectedCharacter { ch: char, position: usize, source: Option< SourcePos > },
+    /// A byte that is not part of valid UTF-8, in a strict compile.
+    UnexpectedByte { byte: u8, position: usize, source: Option< SourcePos > },
+    PointerUnderflow { pc: usize, loc: usize, source: Option< SourcePos > },
+    PointerOverflow { pc: usize, loc: usize, source: Option< SourcePos > },
+    /// The `+` or `-` at `pc` took cell `loc` past the range of its type,
+    /// under `Overflow::Error`.
+    CellOverflow { pc: usize, loc: usize, source: Option< SourcePos > },
+    /// The `=` at `pc` found `actual` in the current cell rather than
+    /// `expected`.
+    AssertionFailed { pc: usize, expected: i64, actual: i64, source: Option< SourcePos > },
+    StepLimitExceeded { limit: u64, pc: usize, source: Option< SourcePos > },
+    /// The output command at `pc` would have written more than `limit`
+    /// bytes in all.
+    OutputLimitExceeded { limit: u64, pc: usize, source: Option< SourcePos > },
+    /// The opcode at `pc` would have grown the tape past `limit` cells,
+    /// under `Engine::run_sandboxed`.
+    TapeLimitExceeded { limit: usize, pc: usize, source: Option< SourcePos > },
+    /// The loop whose OPEN is at `pc` ran more iterations than `budget`.
+    LoopBudgetExceeded { budget: u64, pc: usize, source: Option< SourcePos > },
+    /// The OPEN or CLOSE at `pc` would jump outside the program, which a
+    /// correct compiler never produces.
+    CorruptJumpTable { pc: usize },
+    /// The pc reached one of the unused slots after the final HALT.
+    IllegalInstruction { pc: usize },
+    /// The opcode at `pc` would have written to the protected cell `addr`.
+    WriteProtected { addr: usize, pc: usize, source: Option< SourcePos > },
+    /// Writing the output failed with a broken pipe, so nothing is reading
+    /// it any more.
+    OutputClosed { pc: usize, source: Option< SourcePos > },
+    /// The engine's interrupt flag was set.
+    Interrupted { pc: usize, source: Option< SourcePos > },
+    InvalidJson { message: String },
+    /// The OPEN or CLOSE at `position` of a JSON program gives `target` as
+    /// its partner, which is not the bracket that matches it.
+    InconsistentJsonProgram { position: usize, target: usize },
+    /// A compiled program file that is malformed or from another version.
+    InvalidBinary { message: String },
+    /// A saved engine state that is malformed or does not fit the program.
+    InvalidState { message: String },
+    /// The program needs more slots than the compiler's program size.
+    ProgramTooLarge { needed: usize, capacity: usize },
+    /// A program file that includes itself, or whose includes nest more
+    /// than `include::MAX_DEPTH` deep.
+    IncludeCycle { file: String },
+    /// A malformed command-line option.
+    InvalidArgument { message: String },
+    /// Two compilations of one program that `verify::verify` ran did not
+    /// do the same, for the reason given.
+    Diverged { message: String },
+    Io( std::io::Error ),
+    /// An error in one stage of a pipeline of programs, counting from 1.
+    Pipeline { stage: usize, error: Box< BfError > },
+}
+
+impl fmt::Display for BfError {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            BfError::UnmatchedOpen { position, source } =>
+                write!( f, "Unmatched opening bracket{}", At( *position, source ) ),
+            BfError::UnmatchedClose { position, source } =>
+                write!( f, "Unmatched closing bracket{}", At( *position, source ) ),
+            BfError::UnexpectedCharacter { ch, position, source } =>
+                write!( f, "Unexpected character {:?}{}", ch, At( *position, source ) ),
+            BfError::UnexpectedByte { byte, position, source } =>
+                write!( f, "Unexpected byte 0x{:02x}{}", byte, At( *position, source ) ),
+            BfError::PointerUnderflow { pc, loc, source } =>
+                write!( f, "Data pointer moved left of cell 0{} (pc={}, loc={})", In( source ), pc, loc ),
+            BfError::PointerOverflow { pc, loc, source } =>
+                write!( f, "Data pointer moved right past the last cell{} (pc={}, loc={})", In( source ), pc, loc ),
+            BfError::CellOverflow { pc, loc, source } =>
+                write!( f, "Cell overflowed{} (pc={}, loc={})", In( source ), pc, loc ),
+            BfError::AssertionFailed { pc, expected, actual, source } =>
+                write!( f, "Assertion failed{}: expected {} but found {} (pc={})", In( source ), expected, actual, pc ),
+            BfError::StepLimitExceeded { limit, pc, source } =>
+                write!( f, "Step limit of {} exceeded{} (pc={})", limit, In( source ), pc ),
+            BfError::OutputLimitExceeded { limit, pc, source } =>
+                write!( f, "Output limit of {} bytes exceeded{} (pc={})", limit, In( source ), pc ),
+            BfError::TapeLimitExceeded { limit, pc, source } =>
+                write!( f, "Tape limit of {} cells exceeded{} (pc={})", limit, In( source ), pc ),
+            BfError::LoopBudgetExceeded { budget, pc, source } =>
+                write!( f, "Loop{} ran more than {} iterations (pc={})", In( source ), budget, pc ),
+            BfError::CorruptJumpTable { pc } =>
+                write!( f, "Jump out of the program (pc={})", pc ),
+            BfError::IllegalInstruction { pc } =>
+                write!( f, "Illegal instruction past the end of the program (pc={})", pc ),
+            BfError::WriteProtected { addr, pc, source } =>
+                write!( f, "Write to protected cell {}{} (pc={})", addr, In( source ), pc ),
+            BfError::OutputClosed { pc, source } =>
+                write!( f, "Output closed{} (pc={})", In( source ), pc ),
+            BfError::Interrupted { pc, source } =>
+                write!( f, "Interrupted{} (pc={})", In( source ), pc ),
+            BfError::InvalidJson { message } =>
+                write!( f, "Invalid JSON program: {}", message ),
+            BfError::InconsistentJsonProgram { position, target } =>
+                write!( f, "Invalid JSON program: the bracket at {} gives {} as its partner, which does not match it", position, target ),
+            BfError::InvalidBinary { message } =>
+                write!( f, "Invalid compiled program: {}", message ),
+            BfError::InvalidState { message } =>
+                write!( f, "Invalid engine state: {}", message ),
+            BfError::ProgramTooLarge { needed, capacity } =>
+                write!( f, "Program needs {} slots but only {} are available (raise --program-size)", needed, capacity ),
+            BfError::IncludeCycle { file } =>
+                write!( f, "Includes form a cycle, or nest more than {} deep, at {}", crate::include::MAX_DEPTH, file ),
+            BfError::InvalidArgument { message } =>
+                write!( f, "{}", message ),
+            BfError::Diverged { message } =>
+                write!( f, "The runs diverged: {}", message ),
+            BfError::Io( err ) =>
+                write!( f, "I/O error: {}", err ),
+            BfError::Pipeline { stage, error } =>
+                write!( f, "Stage {} of the pipeline failed: {}", stage, error ),
+        }
+    }
+}
+
+impl std::error::Error for BfError {
+    fn source( &self ) -> Option< &( dyn std::error::Error + 'static ) > {
+        match self {
+            BfError::Io( err ) => Some( err ),
+            BfError::Pipeline { error, .. } => Some( error.as_ref() ),
+            _ => None,
+        }
+    }
+}
+
+impl From< std::io::Error > for BfError {
+    fn from( err: std::io::Error ) -> Self {
+        BfError::Io( err )
+    }
+}
+
+//  Formats a compile-time position, preferring the source location.
+struct At< 'a >( usize, &'a Option< SourcePos > );
+
+impl fmt::Display for At< '_ > {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        match self.1 {
+            Some( pos ) => write!( f, " at {}", pos ),
+            None => write!( f, " at instruction {}", self.0 ),
+        }
+    }
+}
+
+//  Formats the source location of a runtime error, if known.
+struct In< 'a >( &'a Option< SourcePos > );
+
+impl fmt::Display for In< '_ > {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        match self.0 {
+            Some( pos ) => write!( f, " at {}", pos ),
+            None => Ok(()),
+        }
+    }
+}
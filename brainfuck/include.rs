@@ -0,0 +1,55 @@
+/*
+    Splices the files named by `#include` lines into the source of a
+    program, so that programs can share a library of snippets. A line that
+    starts with `#include`, after any indentation, is replaced by the source
+    of the file that it names, which is found relative to the file that
+    includes it and may include others in turn. Positions in errors count
+    the lines of the combined source.
+*/
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::BfError;
+
+/// How deeply includes may nest.
+pub const MAX_DEPTH: usize = 16;
+
+/// Reads the program in `path`, replacing each `#include` line with the
+/// source of the file it names. A file that includes itself, directly or
+/// through others, fails with `BfError::IncludeCycle`, as does one more
+/// than `MAX_DEPTH` includes deep.
+pub fn expand( path: &Path ) -> Result< Vec< u8 >, BfError > {
+    let mut src = Vec::new();
+    splice( path, &mut Vec::new(), &mut src )?;
+    Ok( src )
+}
+
+//  Appends the source of `path` to `src`, with its includes spliced in.
+//  `open` holds the files being expanded, outermost first, which are the
+//  only ones that cannot be included again. The source is kept as bytes,
+//  since it need not be UTF-8.
+fn splice( path: &Path, open: &mut Vec< PathBuf >, src: &mut Vec< u8 > ) -> Result< (), BfError > {
+    let canonical = fs::canonicalize( path )?;
+    if open.contains( &canonical ) || open.len() >= MAX_DEPTH {
+        return Err( BfError::IncludeCycle { file: path.display().to_string() } );
+    }
+    let text = fs::read( path )?;
+    open.push( canonical );
+    for line in text.split_inclusive( | &byte | byte == b'\n' ) {
+        match line.trim_ascii_start().strip_prefix( b"#include" ) {
+            Some( name ) if name.first().is_some_and( u8::is_ascii_whitespace ) => {
+                let dir = path.parent().unwrap_or( Path::new( "" ) );
+                let name = String::from_utf8_lossy( name.trim_ascii() );
+                splice( &dir.join( name.as_ref() ), open, src )?;
+                //  Keep what follows off the last line of the included file.
+                if !src.is_empty() && !src.ends_with( b"\n" ) {
+                    src.push( b'\n' );
+                }
+            },
+            _ => src.extend_from_slice( line ),
+        }
+    }
+    open.pop();
+    Ok(())
+}
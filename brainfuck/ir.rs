@@ -0,0 +1,515 @@
+/*
+    The intermediate representation produced by parsing, one `Instr` per
+    command. Jumps are left unresolved here; the threading stage works out
+    where each bracket lands in the program array.
+*/
+
+use crate::compiler::SourcePos;
+use crate::error::BfError;
+use crate::json::Json;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Instr {
+    Incr,
+    Decr,
+    Right,
+    Left,
+    Open,
+    Close,
+    Put,
+    Get,
+    //  Runs of the same command folded together by the optimizer.
+    IncrN( usize ),
+    DecrN( usize ),
+    RightN( usize ),
+    LeftN( usize ),
+    //  A loop that zeroes the current cell, such as `[-]`.
+    SetZero,
+    //  A clear loop followed by a run of `+`, which stores the count.
+    SetConst( usize ),
+    //  A loop such as `[->++<]` that adds a multiple of the current cell to
+    //  each of its neighbours at the given offsets, then zeroes it.
+    MulAdd( Vec< ( isize, isize ) > ),
+    //  A stretch of moves and `+` or `-` that ends where it started, such
+    //  as `>>+<<`, adding the amount to the cell at the offset.
+    AddAt( isize, isize ),
+    //  The multiply loop `[->+<]` and others like it, with a single target
+    //  at the offset and a factor of 1, which moves the current cell there.
+    Move( isize ),
+    //  The loops `[>]` and `[<]`, which move to the nearest zero cell.
+    ScanRight,
+    ScanLeft,
+    //  The `#` debugging command, which prints the cells around the pointer.
+    Dump,
+    //  The `?` extension, which stores a random byte in the current cell.
+    Rand,
+    //  The `:` extension, which writes the current cell as a decimal number.
+    PutNum,
+    //  The `%` extension, which switches between two tapes.
+    SwitchTape,
+    //  The `@` extension, which halts the program where it stands.
+    Halt,
+    //  The `=` extension, which checks that the current cell holds the
+    //  number written after it.
+    Assert( usize ),
+    //  A command added with `Compiler::register_op`, by its character.
+    Custom( char ),
+}
+
+/// An instruction together with the source position it was compiled from,
+/// so that optimization passes can keep the source map intact.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Located {
+    pub instr: Instr,
+    pub pos: Option< SourcePos >,
+}
+
+impl Instr {
+
+    /// The opcode name used in listings and the JSON format.
+    pub fn name( &self ) -> &'static str {
+        match self {
+            Instr::Incr => "INCR",
+            Instr::Decr => "DECR",
+            Instr::Right => "RIGHT",
+            Instr::Left => "LEFT",
+            Instr::Open => "OPEN",
+            Instr::Close => "CLOSE",
+            Instr::Put => "PUT",
+            Instr::Get => "GET",
+            Instr::IncrN( _ ) => "INCR_N",
+            Instr::DecrN( _ ) => "DECR_N",
+            Instr::RightN( _ ) => "RIGHT_N",
+            Instr::LeftN( _ ) => "LEFT_N",
+            Instr::SetZero => "SET_ZERO",
+            Instr::SetConst( _ ) => "SET_CONST",
+            Instr::MulAdd( _ ) => "MULADD",
+            Instr::AddAt( .. ) => "ADD_AT",
+            Instr::Move( _ ) => "MOVE",
+            Instr::ScanRight => "SCAN_RIGHT",
+            Instr::ScanLeft => "SCAN_LEFT",
+            Instr::Dump => "DUMP",
+            Instr::Rand => "RAND",
+            Instr::PutNum => "PUT_NUM",
+            Instr::SwitchTape => "SWITCH_TAPE",
+            Instr::Halt => "HALT",
+            Instr::Assert( _ ) => "ASSERT",
+            Instr::Custom( _ ) => "CUSTOM",
+        }
+    }
+
+    /// The count carried by a folded instruction.
+    pub fn count( &self ) -> Option< usize > {
+        match self {
+            Instr::IncrN( n ) | Instr::DecrN( n ) | Instr::RightN( n ) | Instr::LeftN( n ) | Instr::SetConst( n ) | Instr::Assert( n ) => Some( *n ),
+            _ => None,
+        }
+    }
+
+    /// The inverse of `name` and `count`.
+    pub fn from_name( name: &str, count: Option< usize > ) -> Option< Instr > {
+        match ( name, count ) {
+            ( "INCR", None ) => Some( Instr::Incr ),
+            ( "DECR", None ) => Some( Instr::Decr ),
+            ( "RIGHT", None ) => Some( Instr::Right ),
+            ( "LEFT", None ) => Some( Instr::Left ),
+            ( "OPEN", _ ) => Some( Instr::Open ),
+            ( "CLOSE", _ ) => Some( Instr::Close ),
+            ( "PUT", None ) => Some( Instr::Put ),
+            ( "GET", None ) => Some( Instr::Get ),
+            ( "INCR_N", Some( n ) ) => Some( Instr::IncrN( n ) ),
+            ( "DECR_N", Some( n ) ) => Some( Instr::DecrN( n ) ),
+            ( "RIGHT_N", Some( n ) ) => Some( Instr::RightN( n ) ),
+            ( "LEFT_N", Some( n ) ) => Some( Instr::LeftN( n ) ),
+            ( "SET_ZERO", None ) => Some( Instr::SetZero ),
+            ( "SET_CONST", Some( n ) ) => Some( Instr::SetConst( n ) ),
+            ( "SCAN_RIGHT", None ) => Some( Instr::ScanRight ),
+            ( "SCAN_LEFT", None ) => Some( Instr::ScanLeft ),
+            ( "DUMP", None ) => Some( Instr::Dump ),
+            ( "RAND", None ) => Some( Instr::Rand ),
+            ( "PUT_NUM", None ) => Some( Instr::PutNum ),
+            ( "SWITCH_TAPE", None ) => Some( Instr::SwitchTape ),
+            ( "HALT", None ) => Some( Instr::Halt ),
+            ( "ASSERT", Some( n ) ) => Some( Instr::Assert( n ) ),
+            _ => None,
+        }
+    }
+
+}
+
+//  For each instruction, the index of its matching bracket (or 0 for
+//  non-brackets and unmatched brackets).
+fn jump_targets( instrs: &[ Instr ] ) -> Vec< usize > {
+    let mut targets = vec![ 0; instrs.len() ];
+    let mut indexes = Vec::<usize>::new();
+    for ( n, instr ) in instrs.iter().enumerate() {
+        match instr {
+            Instr::Open => indexes.push( n ),
+            Instr::Close => {
+                if let Some( start ) = indexes.pop() {
+                    targets[ start ] = n;
+                    targets[ n ] = start;
+                }
+            },
+            _ => {},
+        }
+    }
+    targets
+}
+
+/// Writes the instructions as a JSON array, one instruction per line. The
+/// operand of OPEN and CLOSE is the index of the matching bracket and that
+/// of a folded instruction is its count. A MULADD has its targets instead,
+/// as a list of offset and factor pairs.
+///
+/// ```
+/// use brainfuck::{ir, Instr};
+///
+/// let instrs = [ Instr::IncrN( 8 ), Instr::MulAdd( vec![ ( 1, 2 ), ( -1, -3 ) ] ) ];
+/// let json = ir::to_json( &instrs );
+/// assert_eq!( json, "[\n    {\"op\":\"INCR_N\",\"operand\":8},\n    {\"op\":\"MULADD\",\"targets\":[[1,2],[-1,-3]]}\n]\n" );
+/// assert_eq!( ir::from_json( &json ).unwrap(), instrs );
+/// ```
+pub fn to_json( instrs: &[ Instr ] ) -> String {
+    let targets = jump_targets( instrs );
+    let mut text = String::from( "[\n" );
+    for ( n, instr ) in instrs.iter().enumerate() {
+        let operand = match instr {
+            Instr::Open | Instr::Close => Some( targets[ n ] ),
+            _ => instr.count(),
+        };
+        let item = match ( instr, operand ) {
+            ( Instr::MulAdd( targets ), _ ) => {
+                let pairs = targets.iter().map( | &( offset, factor ) | Json::Array( vec![ ( offset as i64 ).into(), ( factor as i64 ).into() ] ) );
+                Json::object( [ ( "op", instr.name().into() ), ( "targets", Json::Array( pairs.collect() ) ) ] )
+            },
+            ( &Instr::Move( offset ), _ ) => Json::object( [ ( "op", instr.name().into() ), ( "offset", ( offset as i64 ).into() ) ] ),
+            ( &Instr::Custom( ch ), _ ) => Json::object( [ ( "op", instr.name().into() ), ( "char", ch.to_string().as_str().into() ) ] ),
+            ( &Instr::AddAt( offset, amount ), _ ) =>
+                Json::object( [ ( "op", instr.name().into() ), ( "offset", ( offset as i64 ).into() ), ( "amount", ( amount as i64 ).into() ) ] ),
+            ( _, Some( operand ) ) => Json::object( [ ( "op", instr.name().into() ), ( "operand", operand.into() ) ] ),
+            ( _, None ) => Json::object( [ ( "op", instr.name().into() ) ] ),
+        };
+        text.push_str( "    " );
+        text.push_str( &item.to_string() );
+        text.push_str( if n + 1 < instrs.len() { ",\n" } else { "\n" } );
+    }
+    text.push_str( "]\n" );
+    text
+}
+
+/// Writes the instructions back out as Brainfuck source, giving each
+/// optimized instruction in the canonical form of the code it replaces,
+/// such as `[-]` for SET_ZERO. Compiling the source gives the same program.
+///
+/// ```
+/// use brainfuck::{ir, Instr};
+///
+/// let instrs = [ Instr::IncrN( 3 ), Instr::SetZero, Instr::MulAdd( vec![ ( -1, 2 ), ( 2, -1 ) ] ), Instr::Put ];
+/// assert_eq!( ir::to_source( &instrs ), "+++[-][-<++>>>-<<]." );
+/// ```
+pub fn to_source( instrs: &[ Instr ] ) -> String {
+    let moves = | n: isize | if n < 0 { "<".repeat( n.unsigned_abs() ) } else { ">".repeat( n as usize ) };
+    let mut src = String::new();
+    for instr in instrs {
+        match instr {
+            Instr::Incr => src.push( '+' ),
+            Instr::Decr => src.push( '-' ),
+            Instr::Right => src.push( '>' ),
+            Instr::Left => src.push( '<' ),
+            Instr::Open => src.push( '[' ),
+            Instr::Close => src.push( ']' ),
+            Instr::Put => src.push( '.' ),
+            Instr::Get => src.push( ',' ),
+            Instr::IncrN( n ) => src.push_str( &"+".repeat( *n ) ),
+            Instr::DecrN( n ) => src.push_str( &"-".repeat( *n ) ),
+            Instr::RightN( n ) => src.push_str( &">".repeat( *n ) ),
+            Instr::LeftN( n ) => src.push_str( &"<".repeat( *n ) ),
+            Instr::SetZero => src.push_str( "[-]" ),
+            Instr::SetConst( n ) => src.push_str( &format!( "[-]{}", "+".repeat( *n ) ) ),
+            Instr::MulAdd( targets ) => {
+                src.push_str( "[-" );
+                let mut at = 0;
+                for &( offset, factor ) in targets {
+                    src.push_str( &moves( offset - at ) );
+                    let step = if factor < 0 { "-" } else { "+" };
+                    src.push_str( &step.repeat( factor.unsigned_abs() ) );
+                    at = offset;
+                }
+                src.push_str( &moves( -at ) );
+                src.push( ']' );
+            },
+            &Instr::Move( offset ) => src.push_str( &format!( "[-{}+{}]", moves( offset ), moves( -offset ) ) ),
+            &Instr::AddAt( offset, amount ) => {
+                let step = if amount < 0 { "-" } else { "+" };
+                src.push_str( &format!( "{}{}{}", moves( offset ), step.repeat( amount.unsigned_abs() ), moves( -offset ) ) );
+            },
+            Instr::ScanRight => src.push_str( "[>]" ),
+            Instr::ScanLeft => src.push_str( "[<]" ),
+            Instr::Dump => src.push( '#' ),
+            Instr::Rand => src.push( '?' ),
+            Instr::PutNum => src.push( ':' ),
+            Instr::SwitchTape => src.push( '%' ),
+            &Instr::Custom( ch ) => src.push( ch ),
+            Instr::Halt => src.push( '@' ),
+            Instr::Assert( n ) => src.push_str( &format!( "={}", n ) ),
+        }
+    }
+    src
+}
+
+/// Writes the instructions out as the source of a Rust program whose
+/// `main` runs them on a tape of wrapping 8-bit cells, with a `while`
+/// loop for each pair of brackets, so that `rustc` can compile them to
+/// native code. End of input leaves the cell unchanged, as `run` does,
+/// and a move off either end of the tape panics.
+///
+/// ```
+/// use brainfuck::{ir, Instr};
+///
+/// let rust = ir::to_rust( &[ Instr::Open, Instr::Decr, Instr::Close ] );
+/// assert!( rust.contains( "    while memory[ loc ] != 0 {\n        memory[ loc ] = memory[ loc ].wrapping_sub( 1 );\n    }\n" ) );
+/// ```
+pub fn to_rust( instrs: &[ Instr ] ) -> String {
+    let cell = | offset: isize | match offset {
+        0 => "memory[ loc ]".to_string(),
+        _ if offset < 0 => format!( "memory[ loc - {} ]", offset.unsigned_abs() ),
+        _ => format!( "memory[ loc + {} ]", offset ),
+    };
+    //  Counts and factors reduced to the byte that they add.
+    let byte = | n: isize | n.rem_euclid( 256 );
+    let mut body = String::new();
+    let mut depth = 1;
+    for instr in instrs {
+        if *instr == Instr::Close {
+            depth -= 1;
+        }
+        let line = match instr {
+            Instr::Incr => format!( "{0} = {0}.wrapping_add( 1 );", cell( 0 ) ),
+            Instr::Decr => format!( "{0} = {0}.wrapping_sub( 1 );", cell( 0 ) ),
+            Instr::IncrN( n ) => format!( "{0} = {0}.wrapping_add( {1} );", cell( 0 ), n % 256 ),
+            Instr::DecrN( n ) => format!( "{0} = {0}.wrapping_sub( {1} );", cell( 0 ), n % 256 ),
+            Instr::Right => "loc += 1;".to_string(),
+            Instr::Left => "loc -= 1;".to_string(),
+            Instr::RightN( n ) => format!( "loc += {};", n ),
+            Instr::LeftN( n ) => format!( "loc -= {};", n ),
+            Instr::Open => format!( "while {} != 0 {{", cell( 0 ) ),
+            Instr::Close => "}".to_string(),
+            Instr::Put => format!( "output.write_all( &[ {} ] )?;", cell( 0 ) ),
+            Instr::Get => format!( "output.flush()?;\nlet mut byte = [ 0 ];\nif input.read_exact( &mut byte ).is_ok() {{\n    {} = byte[ 0 ];\n}}", cell( 0 ) ),
+            Instr::SetZero => format!( "{} = 0;", cell( 0 ) ),
+            Instr::SetConst( n ) => format!( "{} = {};", cell( 0 ), n % 256 ),
+            Instr::MulAdd( targets ) => {
+                let mut block = format!( "if {} != 0 {{\n    let v = {};", cell( 0 ), cell( 0 ) );
+                for &( offset, factor ) in targets {
+                    block.push_str( &format!( "\n    {0} = {0}.wrapping_add( v.wrapping_mul( {1} ) );", cell( offset ), byte( factor ) ) );
+                }
+                block.push_str( &format!( "\n    {} = 0;\n}}", cell( 0 ) ) );
+                block
+            },
+            &Instr::Move( offset ) => format!( "if {0} != 0 {{\n    {1} = {1}.wrapping_add( {0} );\n    {0} = 0;\n}}", cell( 0 ), cell( offset ) ),
+            &Instr::AddAt( offset, amount ) => format!( "{0} = {0}.wrapping_add( {1} );", cell( offset ), byte( amount ) ),
+            Instr::ScanRight => format!( "while {} != 0 {{\n    loc += 1;\n}}", cell( 0 ) ),
+            Instr::ScanLeft => format!( "while {} != 0 {{\n    loc -= 1;\n}}", cell( 0 ) ),
+            Instr::Dump => "eprintln!( \"loc={}: {:?}\", loc, &memory[ loc.saturating_sub( 4 )..( loc + 5 ).min( memory.len() ) ] );".to_string(),
+            Instr::Rand => format!( "rng ^= rng << 13;\nrng ^= rng >> 7;\nrng ^= rng << 17;\n{} = ( rng >> 56 ) as u8;", cell( 0 ) ),
+            Instr::PutNum => format!( "write!( output, \"{{}} \", {} as i8 )?;", cell( 0 ) ),
+            Instr::SwitchTape => "std::mem::swap( &mut memory, &mut spare );\nstd::mem::swap( &mut loc, &mut spare_loc );".to_string(),
+            Instr::Halt => "return output.flush();".to_string(),
+            Instr::Assert( n ) => format!( "assert_eq!( {}, {} );", cell( 0 ), n % 256 ),
+            Instr::Custom( ch ) => format!( "unimplemented!( {:?} );", format!( "the custom command {}", ch ) ),
+        };
+        for line in line.lines() {
+            body.push_str( &format!( "{}{}\n", "    ".repeat( depth ), line ) );
+        }
+        if *instr == Instr::Open {
+            depth += 1;
+        }
+    }
+    //  Only what the program uses is declared, so that it compiles cleanly.
+    let mut rust = String::from( "// Compiled from Brainfuck.\n\n#![allow(unused_mut, unused_assignments, unreachable_code)]\n\nuse std::io::prelude::*;\n\nfn main() -> std::io::Result< () > {\n" );
+    rust.push_str( &format!( "    let mut memory = vec![ 0u8; {} ];\n    let mut loc: usize = 0;\n", crate::engine::MEMORY_SIZE ) );
+    if instrs.contains( &Instr::SwitchTape ) {
+        rust.push_str( &format!( "    let mut spare = vec![ 0u8; {} ];\n    let mut spare_loc: usize = 0;\n", crate::engine::MEMORY_SIZE ) );
+    }
+    if instrs.contains( &Instr::Rand ) {
+        rust.push_str( &format!( "    let mut rng: u64 = {:#x};\n", crate::engine::DEFAULT_SEED ) );
+    }
+    if instrs.contains( &Instr::Get ) {
+        rust.push_str( "    let mut input = std::io::stdin().lock();\n" );
+    }
+    rust.push_str( "    let mut output = std::io::BufWriter::new( std::io::stdout().lock() );\n" );
+    rust.push_str( &body );
+    rust.push_str( "    output.flush()\n}\n" );
+    rust
+}
+
+/// A part of the bracket structure that `tree` finds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    /// A run of instructions other than brackets, as `to_source` writes it.
+    Run( String ),
+    /// A loop, holding its body.
+    Loop( Vec< Node > ),
+}
+
+/// The instructions as a tree of loops, reflecting the bracket structure
+/// rather than the flat list. Unmatched brackets fail with the index of
+/// the instruction.
+///
+/// ```
+/// use brainfuck::ir::{self, Node};
+///
+/// let instrs = brainfuck::Compiler::new().parse( "+[>+]-" ).unwrap();
+/// assert_eq!( ir::tree( &instrs ).unwrap(), [ Node::Run( "+".into() ), Node::Loop( vec![ Node::Run( ">+".into() ) ] ), Node::Run( "-".into() ) ] );
+/// ```
+pub fn tree( instrs: &[ Instr ] ) -> Result< Vec< Node >, BfError > {
+    let mut top = Vec::new();
+    //  The body so far of each loop still open, innermost last, and the
+    //  index of its OPEN.
+    let mut open: Vec< ( Vec< Node >, usize ) > = Vec::new();
+    let mut run: Vec< Instr > = Vec::new();
+    for ( n, instr ) in instrs.iter().enumerate() {
+        if !matches!( instr, Instr::Open | Instr::Close ) {
+            run.push( instr.clone() );
+            continue;
+        }
+        let body = open.last_mut().map_or( &mut top, | ( body, _ ) | body );
+        if !run.is_empty() {
+            body.push( Node::Run( to_source( &std::mem::take( &mut run ) ) ) );
+        }
+        if *instr == Instr::Open {
+            open.push( ( Vec::new(), n ) );
+        } else {
+            let ( body, _ ) = open.pop().ok_or( BfError::UnmatchedClose { position: n, source: None } )?;
+            open.last_mut().map_or( &mut top, | ( outer, _ ) | outer ).push( Node::Loop( body ) );
+        }
+    }
+    if let Some( &( _, position ) ) = open.first() {
+        return Err( BfError::UnmatchedOpen { position, source: None } );
+    }
+    if !run.is_empty() {
+        top.push( Node::Run( to_source( &run ) ) );
+    }
+    Ok( top )
+}
+
+/// Writes a tree as JSON on one line, in which a run is a string of its
+/// source and a loop is an object whose `loop` field holds its body.
+///
+/// ```
+/// use brainfuck::ir::{self, Node};
+///
+/// let nodes = [ Node::Run( "+".into() ), Node::Loop( vec![ Node::Run( ">+".into() ) ] ), Node::Run( "-".into() ) ];
+/// assert_eq!( ir::tree_json( &nodes ), "[\"+\",{\"loop\":[\">+\"]},\"-\"]\n" );
+/// ```
+pub fn tree_json( nodes: &[ Node ] ) -> String {
+    fn to_json( nodes: &[ Node ] ) -> Json {
+        Json::Array( nodes.iter().map( | node | match node {
+            Node::Run( src ) => src.as_str().into(),
+            Node::Loop( body ) => Json::object( [ ( "loop", to_json( body ) ) ] ),
+        } ).collect() )
+    }
+    format!( "{}\n", to_json( nodes ) )
+}
+
+/// Writes a tree a node to a line, with the body of each loop indented
+/// between its brackets.
+///
+/// ```
+/// use brainfuck::ir::{self, Node};
+///
+/// let nodes = [ Node::Run( "+".into() ), Node::Loop( vec![ Node::Run( ">+".into() ) ] ) ];
+/// assert_eq!( ir::tree_text( &nodes ), "+\n[\n    >+\n]\n" );
+/// ```
+pub fn tree_text( nodes: &[ Node ] ) -> String {
+    fn write( nodes: &[ Node ], indent: usize, text: &mut String ) {
+        for node in nodes {
+            match node {
+                Node::Run( src ) => text.push_str( &format!( "{:indent$}{}\n", "", src ) ),
+                Node::Loop( body ) => {
+                    text.push_str( &format!( "{:indent$}[\n", "" ) );
+                    write( body, indent + 4, text );
+                    text.push_str( &format!( "{:indent$}]\n", "" ) );
+                },
+            }
+        }
+    }
+    let mut text = String::new();
+    write( nodes, 0, &mut text );
+    text
+}
+
+/// Reads back the output of `to_json`. The operand of each OPEN and CLOSE,
+/// if it has one, must be the index of its matching bracket, so that a
+/// hand-edited jump fails with `InconsistentJsonProgram` rather than
+/// running differently; the jumps are recomputed when the instructions
+/// are threaded.
+///
+/// ```
+/// use brainfuck::{ir, BfError};
+///
+/// let json = ir::to_json( &brainfuck::Compiler::new().parse( "+[-]" ).unwrap() );
+/// assert!( ir::from_json( &json ).is_ok() );
+/// let tampered = json.replace( "{\"op\":\"OPEN\",\"operand\":3}", "{\"op\":\"OPEN\",\"operand\":2}" );
+/// assert!( matches!( ir::from_json( &tampered ), Err( BfError::InconsistentJsonProgram { position: 1, target: 2 } ) ) );
+/// ```
+pub fn from_json( text: &str ) -> Result< Vec< Instr >, BfError > {
+    let json = Json::parse( text )?;
+    let items = json.as_array().ok_or_else( || BfError::InvalidJson { message: "expected an array of instructions".to_string() } )?;
+    let instrs = items.iter().map( instr_from_json ).collect::< Result< Vec< Instr >, BfError > >()?;
+    let targets = jump_targets( &instrs );
+    for ( position, ( instr, item ) ) in instrs.iter().zip( items ).enumerate() {
+        if !matches!( instr, Instr::Open | Instr::Close ) {
+            continue;
+        }
+        //  A bracket that is unmatched has no partner to point back at it.
+        let Some( target ) = item.get( "operand" ).and_then( Json::as_usize ) else { continue };
+        let partner = if *instr == Instr::Open { Instr::Close } else { Instr::Open };
+        if instrs.get( target ) != Some( &partner ) || targets[ position ] != target || targets[ target ] != position {
+            return Err( BfError::InconsistentJsonProgram { position, target } );
+        }
+    }
+    Ok( instrs )
+}
+
+//  One instruction of the array that `from_json` reads.
+fn instr_from_json( item: &Json ) -> Result< Instr, BfError > {
+    let name = item.get( "op" ).and_then( Json::as_str )
+        .ok_or_else( || BfError::InvalidJson { message: format!( "instruction without an op: {}", item ) } )?;
+    if name == "MULADD" {
+        return muladd_targets( item ).map( Instr::MulAdd )
+            .ok_or_else( || BfError::InvalidJson { message: format!( "bad targets: {}", item ) } );
+    }
+    if name == "CUSTOM" {
+        let mut chars = item.get( "char" ).and_then( Json::as_str ).unwrap_or( "" ).chars();
+        return match ( chars.next(), chars.next() ) {
+            ( Some( ch ), None ) => Ok( Instr::Custom( ch ) ),
+            _ => Err( BfError::InvalidJson { message: format!( "bad char: {}", item ) } ),
+        };
+    }
+    if name == "MOVE" {
+        return item.get( "offset" ).and_then( Json::as_i64 ).map( | offset | Instr::Move( offset as isize ) )
+            .ok_or_else( || BfError::InvalidJson { message: format!( "bad offset: {}", item ) } );
+    }
+    if name == "ADD_AT" {
+        let field = | key | item.get( key ).and_then( Json::as_i64 ).map( | n | n as isize );
+        return field( "offset" ).zip( field( "amount" ) ).map( | ( offset, amount ) | Instr::AddAt( offset, amount ) )
+            .ok_or_else( || BfError::InvalidJson { message: format!( "bad offset or amount: {}", item ) } );
+    }
+    let operand = match item.get( "operand" ) {
+        Some( operand ) => Some( operand.as_usize()
+            .ok_or_else( || BfError::InvalidJson { message: format!( "bad operand: {}", item ) } )? ),
+        None => None,
+    };
+    Instr::from_name( name, operand )
+        .ok_or_else( || BfError::InvalidJson { message: format!( "unknown op: {}", item ) } )
+}
+
+//  The offset and factor pairs of a MULADD read by `from_json`.
+fn muladd_targets( item: &Json ) -> Option< Vec< ( isize, isize ) > > {
+    item.get( "targets" )?.as_array()?.iter().map( | pair | match pair.as_array()?.as_slice() {
+        [ offset, factor ] => Some( ( offset.as_i64()? as isize, factor.as_i64()? as isize ) ),
+        _ => None,
+    } ).collect()
+}
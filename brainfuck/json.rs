@@ -0,0 +1,299 @@
+/*
+    Just enough JSON to read and write the library's interchange formats,
+    so that the library keeps to the standard library.
+*/
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::BfError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool( bool ),
+    Number( f64 ),
+    String( String ),
+    Array( Vec< Json > ),
+    Object( BTreeMap< String, Json > ),
+}
+
+impl Json {
+
+    //  Builds an object from a list of fields.
+    pub fn object< const N: usize >( fields: [ ( &str, Json ); N ] ) -> Json {
+        Json::Object( fields.into_iter().map( | ( k, v ) | ( k.to_string(), v ) ).collect() )
+    }
+
+    pub fn get( &self, key: &str ) -> Option< &Json > {
+        match self {
+            Json::Object( fields ) => fields.get( key ),
+            _ => None,
+        }
+    }
+
+    pub fn as_str( &self ) -> Option< &str > {
+        match self {
+            Json::String( s ) => Some( s ),
+            _ => None,
+        }
+    }
+
+    pub fn as_array( &self ) -> Option< &Vec< Json > > {
+        match self {
+            Json::Array( items ) => Some( items ),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool( &self ) -> Option< bool > {
+        match self {
+            Json::Bool( b ) => Some( *b ),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64( &self ) -> Option< i64 > {
+        match self {
+            Json::Number( n ) if n.fract() == 0.0 => Some( *n as i64 ),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize( &self ) -> Option< usize > {
+        match self {
+            Json::Number( n ) if n.fract() == 0.0 && *n >= 0.0 => Some( *n as usize ),
+            _ => None,
+        }
+    }
+
+    pub fn parse( text: &str ) -> Result< Json, BfError > {
+        let mut parser = Parser { text: text.as_bytes(), pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.text.len() {
+            return Err( parser.error( "trailing characters" ) );
+        }
+        Ok( value )
+    }
+
+}
+
+impl From< i64 > for Json {
+    fn from( n: i64 ) -> Json {
+        Json::Number( n as f64 )
+    }
+}
+
+impl From< u64 > for Json {
+    fn from( n: u64 ) -> Json {
+        Json::Number( n as f64 )
+    }
+}
+
+impl From< usize > for Json {
+    fn from( n: usize ) -> Json {
+        Json::Number( n as f64 )
+    }
+}
+
+impl From< &str > for Json {
+    fn from( s: &str ) -> Json {
+        Json::String( s.to_string() )
+    }
+}
+
+impl From< bool > for Json {
+    fn from( b: bool ) -> Json {
+        Json::Bool( b )
+    }
+}
+
+fn write_string( f: &mut fmt::Formatter, s: &str ) -> fmt::Result {
+    write!( f, "\"" )?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!( f, "\\\"" )?,
+            '\\' => write!( f, "\\\\" )?,
+            '\n' => write!( f, "\\n" )?,
+            '\r' => write!( f, "\\r" )?,
+            '\t' => write!( f, "\\t" )?,
+            c if ( c as u32 ) < 0x20 => write!( f, "\\u{:04x}", c as u32 )?,
+            c => write!( f, "{}", c )?,
+        }
+    }
+    write!( f, "\"" )
+}
+
+//  Writes compact JSON, with no insignificant whitespace.
+impl fmt::Display for Json {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            Json::Null => write!( f, "null" ),
+            Json::Bool( b ) => write!( f, "{}", b ),
+            Json::Number( n ) => write!( f, "{}", n ),
+            Json::String( s ) => write_string( f, s ),
+            Json::Array( items ) => {
+                write!( f, "[" )?;
+                for ( i, item ) in items.iter().enumerate() {
+                    if i > 0 { write!( f, "," )?; }
+                    write!( f, "{}", item )?;
+                }
+                write!( f, "]" )
+            },
+            Json::Object( fields ) => {
+                write!( f, "{{" )?;
+                for ( i, ( k, v ) ) in fields.iter().enumerate() {
+                    if i > 0 { write!( f, "," )?; }
+                    write_string( f, k )?;
+                    write!( f, ":{}", v )?;
+                }
+                write!( f, "}}" )
+            },
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+
+    fn error( &self, message: &str ) -> BfError {
+        BfError::InvalidJson { message: format!( "{} at byte {}", message, self.pos ) }
+    }
+
+    fn skip_whitespace( &mut self ) {
+        while self.pos < self.text.len() && self.text[ self.pos ].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek( &mut self ) -> Option< u8 > {
+        self.skip_whitespace();
+        self.text.get( self.pos ).copied()
+    }
+
+    fn expect( &mut self, ch: u8 ) -> Result< (), BfError > {
+        if self.peek() == Some( ch ) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err( self.error( &format!( "expected '{}'", ch as char ) ) )
+        }
+    }
+
+    fn keyword( &mut self, word: &str, value: Json ) -> Result< Json, BfError > {
+        if self.text[ self.pos.. ].starts_with( word.as_bytes() ) {
+            self.pos += word.len();
+            Ok( value )
+        } else {
+            Err( self.error( "unexpected character" ) )
+        }
+    }
+
+    fn value( &mut self ) -> Result< Json, BfError > {
+        match self.peek() {
+            Some( b'{' ) => self.object(),
+            Some( b'[' ) => self.array(),
+            Some( b'"' ) => Ok( Json::String( self.string()? ) ),
+            Some( b't' ) => self.keyword( "true", Json::Bool( true ) ),
+            Some( b'f' ) => self.keyword( "false", Json::Bool( false ) ),
+            Some( b'n' ) => self.keyword( "null", Json::Null ),
+            Some( b'-' ) | Some( b'0'..=b'9' ) => self.number(),
+            Some( _ ) => Err( self.error( "unexpected character" ) ),
+            None => Err( self.error( "unexpected end of input" ) ),
+        }
+    }
+
+    fn object( &mut self ) -> Result< Json, BfError > {
+        self.expect( b'{' )?;
+        let mut fields = BTreeMap::new();
+        if self.peek() == Some( b'}' ) {
+            self.pos += 1;
+            return Ok( Json::Object( fields ) );
+        }
+        loop {
+            if self.peek() != Some( b'"' ) {
+                return Err( self.error( "expected a field name" ) );
+            }
+            let key = self.string()?;
+            self.expect( b':' )?;
+            let value = self.value()?;
+            fields.insert( key, value );
+            match self.peek() {
+                Some( b',' ) => self.pos += 1,
+                Some( b'}' ) => { self.pos += 1; return Ok( Json::Object( fields ) ); },
+                _ => return Err( self.error( "expected ',' or '}'" ) ),
+            }
+        }
+    }
+
+    fn array( &mut self ) -> Result< Json, BfError > {
+        self.expect( b'[' )?;
+        let mut items = Vec::new();
+        if self.peek() == Some( b']' ) {
+            self.pos += 1;
+            return Ok( Json::Array( items ) );
+        }
+        loop {
+            items.push( self.value()? );
+            match self.peek() {
+                Some( b',' ) => self.pos += 1,
+                Some( b']' ) => { self.pos += 1; return Ok( Json::Array( items ) ); },
+                _ => return Err( self.error( "expected ',' or ']'" ) ),
+            }
+        }
+    }
+
+    fn string( &mut self ) -> Result< String, BfError > {
+        self.expect( b'"' )?;
+        let mut bytes = Vec::new();
+        loop {
+            let ch = *self.text.get( self.pos ).ok_or_else( || self.error( "unterminated string" ) )?;
+            self.pos += 1;
+            match ch {
+                b'"' => break,
+                b'\\' => {
+                    let esc = *self.text.get( self.pos ).ok_or_else( || self.error( "unterminated string" ) )?;
+                    self.pos += 1;
+                    match esc {
+                        b'"' | b'\\' | b'/' => bytes.push( esc ),
+                        b'n' => bytes.push( b'\n' ),
+                        b'r' => bytes.push( b'\r' ),
+                        b't' => bytes.push( b'\t' ),
+                        b'b' => bytes.push( 0x08 ),
+                        b'f' => bytes.push( 0x0c ),
+                        b'u' => {
+                            let hex = self.text.get( self.pos..self.pos + 4 ).ok_or_else( || self.error( "bad escape" ) )?;
+                            let code = std::str::from_utf8( hex ).ok()
+                                .and_then( | h | u32::from_str_radix( h, 16 ).ok() )
+                                .and_then( char::from_u32 )
+                                .ok_or_else( || self.error( "bad escape" ) )?;
+                            self.pos += 4;
+                            let mut buf = [ 0; 4 ];
+                            bytes.extend_from_slice( code.encode_utf8( &mut buf ).as_bytes() );
+                        },
+                        _ => return Err( self.error( "bad escape" ) ),
+                    }
+                },
+                _ => bytes.push( ch ),
+            }
+        }
+        String::from_utf8( bytes ).map_err( | _ | self.error( "invalid UTF-8 in string" ) )
+    }
+
+    fn number( &mut self ) -> Result< Json, BfError > {
+        let start = self.pos;
+        while self.pos < self.text.len() && matches!( self.text[ self.pos ], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9' ) {
+            self.pos += 1;
+        }
+        std::str::from_utf8( &self.text[ start..self.pos ] ).ok()
+            .and_then( | s | s.parse::< f64 >().ok() )
+            .map( Json::Number )
+            .ok_or_else( || self.error( "malformed number" ) )
+    }
+
+}
@@ -0,0 +1,113 @@
+/*!
+    A Brainfuck interpreter written in subroutine-threaded style.
+
+    Source is parsed into a list of [`Instr`] and then compiled into a
+    [`Program`], an array of function pointers with a parallel array of
+    their operands, which an [`Engine`] then runs.
+
+    ```
+    let program = brainfuck::compile( "++++++++[>++++++++<-]>+." ).unwrap();
+    let mut output = Vec::new();
+    brainfuck::run( &program, std::io::empty(), &mut output ).unwrap();
+    assert_eq!( output, b"A" );
+    ```
+
+    The [`bare`] module is a much smaller engine that needs only `alloc`.
+    Building with `--cfg 'feature="no_std"'` leaves out everything else,
+    and building with `--cfg 'feature="tokio"'` adds `run_async`, which
+    runs a program over tokio's asynchronous streams.
+*/
+
+#![cfg_attr(feature = "no_std", no_std)]
+
+extern crate alloc;
+
+pub mod bare;
+
+//  Everything but `bare` needs std, and is left out of a no_std build.
+macro_rules! with_std {
+    ( $( $item:item )* ) => { $( #[cfg(not(feature = "no_std"))] $item )* };
+}
+
+with_std! {
+
+    //  First, so that its macros are defined for the modules after it.
+    #[macro_use]
+    mod logging;
+    mod binary;
+    mod cell;
+    pub mod check;
+    mod compiler;
+    mod direct;
+    pub mod debugger;
+    mod engine;
+    mod error;
+    pub mod include;
+    pub mod ir;
+    mod json;
+    pub mod minimize;
+    pub mod optimize;
+    pub mod repl;
+    pub mod selftest;
+    pub mod sparse;
+    mod state;
+    mod switch;
+    mod token;
+    #[cfg(feature = "tokio")]
+    mod tokio_run;
+    pub mod verify;
+    pub mod visualize;
+
+    use std::io::prelude::*;
+
+    pub use cell::{Cell, Endian, Shown};
+    pub use compiler::{Compiler, Overflow, Program, SourcePos};
+    pub use engine::{opcode_name, Coverage, Engine, EofPolicy, ExecutedStep, HaltReason, OpCode, RunSummary, SandboxConfig, StepResult, TapeMode, MEMORY_SIZE};
+    pub use error::BfError;
+    pub use ir::Instr;
+    pub use state::EngineState;
+    pub use switch::Dispatch;
+    pub use visualize::DumpFormat;
+    #[cfg(feature = "tokio")]
+    pub use tokio_run::{run_async, YIELD_INTERVAL};
+
+    /// Compiles `src` with the default settings.
+    pub fn compile( src: &str ) -> Result< Program, BfError > {
+        Compiler::new().compile( src )
+    }
+
+    /// Runs `program` on a default-sized tape, reading `,` from `input` and
+    /// writing `.` to `output`.
+    pub fn run<'io>( program: &Program, input: impl Read + 'io, output: impl Write + 'io ) -> Result< (), BfError > {
+        Engine::< i8 >::new( program, MEMORY_SIZE ).with_io( input, output ).run()
+    }
+
+    /// Compiles and runs `src` with `input` as its input, returning its output
+    /// decoded as UTF-8, with invalid sequences replaced. End of input reads
+    /// as 0 and `#` is a comment. It uses no real I/O, so it also works in a
+    /// browser.
+    ///
+    /// ```
+    /// assert_eq!( brainfuck::run_to_string( "+++.", "" ).unwrap(), "\u{3}" );
+    /// assert_eq!( brainfuck::run_to_string( ",[.,]", "echo" ).unwrap(), "echo" );
+    /// ```
+    pub fn run_to_string( src: &str, input: &str ) -> Result< String, BfError > {
+        let program = Compiler::new().debug_op( false ).compile( src )?;
+        let mut output = Vec::new();
+        Engine::< i8 >::new( &program, MEMORY_SIZE )
+            .eof( EofPolicy::Zero )
+            .with_io( input.as_bytes(), &mut output )
+            .debug_output( std::io::sink() )
+            .run()?;
+        Ok( String::from_utf8_lossy( &output ).into_owned() )
+    }
+
+    /// `run_to_string` for JavaScript, built with `--cfg 'feature="wasm"'` and
+    /// the wasm-bindgen crate. Errors are thrown as their messages.
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen::prelude::wasm_bindgen( js_name = runToString )]
+    pub fn run_to_string_js( src: &str, input: &str ) -> Result< String, String > {
+        run_to_string( src, input ).map_err( | e | e.to_string() )
+    }
+
+}
@@ -0,0 +1,53 @@
+/*
+    Logging through the `log` crate, for programs that embed the library
+    and want to choose how verbose it is. It needs a build with the log
+    feature: `--cfg 'feature="log"' --extern log=...`. Without it these
+    macros log nothing, though their arguments are still type checked.
+
+        log_debug!      the instruction counts around each optimization pass
+        log_trace!      each opcode dispatched, when the trace level is on
+        log_warn!       problems that the engine carries on past
+*/
+
+#[cfg(feature = "log")]
+macro_rules! log_debug {
+    ( $( $arg:tt )* ) => { log::debug!( $( $arg )* ) };
+}
+
+#[cfg(feature = "log")]
+macro_rules! log_trace {
+    ( $( $arg:tt )* ) => { log::trace!( $( $arg )* ) };
+}
+
+#[cfg(feature = "log")]
+macro_rules! log_warn {
+    ( $( $arg:tt )* ) => { log::warn!( $( $arg )* ) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_debug {
+    ( $( $arg:tt )* ) => { if false { let _ = format_args!( $( $arg )* ); } };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_trace {
+    ( $( $arg:tt )* ) => { if false { let _ = format_args!( $( $arg )* ); } };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_warn {
+    ( $( $arg:tt )* ) => { if false { let _ = format_args!( $( $arg )* ); } };
+}
+
+//  Whether each step should be logged, which the engine asks once a run so
+//  that the plain loop is kept when it is not. The log crate's max_level
+//  features can make this false at compile time.
+#[cfg(feature = "log")]
+pub( crate ) fn tracing() -> bool {
+    log::log_enabled!( log::Level::Trace )
+}
+
+#[cfg(not(feature = "log"))]
+pub( crate ) fn tracing() -> bool {
+    false
+}
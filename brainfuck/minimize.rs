@@ -0,0 +1,115 @@
+/*
+    Shrinks a program that fails to a smaller one that fails in the same
+    way, for the driver's --minimize mode, which helps to track down a bug
+    in a long program or in the interpreter itself. This is a simple form
+    of delta debugging: it removes runs of commands, halving the length of
+    the run whenever none can go, and then unwraps loops by removing their
+    brackets together, until nothing more can be removed. A run is only
+    removed when the brackets still balance afterwards.
+*/
+
+use std::mem;
+
+use crate::compiler::{Compiler, Program};
+use crate::error::BfError;
+use crate::ir::{self, Instr};
+
+/// Shrinks `src`, which must fail when compiled by `compiler` and given to
+/// `run`, to commands that still fail with the same variant of `BfError`,
+/// such as `PointerUnderflow`. Only the kind of error is kept, not where
+/// it happens. `run` is called once for each attempt, so it should limit
+/// the steps it takes, since removing commands can make a loop that never
+/// ends.
+///
+/// ```
+/// use brainfuck::{minimize, Compiler, Engine};
+///
+/// let run = | program: &brainfuck::Program | Engine::< i8 >::new( program, 8 ).max_steps( 1000 ).with_io( std::io::empty(), std::io::sink() ).run();
+/// assert_eq!( minimize::minimize( &Compiler::new(), "++[->+<]>.<<+", run ).unwrap(), "<" );
+/// ```
+pub fn minimize( compiler: &Compiler, src: impl AsRef< [ u8 ] >, mut run: impl FnMut( &Program ) -> Result< (), BfError > ) -> Result< String, BfError > {
+    let mut outcome = | instrs: &[ Instr ] | compiler.thread( instrs ).and_then( | program | run( &program ) ).err();
+    let mut instrs = compiler.parse( src )?;
+    let Some( error ) = outcome( &instrs ) else {
+        return Err( BfError::InvalidArgument { message: "The program runs without an error, so there is nothing to minimize".to_string() } );
+    };
+    let kind = mem::discriminant( &error );
+    let mut still_fails = | instrs: &[ Instr ] | balanced( instrs ) && outcome( instrs ).is_some_and( | e | mem::discriminant( &e ) == kind );
+    loop {
+        let before = instrs.len();
+        remove_runs( &mut instrs, &mut still_fails );
+        unwrap_loops( &mut instrs, &mut still_fails );
+        if instrs.len() == before {
+            return Ok( ir::to_source( &instrs ) );
+        }
+    }
+}
+
+//  Removes every run of commands that can go, trying long runs first.
+fn remove_runs( instrs: &mut Vec< Instr >, still_fails: &mut impl FnMut( &[ Instr ] ) -> bool ) {
+    let mut n = ( instrs.len() / 2 ).max( 1 );
+    while n > 0 && !instrs.is_empty() {
+        let mut start = 0;
+        while start < instrs.len() {
+            let end = ( start + n ).min( instrs.len() );
+            let candidate: Vec< Instr > = instrs[ ..start ].iter().chain( &instrs[ end.. ] ).cloned().collect();
+            if still_fails( &candidate ) {
+                *instrs = candidate;
+            } else {
+                start += n;
+            }
+        }
+        n /= 2;
+    }
+}
+
+//  Removes the brackets of each loop that can lose them, keeping its body.
+fn unwrap_loops( instrs: &mut Vec< Instr >, still_fails: &mut impl FnMut( &[ Instr ] ) -> bool ) {
+    let mut open = 0;
+    while open < instrs.len() {
+        if let Some( close ) = matching_close( instrs, open ) {
+            let mut candidate = instrs.clone();
+            candidate.remove( close );
+            candidate.remove( open );
+            if still_fails( &candidate ) {
+                *instrs = candidate;
+                continue;
+            }
+        }
+        open += 1;
+    }
+}
+
+//  The index of the `]` that closes the `[` at `open`, if it is one.
+fn matching_close( instrs: &[ Instr ], open: usize ) -> Option< usize > {
+    if instrs[ open ] != Instr::Open {
+        return None;
+    }
+    let mut depth = 0;
+    for ( i, instr ) in instrs.iter().enumerate().skip( open ) {
+        match instr {
+            Instr::Open => depth += 1,
+            Instr::Close => depth -= 1,
+            _ => {},
+        }
+        if depth == 0 {
+            return Some( i );
+        }
+    }
+    None
+}
+
+fn balanced( instrs: &[ Instr ] ) -> bool {
+    let mut depth = 0usize;
+    for instr in instrs {
+        match instr {
+            Instr::Open => depth += 1,
+            Instr::Close => match depth.checked_sub( 1 ) {
+                Some( d ) => depth = d,
+                None => return false,
+            },
+            _ => {},
+        }
+    }
+    depth == 0
+}
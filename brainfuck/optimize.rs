@@ -0,0 +1,452 @@
+/*
+    Optimization passes over the instruction list. Each pass preserves the
+    program's behaviour and keeps the source position of the first command
+    it replaces. An `Optimizer` runs a list of them in order.
+*/
+
+use std::fmt;
+
+use crate::compiler::SourcePos;
+use crate::ir::{Instr, Located};
+
+/// A single optimization pass.
+pub type Pass = fn( Vec< Located > ) -> Vec< Located >;
+
+/// An ordered list of passes, each with a name for reports.
+#[derive(Clone, Default)]
+pub struct Optimizer {
+    passes: Vec< ( &'static str, Pass ) >,
+}
+
+/// How many instructions one pass was given and how many it left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassCount {
+    pub name: &'static str,
+    pub before: usize,
+    pub after: usize,
+}
+
+/// What each pass of an `Optimizer` did to a program, from
+/// `Optimizer::run_report`, which shows as a table of the passes in the
+/// order they ran and a total.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OptReport {
+    pub passes: Vec< PassCount >,
+    /// The instructions before the first pass and after the last.
+    pub before: usize,
+    pub after: usize,
+}
+
+impl OptReport {
+
+    /// How much smaller the passes made the program, as a percentage.
+    pub fn reduction( &self ) -> f64 {
+        if self.before == 0 {
+            0.0
+        } else {
+            100.0 * ( self.before - self.after ) as f64 / self.before as f64
+        }
+    }
+
+}
+
+impl fmt::Display for OptReport {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        writeln!( f, "{:<14}{:>8}{:>8}", "Pass", "Before", "After" )?;
+        for pass in &self.passes {
+            writeln!( f, "{:<14}{:>8}{:>8}", pass.name, pass.before, pass.after )?;
+        }
+        writeln!( f, "{:<14}{:>8}{:>8}  ({:.1}% fewer)", "Total", self.before, self.after, self.reduction() )
+    }
+}
+
+impl Optimizer {
+
+    /// An optimizer with no passes.
+    pub fn new() -> Self {
+        Optimizer { passes: Vec::new() }
+    }
+
+    /// The standard passes for an optimization level from 0 (none) to 3
+    /// (all). Level 1 cancels opposing pairs, drops dead loops and folds
+    /// runs, level 2 adds the clear and scan loops, with any constant
+    /// stored after a clear, and level 3 the multiply loops, the moves
+    /// among them, and the additions at an offset. The passes that rely on
+    /// wrapping are left out unless `wrap` is set.
+    pub fn level( level: u8, wrap: bool ) -> Self {
+        let mut optimizer = Optimizer::new();
+        //  Cancelling goes first, so that folding sees the longer runs.
+        if level >= 1 {
+            optimizer = if wrap { optimizer.pass( "cancel_pairs", cancel_pairs ) } else { optimizer.pass( "cancel_moves", cancel_moves ) };
+        }
+        if level >= 2 && wrap {
+            optimizer = optimizer.pass( "clear_loops", clear_loops );
+        }
+        if level >= 3 && wrap {
+            optimizer = optimizer.pass( "mul_loops", mul_loops ).pass( "move_loops", move_loops );
+        }
+        if level >= 2 {
+            optimizer = optimizer.pass( "scan_loops", scan_loops );
+        }
+        //  After the loop passes, which leave more cells known to be zero.
+        if level >= 1 {
+            optimizer = optimizer.pass( "dead_loops", dead_loops );
+        }
+        //  Folding goes last, since the loop passes look for single steps.
+        if level >= 1 {
+            optimizer = optimizer.pass( "fold_runs", fold_runs );
+        }
+        if level >= 3 && wrap {
+            optimizer = optimizer.pass( "add_at", add_at );
+        }
+        if level >= 2 && wrap {
+            optimizer = optimizer.pass( "set_consts", set_consts );
+        }
+        optimizer
+    }
+
+    /// Adds `pass` to the end of the list, under `name` in reports.
+    pub fn pass( mut self, name: &'static str, pass: Pass ) -> Self {
+        self.passes.push( ( name, pass ) );
+        self
+    }
+
+    /// Runs each pass in turn, logging at debug level how many
+    /// instructions there were before and after it.
+    pub fn run( &self, instrs: Vec< Located > ) -> Vec< Located > {
+        self.run_report( instrs ).0
+    }
+
+    /// Runs each pass in turn like `run`, and also reports how many
+    /// instructions there were before and after each.
+    pub fn run_report( &self, mut instrs: Vec< Located > ) -> ( Vec< Located >, OptReport ) {
+        let mut report = OptReport { passes: Vec::new(), before: instrs.len(), after: 0 };
+        for ( i, &( name, pass ) ) in self.passes.iter().enumerate() {
+            let before = instrs.len();
+            instrs = pass( instrs );
+            log_debug!( "optimization pass {} of {} ({}): {} instructions before, {} after", i + 1, self.passes.len(), name, before, instrs.len() );
+            report.passes.push( PassCount { name, before, after: instrs.len() } );
+        }
+        report.after = instrs.len();
+        ( instrs, report )
+    }
+
+}
+
+/// Removes each `>` next to a `<`, in either order, as mechanically
+/// generated code often has them, and then any pair that removing those
+/// brings together, so that `>><<` goes entirely. Brackets stand between
+/// the moves on either side of them. The one change in behaviour is that
+/// a pair that ran off the end of a bounded tape, such as `<>` at cell 0,
+/// no longer fails.
+///
+/// ```
+/// use brainfuck::ir::{Instr, Located};
+///
+/// let instrs = [ Instr::Right, Instr::Right, Instr::Left, Instr::Open, Instr::Left, Instr::Close ];
+/// let located = instrs.into_iter().map( | instr | Located { instr, pos: None } ).collect();
+/// let cancelled: Vec< Instr > = brainfuck::optimize::cancel_moves( located ).into_iter().map( | l | l.instr ).collect();
+/// assert_eq!( cancelled, [ Instr::Right, Instr::Open, Instr::Left, Instr::Close ] );
+/// ```
+pub fn cancel_moves( instrs: Vec< Located > ) -> Vec< Located > {
+    cancel( instrs, &[ ( Instr::Right, Instr::Left ) ] )
+}
+
+/// Removes each `+` next to a `-` as well as the moves that `cancel_moves`
+/// does, so that `+>-+<-` goes entirely. This is only valid with wrapping
+/// arithmetic, as `+-` changes a saturated cell.
+pub fn cancel_pairs( instrs: Vec< Located > ) -> Vec< Located > {
+    cancel( instrs, &[ ( Instr::Right, Instr::Left ), ( Instr::Incr, Instr::Decr ) ] )
+}
+
+//  Drops each instruction that undoes the one before it, treating what is
+//  kept as a stack, so that pairs which meet once those between them have
+//  gone are dropped as well.
+fn cancel( instrs: Vec< Located >, opposites: &[ ( Instr, Instr ) ] ) -> Vec< Located > {
+    let mut kept: Vec< Located > = Vec::with_capacity( instrs.len() );
+    for located in instrs {
+        let opposed = kept.last().is_some_and( | last | opposites.iter().any( | ( forward, back ) | {
+            ( last.instr == *forward && located.instr == *back ) || ( last.instr == *back && located.instr == *forward )
+        } ) );
+        if opposed {
+            kept.pop();
+        } else {
+            kept.push( located );
+        }
+    }
+    kept
+}
+
+/// Removes each loop that can never run because the cell is known to be
+/// zero at its `[`, such as the comment loops that programs put after
+/// another loop to hold their documentation. The cell is only known to be
+/// zero straight after a `]`, which is only passed when it is, or after a
+/// clear, scan or multiply loop. As in `set_consts`, the start of the
+/// program does not count, since the tape may have been loaded with data.
+///
+/// ```
+/// use brainfuck::ir::{Instr, Located};
+///
+/// let instrs = [ Instr::SetZero, Instr::Open, Instr::Put, Instr::Close, Instr::Open, Instr::Get, Instr::Close ];
+/// let located = instrs.into_iter().map( | instr | Located { instr, pos: None } ).collect();
+/// let live: Vec< Instr > = brainfuck::optimize::dead_loops( located ).into_iter().map( | l | l.instr ).collect();
+/// assert_eq!( live, [ Instr::SetZero ] );
+/// ```
+pub fn dead_loops( instrs: Vec< Located > ) -> Vec< Located > {
+    let mut live: Vec< Located > = Vec::with_capacity( instrs.len() );
+    //  The dead loop being skipped and how deeply nested its brackets are
+    //  so far, kept so that an unmatched `[` is left for the compiler to
+    //  report.
+    let mut dead: Vec< Located > = Vec::new();
+    let mut depth = 0;
+    for located in instrs {
+        let zero = live.last().is_some_and( | last | matches!( last.instr, Instr::Close | Instr::SetZero | Instr::ScanRight | Instr::ScanLeft | Instr::MulAdd( _ ) ) );
+        if depth == 0 && !( zero && located.instr == Instr::Open ) {
+            live.push( located );
+            continue;
+        }
+        match located.instr {
+            Instr::Open => depth += 1,
+            Instr::Close => depth -= 1,
+            _ => {},
+        }
+        dead.push( located );
+        if depth == 0 {
+            dead.clear();
+        }
+    }
+    live.extend( dead );
+    live
+}
+
+/// Folds runs of two or more identical `+`, `-`, `>` or `<` into a single
+/// instruction carrying the count.
+pub fn fold_runs( instrs: Vec< Located > ) -> Vec< Located > {
+    let mut folded: Vec< Located > = Vec::with_capacity( instrs.len() );
+    for located in instrs {
+        let merged = folded.last_mut().and_then( | last | {
+            let n = match ( &last.instr, &located.instr ) {
+                ( Instr::Incr, Instr::Incr ) | ( Instr::Decr, Instr::Decr ) |
+                ( Instr::Right, Instr::Right ) | ( Instr::Left, Instr::Left ) => 2,
+                ( Instr::IncrN( n ), Instr::Incr ) | ( Instr::DecrN( n ), Instr::Decr ) |
+                ( Instr::RightN( n ), Instr::Right ) | ( Instr::LeftN( n ), Instr::Left ) => *n + 1,
+                _ => return None,
+            };
+            last.instr = match located.instr {
+                Instr::Incr => Instr::IncrN( n ),
+                Instr::Decr => Instr::DecrN( n ),
+                Instr::Right => Instr::RightN( n ),
+                _ => Instr::LeftN( n ),
+            };
+            Some(())
+        } );
+        if merged.is_none() {
+            folded.push( located );
+        }
+    }
+    folded
+}
+
+/// Replaces the clear loops `[-]` and `[+]` with SET_ZERO. This is only
+/// valid with wrapping arithmetic, as a saturating cell may never reach 0.
+pub fn clear_loops( instrs: Vec< Located > ) -> Vec< Located > {
+    let mut cleared: Vec< Located > = Vec::with_capacity( instrs.len() );
+    for located in instrs {
+        let n = cleared.len();
+        if located.instr == Instr::Close && n >= 2
+            && cleared[ n - 2 ].instr == Instr::Open
+            && matches!( cleared[ n - 1 ].instr, Instr::Incr | Instr::Decr )
+        {
+            cleared.truncate( n - 1 );
+            cleared[ n - 2 ].instr = Instr::SetZero;
+        } else {
+            cleared.push( located );
+        }
+    }
+    cleared
+}
+
+/// Replaces a stretch of `+`, `-`, `>` and `<`, folded or not, that ends
+/// where it started, such as `>>+<<`, with an ADD_AT for each cell it
+/// changes, so that the pointer does not move at all. The stretch is left
+/// alone if it moves past the cells it changes, since those moves could
+/// run off the tape. Like `clear_loops` this assumes wrapping.
+pub fn add_at( instrs: Vec< Located > ) -> Vec< Located > {
+    let mut result: Vec< Located > = Vec::with_capacity( instrs.len() );
+    let mut stretch: Vec< Located > = Vec::new();
+    for located in instrs {
+        if step( &located.instr ).is_some() {
+            stretch.push( located );
+        } else {
+            fuse_moves( std::mem::take( &mut stretch ), &mut result );
+            result.push( located );
+        }
+    }
+    fuse_moves( stretch, &mut result );
+    result
+}
+
+//  How far an instruction moves the pointer and how much it adds to the
+//  current cell, if it only does those.
+fn step( instr: &Instr ) -> Option< ( isize, isize ) > {
+    match *instr {
+        Instr::Incr => Some( ( 0, 1 ) ),
+        Instr::Decr => Some( ( 0, -1 ) ),
+        Instr::IncrN( n ) => Some( ( 0, n as isize ) ),
+        Instr::DecrN( n ) => Some( ( 0, -( n as isize ) ) ),
+        Instr::Right => Some( ( 1, 0 ) ),
+        Instr::Left => Some( ( -1, 0 ) ),
+        Instr::RightN( n ) => Some( ( n as isize, 0 ) ),
+        Instr::LeftN( n ) => Some( ( -( n as isize ), 0 ) ),
+        _ => None,
+    }
+}
+
+//  Appends the stretch to `result`, as ADD_ATs if `add_at` allows. Each
+//  cell's addition goes in the order the stretch first changes it, at the
+//  source position of that change.
+fn fuse_moves( stretch: Vec< Located >, result: &mut Vec< Located > ) {
+    let mut offset: isize = 0;
+    let ( mut lowest, mut highest ) = ( 0, 0 );
+    let mut amounts: Vec< ( isize, isize, Option< SourcePos > ) > = Vec::new();
+    for located in &stretch {
+        let ( moves, amount ) = step( &located.instr ).unwrap_or_default();
+        offset += moves;
+        lowest = lowest.min( offset );
+        highest = highest.max( offset );
+        if amount != 0 {
+            match amounts.iter_mut().find( | ( at, _, _ ) | *at == offset ) {
+                Some( ( _, total, _ ) ) => *total += amount,
+                None => amounts.push( ( offset, amount, located.pos ) ),
+            }
+        }
+    }
+    amounts.retain( | &( _, amount, _ ) | amount != 0 );
+    let targets = amounts.iter().map( | &( at, _, _ ) | at );
+    let within = lowest >= targets.clone().min().unwrap_or( 0 ).min( 0 ) && highest <= targets.max().unwrap_or( 0 ).max( 0 );
+    if offset != 0 || !within || amounts.iter().all( | &( at, _, _ ) | at == 0 ) {
+        result.extend( stretch );
+        return;
+    }
+    for ( at, amount, pos ) in amounts {
+        let instr = match ( at, amount ) {
+            ( 0, 1 ) => Instr::Incr,
+            ( 0, -1 ) => Instr::Decr,
+            ( 0, n ) if n > 0 => Instr::IncrN( n as usize ),
+            ( 0, n ) => Instr::DecrN( n.unsigned_abs() ),
+            _ => Instr::AddAt( at, amount ),
+        };
+        result.push( Located { instr, pos } );
+    }
+}
+
+/// Replaces a SET_ZERO followed by `+` or a folded run of them with a
+/// SET_CONST of the count, since the cell is known to be zero before the
+/// run. It needs the output of `clear_loops` and `fold_runs`. Nowhere else
+/// is the cell's value taken as known, not even at the start, where the
+/// tape may have been loaded with data.
+pub fn set_consts( instrs: Vec< Located > ) -> Vec< Located > {
+    let mut result: Vec< Located > = Vec::with_capacity( instrs.len() );
+    for located in instrs {
+        let n = match located.instr {
+            Instr::Incr => 1,
+            Instr::IncrN( n ) => n,
+            _ => 0,
+        };
+        match result.last_mut() {
+            Some( last ) if n > 0 && last.instr == Instr::SetZero => last.instr = Instr::SetConst( n ),
+            _ => result.push( located ),
+        }
+    }
+    result
+}
+
+/// Replaces the scan loops `[>]` and `[<]` with SCAN_RIGHT and SCAN_LEFT.
+/// Unlike the other loop passes this is valid without wrapping, as the
+/// loop body does no arithmetic.
+pub fn scan_loops( instrs: Vec< Located > ) -> Vec< Located > {
+    let mut scanned: Vec< Located > = Vec::with_capacity( instrs.len() );
+    for located in instrs {
+        let n = scanned.len();
+        let scan = if located.instr == Instr::Close && n >= 2 && scanned[ n - 2 ].instr == Instr::Open {
+            match scanned[ n - 1 ].instr {
+                Instr::Right => Some( Instr::ScanRight ),
+                Instr::Left => Some( Instr::ScanLeft ),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        match scan {
+            Some( scan ) => {
+                scanned.truncate( n - 1 );
+                scanned[ n - 2 ].instr = scan;
+            },
+            None => scanned.push( located ),
+        }
+    }
+    scanned
+}
+
+/// Replaces loops like `[->++<]`, whose bodies only use `+`, `-`, `>` and
+/// `<`, return the pointer to where it started and decrement the loop cell
+/// by one, with a MULADD. Like `clear_loops` this assumes wrapping.
+pub fn mul_loops( instrs: Vec< Located > ) -> Vec< Located > {
+    let mut result: Vec< Located > = Vec::with_capacity( instrs.len() );
+    for located in instrs {
+        if located.instr == Instr::Close {
+            if let Some( start ) = result.iter().rposition( | l | l.instr == Instr::Open ) {
+                if let Some( targets ) = mul_targets( &result[ start + 1.. ] ) {
+                    result.truncate( start + 1 );
+                    result[ start ].instr = Instr::MulAdd( targets );
+                    continue;
+                }
+            }
+        }
+        result.push( located );
+    }
+    result
+}
+
+/// Replaces each MULADD with a single target and a factor of 1, such as
+/// the one made from `[->+<]`, with a MOVE, which adds the cell to the
+/// target without multiplying. It needs the output of `mul_loops`.
+///
+/// ```
+/// use brainfuck::ir::{Instr, Located};
+///
+/// let located = vec![ Located { instr: Instr::MulAdd( vec![ ( 1, 1 ) ] ), pos: None } ];
+/// assert_eq!( brainfuck::optimize::move_loops( located )[ 0 ].instr, Instr::Move( 1 ) );
+/// ```
+pub fn move_loops( instrs: Vec< Located > ) -> Vec< Located > {
+    instrs.into_iter().map( | mut located | {
+        if let Instr::MulAdd( targets ) = &located.instr {
+            if let [ ( offset, 1 ) ] = targets[ .. ] {
+                located.instr = Instr::Move( offset );
+            }
+        }
+        located
+    } ).collect()
+}
+
+//  The (offset, factor) pairs of a multiply loop body, or None if the body
+//  is not one.
+fn mul_targets( body: &[ Located ] ) -> Option< Vec< ( isize, isize ) > > {
+    let mut offset: isize = 0;
+    let mut deltas = std::collections::BTreeMap::< isize, isize >::new();
+    for located in body {
+        match located.instr {
+            Instr::Incr => *deltas.entry( offset ).or_insert( 0 ) += 1,
+            Instr::Decr => *deltas.entry( offset ).or_insert( 0 ) -= 1,
+            Instr::Right => offset += 1,
+            Instr::Left => offset -= 1,
+            _ => return None,
+        }
+    }
+    if offset != 0 || deltas.remove( &0 ) != Some( -1 ) {
+        return None;
+    }
+    //  The factors are kept exact, since how they wrap depends on the width
+    //  of the cells.
+    Some( deltas.into_iter().filter( | &( _, factor ) | factor != 0 ).collect() )
+}
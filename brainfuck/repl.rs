@@ -0,0 +1,50 @@
+/*
+    A read-eval-print loop that runs Brainfuck a line at a time on one
+    engine, so that the tape carries over from each line to the next and
+    a computation can be built up by hand. A line that leaves a loop open
+    waits for the lines that close it, and then they all run together.
+    Lines are read from `lines` and the prompts and tape are written to
+    `out`, so it can be driven by a script as well as interactively, like
+    the debugger.
+*/
+
+use std::io::prelude::*;
+
+use crate::cell::Cell;
+use crate::compiler::Compiler;
+use crate::engine::Engine;
+use crate::error::BfError;
+
+const PROMPT: &str = "bf> ";
+const WAITING: &str = "waiting for ]> ";
+
+pub fn repl< C: Cell >( compiler: &Compiler, e: &mut Engine< C >, mut lines: impl Iterator< Item = String >, mut out: impl Write ) -> Result< (), BfError > {
+    let mut pending = String::new();
+    loop {
+        let _ = write!( out, "{}", if pending.is_empty() { PROMPT } else { WAITING } );
+        let _ = out.flush();
+        let Some( line ) = lines.next() else { return Ok(()) };
+        pending.push_str( line.trim_end_matches( '\n' ) );
+        pending.push( '\n' );
+        let program = match compiler.compile( &pending ) {
+            Err( BfError::UnmatchedOpen { .. } ) => continue,
+            Err( err ) => {
+                let _ = writeln!( out, "Error: {}", err );
+                pending.clear();
+                continue;
+            },
+            Ok( program ) => program,
+        };
+        pending.clear();
+        //  Run the new code from its start, on the tape as the last left it.
+        let mut state = e.snapshot();
+        e.load( &program );
+        state.pc = 0;
+        state.halted = false;
+        e.restore( &state )?;
+        let _ = match e.run() {
+            Ok(()) => writeln!( out, "{}", e.tape_window( 4 ) ),
+            Err( err ) => writeln!( out, "Error: {}", err ),
+        };
+    }
+}
@@ -0,0 +1,34 @@
+/*
+    A few small programs with known input and output, built into the
+    library so that the driver's --self-test can check a build without
+    needing any files. They are copies of programs in tests/programs.
+*/
+
+use crate::compiler::Compiler;
+
+//  The name, source, input and expected output of each program.
+const PROGRAMS: [ ( &str, &str, &[ u8 ], &[ u8 ] ); 3 ] = [
+    ( "hello", include_str!( "../tests/programs/hello.bf" ), b"", include_bytes!( "../tests/programs/hello.out" ) ),
+    ( "echo", include_str!( "../tests/programs/echo.bf" ), include_bytes!( "../tests/programs/echo.in" ), include_bytes!( "../tests/programs/echo.out" ) ),
+    ( "add", include_str!( "../tests/programs/add.bf" ), include_bytes!( "../tests/programs/add.in" ), include_bytes!( "../tests/programs/add.out" ) ),
+];
+
+/// Compiles and runs each built-in program, giving its name and, if it
+/// failed, why.
+///
+/// ```
+/// assert!( brainfuck::selftest::run_all().iter().all( | ( _, result ) | result.is_ok() ) );
+/// ```
+pub fn run_all() -> Vec< ( &'static str, Result< (), String > ) > {
+    PROGRAMS.iter().map( | &( name, src, input, expected ) | ( name, check( src, input, expected ) ) ).collect()
+}
+
+fn check( src: &str, input: &[ u8 ], expected: &[ u8 ] ) -> Result< (), String > {
+    let program = Compiler::new().compile( src ).map_err( | e | e.to_string() )?;
+    let mut output = Vec::new();
+    crate::run( &program, input, &mut output ).map_err( | e | e.to_string() )?;
+    if output != expected {
+        return Err( format!( "wrote {:?}", String::from_utf8_lossy( &output ) ) );
+    }
+    Ok(())
+}
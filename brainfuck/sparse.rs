@@ -0,0 +1,184 @@
+/*
+    Runs a program on a sparse tape, which keeps only the cells that are
+    not zero in an ordered map, for programs that move the pointer a long
+    way but touch few cells. Every cell reads as zero until it is written,
+    and writing zero removes it again, so a program that visits cell
+    1,000,000 stores one cell rather than a million.
+
+    The engine indexes its tape directly on every opcode, so rather than
+    making it look up cells in a map as well, this interprets the same
+    compiled program with a loop of its own. Each lookup costs much more
+    than indexing a dense tape; `benches/dispatch` times both.
+*/
+
+use std::collections::BTreeMap;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+use crate::cell::Cell;
+use crate::compiler::Program;
+use crate::engine::{Op, Slot};
+use crate::error::BfError;
+
+/// A tape of cells from 0 upwards, storing only those that are not zero,
+/// and the position of its head.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SparseTape< C > {
+    cells : BTreeMap< usize, C >,
+    head : usize,
+}
+
+impl< C: Cell > SparseTape< C > {
+
+    pub fn new() -> Self {
+        SparseTape { cells: BTreeMap::new(), head: 0 }
+    }
+
+    /// The cell at `addr`, which is zero unless it has been written.
+    pub fn get( &self, addr: usize ) -> C {
+        self.cells.get( &addr ).copied().unwrap_or( C::ZERO )
+    }
+
+    pub fn set( &mut self, addr: usize, cell: C ) {
+        if cell == C::ZERO {
+            self.cells.remove( &addr );
+        } else {
+            self.cells.insert( addr, cell );
+        }
+    }
+
+    /// Where the head is, which starts at cell 0.
+    pub fn head( &self ) -> usize {
+        self.head
+    }
+
+    /// The number of cells stored, which is those that are not zero.
+    pub fn len( &self ) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty( &self ) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The cells that are not zero, in order of address.
+    pub fn iter( &self ) -> impl Iterator< Item = ( usize, C ) > + '_ {
+        self.cells.iter().map( | ( &addr, &cell ) | ( addr, cell ) )
+    }
+
+}
+
+/// Runs `program` like `run`, but on a sparse tape that has no end to the
+/// right, and returns the tape. End of input leaves the cell unchanged.
+/// The `#`, `?` and `%` extensions and custom commands are not supported,
+/// and nor is any of the engine's instrumentation.
+///
+/// ```
+/// let program = brainfuck::compile( "+++[>+++<-]>." ).unwrap();
+/// let mut output = Vec::new();
+/// let tape = brainfuck::sparse::run_sparse::< i8 >( &program, std::io::empty(), &mut output ).unwrap();
+/// assert_eq!( ( output, tape.len(), tape.get( 1 ) ), ( b"\x09".to_vec(), 1, 9 ) );
+/// ```
+pub fn run_sparse< C: Cell >( program: &Program, input: impl Read, output: impl Write ) -> Result< SparseTape< C >, BfError > {
+    let mut tape = SparseTape::new();
+    let mut output = BufWriter::new( output );
+    let result = dispatch( program, &mut tape, &mut BufReader::new( input ), &mut output );
+    let flushed = output.flush();
+    result?;
+    flushed?;
+    Ok( tape )
+}
+
+fn dispatch< C: Cell >( program: &Program, tape: &mut SparseTape< C >, input: &mut impl Read, output: &mut impl Write ) -> Result< (), BfError > {
+    let mut pc = 0;
+    loop {
+        let Slot { op, operand } = *program.code.get( pc ).ok_or( BfError::CorruptJumpTable { pc } )?;
+        let at = tape.head;
+        let cell = tape.get( at );
+        let source = program.source_pos( pc );
+        let underflow = || BfError::PointerUnderflow { pc, loc: at, source };
+        let overflow = || BfError::PointerOverflow { pc, loc: at, source };
+        let cell_overflow = || BfError::CellOverflow { pc, loc: at, source };
+        let output_error = | err: std::io::Error | if err.kind() == std::io::ErrorKind::BrokenPipe {
+            BfError::OutputClosed { pc, source }
+        } else {
+            BfError::Io( err )
+        };
+        //  Only the folded opcodes take their count from their operand.
+        let n = match op {
+            Op::Incr | Op::Decr | Op::IncrSat | Op::DecrSat | Op::IncrChk | Op::DecrChk |
+            Op::Right | Op::Left | Op::RightGrow | Op::LeftGrow => 1,
+            _ => operand,
+        };
+        match op {
+            Op::Incr | Op::IncrN => tape.set( at, cell.wrapping_add_n( n ) ),
+            Op::Decr | Op::DecrN => tape.set( at, cell.wrapping_sub_n( n ) ),
+            Op::IncrSat | Op::IncrNSat => tape.set( at, cell.saturating_add_n( n ) ),
+            Op::DecrSat | Op::DecrNSat => tape.set( at, cell.saturating_sub_n( n ) ),
+            Op::IncrChk | Op::IncrNChk => tape.set( at, cell.checked_add_n( n ).ok_or_else( cell_overflow )? ),
+            Op::DecrChk | Op::DecrNChk => tape.set( at, cell.checked_sub_n( n ).ok_or_else( cell_overflow )? ),
+            Op::SetZero => tape.set( at, C::ZERO ),
+            Op::SetConst => tape.set( at, C::ZERO.wrapping_add_n( operand ) ),
+            Op::MulAdd => if cell != C::ZERO {
+                for &( offset, factor ) in &program.muladds[ operand ] {
+                    let t = at.checked_add_signed( offset ).ok_or_else( || if offset < 0 { underflow() } else { overflow() } )?;
+                    tape.set( t, tape.get( t ).wrapping_mul_add( cell, factor ) );
+                }
+                tape.set( at, C::ZERO );
+            },
+            Op::Move => if cell != C::ZERO {
+                let ( offset, _ ) = program.muladds[ operand ][ 0 ];
+                let t = at.checked_add_signed( offset ).ok_or_else( || if offset < 0 { underflow() } else { overflow() } )?;
+                tape.set( t, tape.get( t ).wrapping_add_cell( cell ) );
+                tape.set( at, C::ZERO );
+            },
+            Op::AddAt => {
+                let ( offset, amount ) = program.muladds[ operand ][ 0 ];
+                let t = at.checked_add_signed( offset ).ok_or_else( || if offset < 0 { underflow() } else { overflow() } )?;
+                tape.set( t, tape.get( t ).wrapping_mul_add( C::from_byte( 1 ), amount ) );
+            },
+            Op::Right | Op::RightN | Op::RightGrow | Op::RightNGrow => tape.head = at.checked_add( n ).ok_or_else( overflow )?,
+            Op::Left | Op::LeftN | Op::LeftGrow | Op::LeftNGrow => tape.head = at.checked_sub( n ).ok_or_else( underflow )?,
+            //  A cell that is not stored is zero, so a scan stops at the
+            //  first gap in the stored cells.
+            Op::ScanRight => while tape.get( tape.head ) != C::ZERO {
+                tape.head = tape.head.checked_add( 1 ).ok_or_else( overflow )?;
+            },
+            Op::ScanLeft => while tape.get( tape.head ) != C::ZERO {
+                tape.head = tape.head.checked_sub( 1 ).ok_or_else( underflow )?;
+            },
+            Op::Open if cell == C::ZERO => {
+                pc = operand;
+                continue;
+            },
+            Op::Close if cell != C::ZERO => {
+                pc = operand;
+                continue;
+            },
+            Op::Open | Op::Close => {},
+            Op::Put => output.write_all( &[ cell.to_byte() ] ).map_err( output_error )?,
+            Op::PutNum => write!( output, "{} ", cell ).map_err( output_error )?,
+            Op::Get => {
+                output.flush().map_err( output_error )?;
+                let mut buf = [0; 1];
+                match input.read_exact( &mut buf ) {
+                    Ok(()) => tape.set( at, C::from_byte( buf[ 0 ] ) ),
+                    Err( err ) if err.kind() == std::io::ErrorKind::UnexpectedEof => {},
+                    Err( err ) => return Err( err.into() ),
+                }
+            },
+            Op::Assert => {
+                let expected = C::ZERO.wrapping_add_n( operand );
+                if cell != expected {
+                    return Err( BfError::AssertionFailed { pc, expected: expected.to_i64(), actual: cell.to_i64(), source } );
+                }
+            },
+            Op::Halt => return Ok(()),
+            Op::Trap => return Err( BfError::IllegalInstruction { pc } ),
+            Op::Dump | Op::Rand | Op::SwitchTape | Op::Custom => {
+                return Err( BfError::InvalidArgument { message: format!( "{} is not supported on a sparse tape (pc={})", op.name(), pc ) } );
+            },
+        }
+        pc += 1;
+    }
+}
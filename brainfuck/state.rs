@@ -0,0 +1,148 @@
+/*
+    Snapshots of the mutable part of an engine - the registers, both tapes,
+    the state of `?` and the counts kept against the output limit and the
+    loop budget - so that a run can be saved, for example when it hits a
+    step limit, and resumed later. The program itself is not included,
+    since it never changes; a state is only meaningful for the program it
+    was taken from.
+*/
+
+use crate::cell::Cell;
+use crate::engine::Engine;
+use crate::error::BfError;
+use crate::json::Json;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EngineState< C: Cell = i8 > {
+    pub pc: usize,
+    pub loc: usize,
+    pub origin: usize,
+    pub halted: bool,
+    pub steps: u64,
+    pub memory: Vec< C >,
+    /// Which of the two tapes of `%` is current, and the other one with its
+    /// `loc` and `origin`, which is empty until it is first used.
+    pub tape_index: usize,
+    pub spare: ( Vec< C >, usize, usize ),
+    /// The xorshift state that `?` draws from.
+    pub rng: u64,
+    /// The bytes written so far, counted against the output limit.
+    pub written: u64,
+    /// The iterations of each loop so far under a loop budget, or empty.
+    pub loop_iterations: Vec< u64 >,
+}
+
+impl< C: Cell > EngineState< C > {
+
+    /// Writes the state as a single JSON object.
+    pub fn to_json( &self ) -> String {
+        let cells = | memory: &[ C ] | Json::Array( memory.iter().map( | &cell | cell.to_i64().into() ).collect() );
+        let json = Json::object( [
+            ( "pc", self.pc.into() ),
+            ( "loc", self.loc.into() ),
+            ( "origin", self.origin.into() ),
+            ( "halted", self.halted.into() ),
+            ( "steps", ( self.steps as usize ).into() ),
+            ( "memory", cells( &self.memory ) ),
+            ( "tape_index", self.tape_index.into() ),
+            ( "spare", cells( &self.spare.0 ) ),
+            ( "spare_loc", self.spare.1.into() ),
+            ( "spare_origin", self.spare.2.into() ),
+            //  In hex, since a JSON number cannot hold every u64.
+            ( "rng", format!( "{:016x}", self.rng ).as_str().into() ),
+            ( "written", self.written.into() ),
+            ( "loop_iterations", Json::Array( self.loop_iterations.iter().map( | &n | n.into() ).collect() ) ),
+        ] );
+        format!( "{}\n", json )
+    }
+
+    /// Reads back the output of `to_json`.
+    pub fn from_json( text: &str ) -> Result< EngineState< C >, BfError > {
+        let json = Json::parse( text )?;
+        let field = | name: &str | json.get( name ).ok_or_else( || invalid( format!( "missing field '{}'", name ) ) );
+        let number = | name: &str | field( name )?.as_usize().ok_or_else( || invalid( format!( "bad value for '{}'", name ) ) );
+        let array = | name: &str | field( name )?.as_array().ok_or_else( || invalid( format!( "{} should be an array", name ) ) );
+        let cells = | name: &str | array( name )?.iter().map( | cell | {
+            cell.as_i64().and_then( C::from_i64 ).ok_or_else( || invalid( format!( "bad cell: {}", cell ) ) )
+        } ).collect::< Result< Vec< C >, BfError > >();
+        let rng = field( "rng" )?.as_str().and_then( | hex | u64::from_str_radix( hex, 16 ).ok() ).ok_or_else( || invalid( "bad value for 'rng'".to_string() ) )?;
+        let loop_iterations = array( "loop_iterations" )?.iter().map( | n | {
+            n.as_usize().map( | n | n as u64 ).ok_or_else( || invalid( format!( "bad loop count: {}", n ) ) )
+        } ).collect::< Result< Vec< u64 >, BfError > >()?;
+        Ok( EngineState {
+            pc: number( "pc" )?,
+            loc: number( "loc" )?,
+            origin: number( "origin" )?,
+            halted: field( "halted" )?.as_bool().ok_or_else( || invalid( "bad value for 'halted'".to_string() ) )?,
+            steps: number( "steps" )? as u64,
+            memory: cells( "memory" )?,
+            tape_index: number( "tape_index" )?,
+            spare: ( cells( "spare" )?, number( "spare_loc" )?, number( "spare_origin" )? ),
+            rng,
+            written: number( "written" )? as u64,
+            loop_iterations,
+        } )
+    }
+
+}
+
+fn invalid( message: String ) -> BfError {
+    BfError::InvalidState { message }
+}
+
+impl<'io, C: Cell> Engine<'io, C> {
+
+    /// Captures the registers and tapes, and the rest of what the run goes
+    /// on to depend on.
+    pub fn snapshot( &self ) -> EngineState< C > {
+        EngineState {
+            pc: self.pc,
+            loc: self.loc,
+            origin: self.origin,
+            halted: self.halted,
+            steps: self.steps,
+            memory: self.memory.clone(),
+            tape_index: self.tape_index,
+            spare: self.spare.clone(),
+            rng: self.rng,
+            written: self.output_limit.map_or( 0, | ( _, written ) | written ),
+            loop_iterations: self.loop_budget.as_ref().map_or( Vec::new(), | ( _, iterations ) | iterations.clone() ),
+        }
+    }
+
+    /// Puts the engine back into a state captured by `snapshot`, after
+    /// checking that it fits this engine's program.
+    pub fn restore( &mut self, state: &EngineState< C > ) -> Result< (), BfError > {
+        if state.pc >= self.program.len() {
+            return Err( invalid( format!( "pc {} is beyond the end of the program", state.pc ) ) );
+        }
+        if state.loc >= state.memory.len() || state.origin > state.memory.len() {
+            return Err( invalid( "the data pointer is off the tape".to_string() ) );
+        }
+        let ( spare, spare_loc, spare_origin ) = &state.spare;
+        if state.tape_index > 1 || ( !spare.is_empty() && ( *spare_loc >= spare.len() || *spare_origin > spare.len() ) ) {
+            return Err( invalid( "the other tape is malformed".to_string() ) );
+        }
+        if let Some( ( _, iterations ) ) = &mut self.loop_budget {
+            if !state.loop_iterations.is_empty() && state.loop_iterations.len() != iterations.len() {
+                return Err( invalid( "the loop counts do not fit the program".to_string() ) );
+            }
+            iterations.fill( 0 );
+            iterations[ ..state.loop_iterations.len() ].copy_from_slice( &state.loop_iterations );
+        }
+        if let Some( ( _, written ) ) = &mut self.output_limit {
+            *written = state.written;
+        }
+        self.pc = state.pc;
+        self.loc = state.loc;
+        self.origin = state.origin;
+        self.halted = state.halted;
+        self.steps = state.steps;
+        self.memory = state.memory.clone();
+        self.tape_index = state.tape_index;
+        self.spare = state.spare.clone();
+        self.rng = state.rng;
+        Ok(())
+    }
+
+}
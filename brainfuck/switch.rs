@@ -0,0 +1,106 @@
+/*
+    A switch-dispatched alternative to the threaded engine, for comparing
+    the two styles. It runs the same compiled program on the same engine
+    state, but dispatches with a `match` on the `Op` that each opcode was
+    made from, as a conventional bytecode interpreter would. Each arm
+    makes a direct call to the corresponding opcode, so only the style of
+    dispatch differs.
+*/
+
+use std::io::prelude::*;
+
+use crate::cell::Cell;
+use crate::engine::*;
+use crate::error::BfError;
+
+/// How the engine dispatches each instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Calls through the function pointer stored in each instruction,
+    /// from a loop, as in subroutine threading.
+    Threaded,
+    /// Has each opcode fetch the one that follows, as in direct threading.
+    Direct,
+    /// Matches on an opcode enum.
+    Switch,
+    /// Looks up each opcode, stored as a byte, in a table of functions, as
+    /// in token threading.
+    Token,
+}
+
+impl std::str::FromStr for Dispatch {
+    type Err = String;
+
+    fn from_str( s: &str ) -> Result< Self, Self::Err > {
+        match s {
+            "threaded" | "subroutine" => Ok( Dispatch::Threaded ),
+            "direct" => Ok( Dispatch::Direct ),
+            "switch" => Ok( Dispatch::Switch ),
+            "token" => Ok( Dispatch::Token ),
+            _ => Err( format!( "Unknown dispatch '{}' (expected threaded, subroutine, direct, switch or token)", s ) ),
+        }
+    }
+}
+
+impl<'io, C: Cell> Engine<'io, C> {
+
+    /// Runs the program like `run`, but dispatching with a `match` rather
+    /// than through function pointers. Tracing, profiling, progress
+    /// reports, step limits, loop budgets,
+    /// protected cells and the cost model are not supported.
+    pub fn run_switch( &mut self ) -> Result< (), BfError > {
+        let result = self.dispatch_switch();
+        let flushed = self.output.flush();
+        result?;
+        flushed.map_err( | err | self.output_error( err ) )
+    }
+
+    fn dispatch_switch( &mut self ) -> Result< (), BfError > {
+        while !self.halted {
+            self.steps += 1;
+            match self.ops[ self.pc ] {
+                Op::Incr => INCR( self )?,
+                Op::Decr => DECR( self )?,
+                Op::IncrSat => INCR_SAT( self )?,
+                Op::DecrSat => DECR_SAT( self )?,
+                Op::IncrN => INCR_N( self )?,
+                Op::DecrN => DECR_N( self )?,
+                Op::IncrNSat => INCR_N_SAT( self )?,
+                Op::DecrNSat => DECR_N_SAT( self )?,
+                Op::IncrChk => INCR_CHK( self )?,
+                Op::DecrChk => DECR_CHK( self )?,
+                Op::IncrNChk => INCR_N_CHK( self )?,
+                Op::DecrNChk => DECR_N_CHK( self )?,
+                Op::SetZero => SET_ZERO( self )?,
+                Op::SetConst => SET_CONST( self )?,
+                Op::MulAdd => MULADD( self )?,
+                Op::AddAt => ADD_AT( self )?,
+                Op::Right => RIGHT( self )?,
+                Op::Left => LEFT( self )?,
+                Op::RightN => RIGHT_N( self )?,
+                Op::LeftN => LEFT_N( self )?,
+                Op::RightGrow => RIGHT_GROW( self )?,
+                Op::RightNGrow => RIGHT_N_GROW( self )?,
+                Op::LeftGrow => LEFT_GROW( self )?,
+                Op::LeftNGrow => LEFT_N_GROW( self )?,
+                Op::Open => OPEN( self )?,
+                Op::Close => CLOSE( self )?,
+                Op::Put => PUT( self )?,
+                Op::Get => GET( self )?,
+                Op::Halt => HALT( self )?,
+                Op::Dump => DUMP( self )?,
+                Op::Rand => RAND( self )?,
+                Op::PutNum => PUT_NUM( self )?,
+                Op::SwitchTape => SWITCH_TAPE( self )?,
+                Op::Assert => ASSERT( self )?,
+                Op::Move => MOVE( self )?,
+                Op::Custom => CUSTOM( self )?,
+                Op::Trap => TRAP( self )?,
+                Op::ScanRight => SCAN_RIGHT( self )?,
+                Op::ScanLeft => SCAN_LEFT( self )?,
+            }
+        }
+        Ok(())
+    }
+
+}
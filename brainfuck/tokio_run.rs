@@ -0,0 +1,67 @@
+/*
+    Runs a program over tokio's asynchronous streams, so that a server can
+    run Brainfuck for a connection without tying up a thread. `,` and `.`
+    await on the streams, and every other opcode is the same function that
+    the threaded engine calls. A long computation yields to the runtime
+    every `YIELD_INTERVAL` steps, so that it does not starve other tasks.
+
+    This is only built with `--cfg 'feature="tokio"'`, and needs tokio with
+    its `rt` and `io-util` features; see the tokio target of rust.makefile.
+*/
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+
+use crate::cell::Cell;
+use crate::compiler::Program;
+use crate::engine::{Engine, Op, MEMORY_SIZE};
+use crate::error::BfError;
+
+/// How many steps `run_async` takes between yields to the runtime.
+pub const YIELD_INTERVAL: u64 = 10_000;
+
+/// Runs `program` like `run`, on a default-sized tape, but reading `,`
+/// from `input` and writing `.` to `output` asynchronously. The engine is
+/// not `Send`, and so neither is the future, which must run on a
+/// `LocalSet` or be blocked on rather than spawned.
+pub async fn run_async( program: &Program, input: impl AsyncRead + Unpin, output: impl AsyncWrite + Unpin ) -> Result< (), BfError > {
+    let mut engine = Engine::< i8 >::new( program, MEMORY_SIZE ).with_io( std::io::empty(), std::io::sink() );
+    let mut input = BufReader::new( input );
+    let mut output = BufWriter::new( output );
+    let result = dispatch( &mut engine, &mut input, &mut output ).await;
+    let flushed = output.flush().await;
+    result?;
+    flushed.map_err( | err | engine.output_error( err ) )
+}
+
+async fn dispatch< C: Cell >( e: &mut Engine< '_, C >, input: &mut ( impl AsyncRead + Unpin ), output: &mut ( impl AsyncWrite + Unpin ) ) -> Result< (), BfError > {
+    while !e.halted {
+        if e.steps > 0 && e.steps.is_multiple_of( YIELD_INTERVAL ) {
+            tokio::task::yield_now().await;
+        }
+        e.steps += 1;
+        match e.ops[ e.pc ] {
+            Op::Put => {
+                let byte = e.memory[ e.loc ].to_byte();
+                output.write_all( &[ byte ] ).await.map_err( | err | e.output_error( err ) )?;
+                e.pc += 1;
+            },
+            Op::PutNum => {
+                let text = format!( "{} ", e.show( e.memory[ e.loc ] ) );
+                output.write_all( text.as_bytes() ).await.map_err( | err | e.output_error( err ) )?;
+                e.pc += 1;
+            },
+            //  End of input leaves the cell unchanged, as `run` does.
+            Op::Get => {
+                output.flush().await.map_err( | err | e.output_error( err ) )?;
+                match input.read_u8().await {
+                    Ok( byte ) => e.memory[ e.loc ] = C::from_byte( byte ),
+                    Err( err ) if err.kind() == std::io::ErrorKind::UnexpectedEof => {},
+                    Err( err ) => return Err( err.into() ),
+                }
+                e.pc += 1;
+            },
+            _ => ( e.program[ e.pc ] )( e )?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+/*
+    Runs one program compiled in two ways on the same input and checks
+    that both write the same output and leave the same tape, for the
+    driver's --verify mode. The optimizer must never change what a program
+    does, so an unoptimized compilation makes a reference for an optimized
+    one, and any difference between them points to a bug in a pass or in
+    an opcode that only the optimized program uses.
+*/
+
+use std::mem;
+
+use crate::compiler::{Compiler, Program};
+use crate::error::BfError;
+
+//  How many bytes of each output a difference shows.
+const SHOWN: usize = 16;
+
+/// Compiles `src` with `reference` and with `candidate`, runs each program
+/// with `run`, and fails with `Diverged` unless they agree. `run` gives
+/// the output and the tape checksum of a run, and should give each the
+/// same input. Runs that both fail agree if they fail with the same
+/// variant of `BfError`, since the pcs differ between compilations, but a
+/// step limit in either fails the check with that error, as a run cut
+/// short cannot be compared.
+///
+/// ```
+/// use brainfuck::{verify, Compiler, Engine, Program};
+///
+/// let run = | program: &Program | {
+///     let mut output = Vec::new();
+///     let mut engine = Engine::< i8 >::new( program, 16 ).with_io( std::io::empty(), &mut output );
+///     engine.run()?;
+///     let checksum = engine.tape_checksum();
+///     drop( engine );
+///     Ok( ( output, checksum ) )
+/// };
+/// assert!( verify::verify( &Compiler::new().opt_level( 0 ), &Compiler::new(), "++[->+++<]>.", run ).is_ok() );
+/// ```
+pub fn verify( reference: &Compiler, candidate: &Compiler, src: impl AsRef< [ u8 ] >, mut run: impl FnMut( &Program ) -> Result< ( Vec< u8 >, u64 ), BfError > ) -> Result< (), BfError > {
+    let src = src.as_ref();
+    let expected = run( &reference.compile( src )? );
+    let actual = run( &candidate.compile( src )? );
+    let message = match ( expected, actual ) {
+        ( Err( err @ BfError::StepLimitExceeded { .. } ), _ ) | ( _, Err( err @ BfError::StepLimitExceeded { .. } ) ) => return Err( err ),
+        ( Ok( ( expected, _ ) ), Ok( ( actual, _ ) ) ) if expected != actual => output_difference( &expected, &actual ),
+        ( Ok( ( _, expected ) ), Ok( ( _, actual ) ) ) if expected != actual =>
+            format!( "the tape checksum is {:016x} rather than {:016x}", actual, expected ),
+        ( Ok( _ ), Ok( _ ) ) => return Ok(()),
+        ( Err( expected ), Err( actual ) ) if mem::discriminant( &expected ) == mem::discriminant( &actual ) => return Ok(()),
+        ( Err( expected ), Err( actual ) ) => format!( "the run fails with \"{}\" rather than \"{}\"", actual, expected ),
+        ( Ok( _ ), Err( actual ) ) => format!( "the run fails with \"{}\" rather than finishing", actual ),
+        ( Err( expected ), Ok( _ ) ) => format!( "the run finishes rather than failing with \"{}\"", expected ),
+    };
+    Err( BfError::Diverged { message } )
+}
+
+//  Describes where the output of the candidate first differs from that of
+//  the reference, showing a few bytes of each from there.
+fn output_difference( expected: &[ u8 ], actual: &[ u8 ] ) -> String {
+    let at = expected.iter().zip( actual ).take_while( | ( a, b ) | a == b ).count();
+    let shown = | bytes: &[ u8 ] | String::from_utf8_lossy( &bytes[ at..bytes.len().min( at + SHOWN ) ] ).into_owned();
+    format!( "the output differs from byte {}, with {:?} rather than {:?}", at, shown( actual ), shown( expected ) )
+}
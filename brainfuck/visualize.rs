@@ -0,0 +1,127 @@
+/*
+    Draws the state of an engine for the driver's --visualize mode: the
+    program counter and its opcode above a row of tape cells, with the cell
+    under the head shown in reverse video. Frames start by clearing the
+    terminal with ANSI escape codes, so they are only meant for a terminal.
+    Also formats the cells of the tape in the form that --dump-format
+    chooses, for `#`, the debugger and --dump-tape-on-halt.
+*/
+
+use std::ops::Range;
+
+use crate::cell::{Cell, Shown};
+use crate::engine::Engine;
+
+const HIGHLIGHT: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+const CLEAR: &str = "\x1b[H\x1b[2J";
+
+/// How a dump of the tape shows each cell.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// As a hex number with a digit for every four bits of the cell.
+    Hex,
+    /// As a decimal number, which is signed unless the engine has
+    /// `unsigned_cells`.
+    #[default]
+    Dec,
+    /// As the character of its low byte if that is printable ASCII, and
+    /// as `.` if not.
+    Ascii,
+}
+
+impl std::str::FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str( s: &str ) -> Result< Self, Self::Err > {
+        match s {
+            "hex" => Ok( DumpFormat::Hex ),
+            "dec" => Ok( DumpFormat::Dec ),
+            "ascii" => Ok( DumpFormat::Ascii ),
+            _ => Err( format!( "Unknown dump format '{}' (expected hex, dec or ascii)", s ) ),
+        }
+    }
+}
+
+/// Formats the cells of `memory` in `range` in `format`, separated by
+/// spaces, with the one at `head`, if it is in the range, in brackets.
+/// Decimal cells are read as unsigned if `unsigned`.
+///
+/// ```
+/// use brainfuck::visualize::{format_tape, DumpFormat};
+///
+/// let tape = [ 0i8, 72, -1, 105 ];
+/// assert_eq!( format_tape( &tape, 0..3, DumpFormat::Dec, 1, false ), "0 [72] -1" );
+/// assert_eq!( format_tape( &tape, 1..4, DumpFormat::Hex, 1, false ), "[48] ff 69" );
+/// ```
+pub fn format_tape< C: Cell >( memory: &[ C ], range: Range< usize >, format: DumpFormat, head: usize, unsigned: bool ) -> String {
+    let digits = std::mem::size_of::< C >() * 2;
+    let mask = u64::MAX >> ( 64 - 4 * digits );
+    let cells: Vec< String > = memory[ range.clone() ].iter().zip( range ).map( | ( &cell, addr ) | {
+        let shown = match format {
+            DumpFormat::Hex => format!( "{:0w$x}", cell.to_i64() as u64 & mask, w = digits ),
+            DumpFormat::Dec => Shown( cell, unsigned ).to_string(),
+            DumpFormat::Ascii => match cell.to_byte() {
+                byte @ 0x20..=0x7e => ( byte as char ).to_string(),
+                _ => ".".to_string(),
+            },
+        };
+        if addr == head { format!( "[{}]", shown ) } else { shown }
+    } ).collect();
+    cells.join( " " )
+}
+
+/// Formats `cells` as a row of right-aligned numbers, highlighting the
+/// one at index `head`, and reading them as unsigned if `unsigned`.
+///
+/// ```
+/// assert_eq!( brainfuck::visualize::render_tape( &[ 0i8, 65, -1 ], 1, false ), "   0 \x1b[7m  65\x1b[0m   -1" );
+/// ```
+pub fn render_tape< C: Cell >( cells: &[ C ], head: usize, unsigned: bool ) -> String {
+    let row: Vec< String > = cells.iter().map( | &cell | Shown( cell, unsigned ) ).enumerate().map( | ( i, cell ) | {
+        if i == head { format!( "{}{:>4}{}", HIGHLIGHT, cell, RESET ) } else { format!( "{:>4}", cell ) }
+    } ).collect();
+    row.join( " " )
+}
+
+/// A whole frame: clears the terminal and shows the pc, its opcode and
+/// the `width` cells around the head.
+pub fn frame< C: Cell >( e: &Engine< C >, width: usize ) -> String {
+    let head = e.origin().saturating_add_signed( e.loc() );
+    let start = head.saturating_sub( width / 2 );
+    let end = start.saturating_add( width ).min( e.tape().len() );
+    let name = e.opcode_name_at( e.pc() ).unwrap_or( "?" );
+    format!( "{}pc={} {} loc={}\n{}\n", CLEAR, e.pc(), name, e.loc(), render_tape( &e.tape()[ start..end ], head - start, e.unsigned ) )
+}
+
+/// A dump of the tape in `format_tape` rows of 16 bytes, each after the
+/// address of its first cell, following a line giving the head. Only the
+/// rows from the first cell that is not zero, or the head if that comes
+/// first, to the last such cell are shown, and read as unsigned if
+/// `unsigned`.
+///
+/// ```
+/// use brainfuck::visualize::{dump_tape, DumpFormat};
+///
+/// let mut tape = [ 0i8; 20 ];
+/// tape[ 1 ] = b'H' as i8;
+/// tape[ 2 ] = b'i' as i8;
+/// assert_eq!( dump_tape( &tape, 17, DumpFormat::Hex, false ), concat!(
+///     "head=17\n",
+///     "00000000  00 48 69 00 00 00 00 00 00 00 00 00 00 00 00 00\n",
+///     "00000010  00 [00] 00 00\n",
+/// ) );
+/// assert_eq!( dump_tape( &tape, 1, DumpFormat::Ascii, false ), "head=1\n00000000  . [H] i . . . . . . . . . . . . .\n" );
+/// ```
+pub fn dump_tape< C: Cell >( memory: &[ C ], head: usize, format: DumpFormat, unsigned: bool ) -> String {
+    let per_row = 16 / std::mem::size_of::< C >();
+    let used = memory.iter().enumerate().filter( | ( _, &cell ) | cell != C::ZERO ).map( | ( i, _ ) | i );
+    let first = used.clone().next().unwrap_or( head ).min( head );
+    let last = used.last().unwrap_or( head ).max( head );
+    let mut out = format!( "head={}\n", head );
+    for start in ( first / per_row * per_row..=last.min( memory.len().saturating_sub( 1 ) ) ).step_by( per_row ) {
+        let row = format_tape( memory, start..( start + per_row ).min( memory.len() ), format, head, unsigned );
+        out.push_str( &format!( "{:08x}  {}\n", start, row ) );
+    }
+    out
+}
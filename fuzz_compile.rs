@@ -0,0 +1,109 @@
+/*
+    Compiles and runs random Brainfuck sources to check that the library
+    reports malformed input as a `BfError` and never panics. Each source is
+    mostly made of commands, so that loops of interesting depth turn up,
+    and is compiled at a random level of optimization. A compiled program
+    is also run for a bounded number of steps and its binary form is
+    corrupted and loaded back, along with a binary made of random slots,
+    since that is the other way that untrusted input reaches the engine.
+
+    Usage: fuzz_compile [ITERATIONS [SEED]]
+*/
+
+use std::panic;
+use std::process::ExitCode;
+
+use brainfuck::{Compiler, Engine, Program};
+
+const ALPHABET: &[ u8 ] = b"+-<>[].,#[]+-  x\n";
+
+//  A xorshift generator, which is plenty for picking test inputs.
+struct Rng( u64 );
+
+impl Rng {
+
+    fn next( &mut self ) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below( &mut self, n: usize ) -> usize {
+        ( self.next() % n as u64 ) as usize
+    }
+
+}
+
+fn random_source( rng: &mut Rng ) -> String {
+    let len = rng.below( 64 );
+    ( 0..len ).map( | _ | ALPHABET[ rng.below( ALPHABET.len() ) ] as char ).collect()
+}
+
+//  A binary of a few random slots with small operands, so that the jumps
+//  often stay within the program, ending in a HALT (id 22) and a table of
+//  MULADD targets. Operands below 128 take one byte as a varint.
+fn random_binary( rng: &mut Rng ) -> Vec< u8 > {
+    let mut bytes = b"BFC\0\x02".to_vec();
+    for _ in 0..rng.below( 12 ) {
+        bytes.push( rng.below( 40 ) as u8 );
+        bytes.push( rng.below( 12 ) as u8 );
+    }
+    bytes.extend( [ 22, 0 ] );
+    let muladds = rng.below( 3 );
+    bytes.push( muladds as u8 );
+    for _ in 0..muladds {
+        let targets = rng.below( 3 );
+        bytes.push( targets as u8 );
+        for _ in 0..targets * 2 {
+            bytes.push( rng.below( 128 ) as u8 );
+        }
+    }
+    bytes
+}
+
+fn run( program: &Program ) {
+    let mut output = Vec::new();
+    let mut engine = Engine::< i8 >::new( program, 16 ).max_steps( 10_000 ).with_io( std::io::empty(), &mut output ).debug_output( std::io::sink() );
+    let _ = engine.run();
+}
+
+//  Compiles and runs `src`, and then loads and runs a corrupted copy of its
+//  binary form. Errors are expected; only a panic is a failure.
+fn exercise( src: &str, level: u8, wrap: bool, corruption: ( usize, u8 ), binary: &[ u8 ] ) {
+    let compiler = Compiler::new().opt_level( level ).wrap( wrap ).debug_op( false );
+    if let Ok( program ) = compiler.compile( src ) {
+        run( &program );
+        let mut bytes = program.to_bytes();
+        let ( at, byte ) = corruption;
+        let at = at % bytes.len();
+        bytes[ at ] = byte;
+        if let Ok( program ) = Program::from_bytes( &bytes ) {
+            run( &program );
+        }
+    }
+    if let Ok( program ) = Program::from_bytes( binary ) {
+        run( &program );
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip( 1 );
+    let iterations = args.next().and_then( | n | n.parse().ok() ).unwrap_or( 10_000 );
+    let seed = args.next().and_then( | n | n.parse().ok() ).unwrap_or( 0x2545_f491_4f6c_dd1d );
+    let mut rng = Rng( seed | 1 );
+    panic::set_hook( Box::new( | _ | {} ) );
+    for i in 0..iterations {
+        let src = random_source( &mut rng );
+        let level = rng.below( 4 ) as u8;
+        let wrap = rng.below( 2 ) == 0;
+        let corruption = ( rng.below( 1 << 16 ), rng.next() as u8 );
+        let binary = random_binary( &mut rng );
+        if panic::catch_unwind( || exercise( &src, level, wrap, corruption, &binary ) ).is_err() {
+            eprintln!( "Panicked on iteration {}: {:?} (--opt={}, wrap {}, corrupting {:?}, binary {:?})", i, src, level, wrap, corruption, binary );
+            return ExitCode::FAILURE;
+        }
+    }
+    println!( "{} sources compiled without a panic", iterations );
+    ExitCode::SUCCESS
+}
@@ -1,165 +1,1352 @@
 /*
-    Brainfuck interpreter written in subroutine-threaded style.
+    Command-line driver for the subroutine-threaded Brainfuck interpreter
+    in the `brainfuck` library.
+
+    Plain arguments are Brainfuck files to run, where `-` reads the program
+    from stdin. Any file whose name ends in `.gz` is decompressed as it is
+    read, in a build with the gzip feature. `--help` lists every option
+    briefly, `--version` shows the version, and any other argument that
+    starts with `-`, other than `-` itself, is an error rather than a file.
+    The less obvious options are:
+
+        --compile FILE      write the parsed and optimized program as JSON,
+                            to the file named by --out or to stdout
+        --run FILE          run such a JSON file
+        --compile-bin FILE  likewise, but write the threaded program in a
+                            compact binary form
+        --run-bin FILE      run such a binary file
+        --decompile FILE    write a JSON file from --compile back out as
+                            Brainfuck, to the file named by --out or to
+                            stdout
+        --emit-rust FILE    write the optimized program as the source of a
+                            Rust program, with a loop for each pair of
+                            brackets, to the file named by --out or to
+                            stdout, for rustc to compile; its cells are
+                            8 bits wide and always wrap
+        --eval PROGRAM      run PROGRAM, given inline, before any files
+        --pipe FILE1 FILE2  run FILE1 and then FILE2 with FILE1's output as
+                            its input
+        --cache             save each program file compiled as FILE.cache,
+                            and use that instead while it is up to date
+        --disasm FILE       list the compiled opcodes instead of running them
+        --listing FILE      list each command of FILE beside the opcode it
+                            compiled to, and each bracket's partner
+        --dir PATH          run each .bf file in PATH in order of name, and
+                            report which failed, carrying on past them
+        --repl              read Brainfuck from stdin a line at a time and
+                            run each line, or each that closes the loops
+                            left open, on the same tape; `,` reads from
+                            stdin too, unless --input is given
+        --minimize FILE     shrink FILE, which must fail, to the fewest
+                            commands that fail with the same kind of error,
+                            and write them out; each attempt reads --input
+                            if given and stops after --max-steps, or a
+                            million steps
+        --verify FILE       run FILE both unoptimized and at --opt, on the
+                            same input from --input if given, and fail
+                            showing where they differ unless both write
+                            the same output and leave the same tape; each
+                            run stops after --max-steps, or a hundred
+                            million steps
+        --self-test         run the few programs built into the library and
+                            report which failed
+        --check FILE        check that the brackets balance and summarize
+                            the program instead of running it, as JSON
+                            with --json
+        --warn              before running each program file, other than
+                            stdin, or --eval, list likely mistakes such as
+                            loops that never end on stderr
+        --count-only FILE   count each command of FILE, its loops and how
+                            deeply they nest, instead of running it, as
+                            JSON with --json
+        --ast FILE          show the loops of FILE as a tree, with the body
+                            of each indented, instead of running it, as
+                            nested JSON with --json
+        --input FILE        read `,` from FILE rather than stdin
+        --record FILE       write each byte that `,` reads to FILE, so that
+                            --replay FILE, which is --input by another
+                            name, can give a later run the same input
+        --tee FILE          write everything that `.` writes to FILE as well
+                            as to stdout
+        --strip-cr          make `,` skip carriage returns in the input
+        --null-io           discard everything `.` writes and give `,`
+                            zeros for ever, without touching stdin or
+                            stdout, for timing a program's computation
+        --unsigned          show cells as unsigned numbers in traces, dumps
+                            and the debugger
+        --ascii-only        make `.` write bytes other than printable ASCII,
+                            newline and tab as escapes like \xC8
+        --bang-input        treat everything after the first `!` in a
+                            program as the input for `,`
+        --init-tape FILE    load the bytes of FILE onto the tape from cell 0
+        --start-cell N      start the data pointer at cell N, which must be
+                            on the tape, rather than cell 0
+        --fill BYTE         start every cell of the tape at BYTE, not zero
+        --fill-pattern LIST start the cells at the bytes of LIST, such as
+                            1,2,3, repeated across the tape
+        --protect START:END fail on a write to cells START up to but not
+                            including END, which the program may still read
+        --tape=MODE         bounded, grow-right, bidirectional or wrap, where
+                            the pointer wraps around a tape of fixed size;
+                            a growing tape is limited by --max-cells, which
+                            on its own implies grow-right; or sparse, which
+                            stores only the cells that are not zero, for a
+                            program that moves far but touches few cells,
+                            and which ignores the options that report on or
+                            limit a run
+        --opt=N             optimize at level 0 (not at all) to 3 (fully,
+                            the default)
+        --opt-report        before running each program file, other than
+                            stdin, or --eval, show on stderr how many
+                            instructions each optimization pass left
+        --cell-bits=N       use 8 (the default), 16 or 32-bit cells; `.`
+                            writes the low byte of a cell
+        --byte-cells        use unsigned 8-bit cells, from 0 to 255, where
+                            `+` and `-` stop at either end rather than
+                            wrapping, for --overflow=saturate on cells that
+                            `.` and `,` copy as they are
+        --wide-io           make `.` write every byte of a cell wider than 8
+                            bits and `,` read as many, in the order given by
+                            --endian=ORDER, little (the default) or big
+        --overflow=MODE     what `+` and `-` do past the ends of a signed
+                            cell: wrap (the default), saturate, or error,
+                            which stops the program; --wrap and --no-wrap
+                            are short for wrap and saturate
+        --include           replace each line of a program file that starts
+                            with #include FILE by the source of FILE, found
+                            relative to the file with the line
+        --comment-char C    ignore the rest of a line after C, even commands
+        --strict            reject characters other than commands and
+                            whitespace, rather than ignoring them
+        --doc-comments      show the prose at the top of a program, before
+                            its first command, under --check
+        --enable-rand       make `?` store a random byte in the current cell,
+                            from a seed given by --seed N or the clock
+        --enable-ext        make `:` write the current cell as a decimal
+                            number and a space, and `@` halt the program
+        --enable-assert     make `=` followed by a number, such as =65, fail
+                            unless the current cell holds that number
+        --multi-tape        make `%` switch between two tapes, each with its
+                            own pointer
+        --no-debug-op       treat `#` as a comment, which is the default in
+                            release builds; --debug-op makes it dump the
+                            cells around the pointer to stderr
+        --exit-cell         exit with the low byte of the final cell
+        --dispatch=MODE     threaded (the default, also called subroutine),
+                            where a loop calls each opcode in turn; direct,
+                            where each opcode fetches the next; switch,
+                            which dispatches with a `match`; or token,
+                            which looks up each opcode stored as a byte
+        --visualize         draw the tape on stderr as the program runs,
+                            every --viz-interval N steps (default 1)
+        --dump-format=FMT   show the cells of dumps as hex, dec or ascii,
+                            for `#` and the debugger, which default to dec,
+                            and --dump-tape-on-halt, which defaults to hex
+        --dump-tape-on-halt when the program halts, show the head and a
+                            dump of the used part of the tape on stderr
+        --diff-tape         when the program halts, list the cells that it
+                            changed on stderr, as ADDR: OLD -> NEW
+        --checksum          when the program halts, show a hash of the tape
+                            on stderr, to compare with another run
+        --progress          report the steps, pc and time taken on stderr
+                            every --progress-interval N steps (default
+                            10000000)
+        --trace-json FILE   write a line of JSON to FILE for each opcode
+                            dispatched, giving the step, pc, opcode, head
+                            and current cell
+        --profile-loops     count the iterations of each loop that runs, and
+                            show them on stderr beside the position of its
+                            `[`, hottest first
+        --coverage          when the program stops, report the share of its
+                            instructions that ran, and where each of the
+                            rest was compiled from, on stderr
+        --heatmap N         count the reads and writes of each cell, and
+                            show the N most used cells on stderr
+        --report-extent     show the lowest and highest cells that were
+                            read or written on stderr, which is how much
+                            of the tape the program needs
+        --loop-budget N     fail if any loop runs more than N iterations
+                            each time it is entered, at the loop's `[`
+        --max-output N      fail rather than write more than N bytes, which
+                            with --max-steps keeps an untrusted program in
+                            check
+        --repeat N          run each program N times, each on a fresh tape,
+                            and report the shortest, median and longest
+                            times and the steps taken in all
+        --no-exit           write a line of dashes between the outputs of
+                            the programs given, each of which ends at its
+                            HALT, the final one or an `@`, before the next
+                            one runs
+        --time              report the run time and the opcodes dispatched
+        --deterministic     report a cost rather than a time, which counts
+                            the opcodes dispatched with `[` and `]` double,
+                            and so is the same on every machine
+        --dump-state FILE   save the engine state if --max-steps is reached
+                            or the run is interrupted
+        --on-interrupt-dump on Ctrl-C, stop and show where the program was
+        --load-state FILE   resume from such a state
 */
 
+use std::collections::hash_map::DefaultHasher;
 use std::env;
-use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::{prelude::*, BufReader};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-type OpCode = fn( &mut Engine ) -> ();
+use brainfuck::{check, debugger, include, ir, minimize, repl, selftest, sparse, verify, visualize, BfError, Cell, Compiler, Dispatch, DumpFormat, Engine, EngineState, EofPolicy, Endian, HaltReason, Overflow, Program, StepResult, TapeMode, MEMORY_SIZE};
 
-#[derive(Copy, Clone)]
-union InstructionField {
-    opcode: OpCode,
-    operand: usize,
+enum Action {
+    Run( String ),
+    Compile( String ),
+    RunJson( String ),
+    Disasm( String ),
+    Listing( String ),
+    Eval( String ),
+    CompileBin( String ),
+    RunBin( String ),
+    Pipe( String, String ),
+    Check( String ),
+    CountOnly( String ),
+    Ast( String ),
+    Dir( String ),
+    Decompile( String ),
+    EmitRust( String ),
+    Minimize( String ),
+    Verify( String ),
+    SelfTest,
+    Repl,
+    Help,
+    Version,
 }
 
-const MEMORY_SIZE: usize = 30000;
+//  The version that `--version` shows.
+const VERSION: &str = "0.1.0";
+
+//  What `--help` shows, which lists every option.
+const USAGE: &str = "\
+Usage: rust_threading_demo [OPTION]... [FILE]...
+Run each Brainfuck FILE in turn, where - reads the program from stdin.
+
+Actions:
+  --eval PROGRAM          run PROGRAM, given inline, before any files
+  --compile FILE          write FILE, parsed and optimized, as JSON
+  --run FILE              run such a JSON file
+  --decompile FILE        write such a JSON file back out as Brainfuck
+  --compile-bin FILE      write FILE in the compact binary form
+  --run-bin FILE          run such a binary file
+  --emit-rust FILE        write FILE as the source of a Rust program
+  --pipe FILE1 FILE2      run FILE2 with the output of FILE1 as its input
+  --disasm FILE           list the compiled opcodes of FILE
+  --listing FILE          list each command of FILE beside its opcode
+  --check FILE            check the brackets of FILE and summarize it
+  --count-only FILE       count the commands and loops of FILE
+  --ast FILE              show the loops of FILE as a tree
+  --dir PATH              run each .bf file in PATH
+  --minimize FILE         shrink FILE, which fails, to the fewest commands
+  --verify FILE           check that optimizing FILE does not change it
+  --repl                  read and run Brainfuck a line at a time
+  --self-test             run the programs built into the library
+  --help, -h              show this help
+  --version               show the version
+
+Compiling:
+  --opt=N                 optimize at level 0 to 3 (default 3)
+  --opt-report            show what each optimization pass did
+  --cell-bits=N           use 8, 16 or 32-bit cells (default 8)
+  --byte-cells            use cells from 0 to 255 that saturate
+  --overflow=MODE         wrap, saturate or error past the ends of a cell
+  --wrap, --no-wrap       short for --overflow=wrap and --overflow=saturate
+  --eof=POLICY            nochange, zero or neg-one at the end of input
+  --include               splice in the files named by #include lines
+  --comment-char C        ignore the rest of a line after C
+  --strict                reject characters other than commands
+  --doc-comments          show the prose before the first command
+  --bang-input            use everything after the first ! as the input
+  --enable-rand           make ? store a random byte
+  --seed N                seed the random bytes of ?
+  --enable-ext            make : write a number and @ halt
+  --enable-assert         make =N fail unless the cell holds N
+  --multi-tape            make % switch between two tapes
+  --debug-op, --no-debug-op
+                          make # dump the tape, or treat it as a comment
+  --cache                 save and reuse each compiled program file
+  --out FILE              write --compile, --compile-bin, --decompile and
+                          --emit-rust to FILE
+  --json                  write --check, --count-only and --ast as JSON
+  --warn                  list likely mistakes before running
+
+Tape:
+  --cells N               the size of the tape
+  --tape=MODE             bounded, grow-right, bidirectional, wrap or sparse
+  --max-cells N           the limit on a growing tape
+  --program-size N        the limit on the size of a program
+  --init-tape FILE        load the bytes of FILE onto the tape
+  --start-cell N          start the data pointer at cell N
+  --fill BYTE             start every cell at BYTE
+  --fill-pattern LIST     start the cells at the bytes of LIST, repeated
+  --protect START:END     fail on a write to cells START to END
+  --load-state FILE       resume from a saved state
+  --dump-state FILE       save the state when the run is cut short
+
+Input and output:
+  --input FILE            read , from FILE rather than stdin
+  --record FILE           save the bytes that , reads to FILE
+  --replay FILE           read , from a file saved by --record
+  --tee FILE              copy everything . writes to FILE
+  --strip-cr              skip carriage returns in the input
+  --null-io               discard the output and read zeros
+  --ascii-only            write unprintable bytes as escapes
+  --wide-io               read and write every byte of a wide cell
+  --endian=ORDER          little or big, for --wide-io
+  --unsigned              show cells as unsigned numbers
+  --exit-cell             exit with the low byte of the final cell
+
+Running:
+  --dispatch=MODE         threaded, direct, switch or token
+  --max-steps N           stop after N steps
+  --max-output N          fail rather than write more than N bytes
+  --loop-budget N         fail if a loop runs more than N iterations
+  --repeat N              run each program N times and report the times
+  --no-exit               run each program in turn, separating their output
+  --debug                 step through the program in a debugger
+  --on-interrupt-dump     on Ctrl-C, show where the program was
+
+Reports:
+  --trace                 show each opcode as it runs
+  --trace-json FILE       write a line of JSON to FILE for each opcode
+  --profile               count the opcodes dispatched
+  --profile-loops         count the iterations of each loop
+  --coverage              report the instructions that never ran
+  --heatmap N             show the N most used cells
+  --report-extent         show the lowest and highest cells used
+  --time                  report the run time and the steps taken
+  --deterministic         report a cost rather than a time
+  --progress              report progress as the program runs
+  --progress-interval N   every N steps (default 10000000)
+  --visualize             draw the tape as the program runs
+  --viz-interval N        every N steps (default 1)
+  --dump-format=FMT       show dumped cells as hex, dec or ascii
+  --dump-tape-on-halt     show the used part of the tape on halting
+  --diff-tape             list the cells changed on halting
+  --checksum              show a hash of the tape on halting
+";
 
-struct Engine {
-    program : [ InstructionField; MEMORY_SIZE ],
-    pc : usize,
-    memory : [ i8; 30000 ],
-    loc : usize,
+//  The default limit on a growing tape.
+const MAX_CELLS: usize = 1 << 24;
+
+//  What --no-exit writes between the outputs of two programs.
+const SEPARATOR: &str = "\n--------\n";
+
+//  The steps that each attempt of --minimize may take without --max-steps.
+const MINIMIZE_STEPS: u64 = 1_000_000;
+
+//  The steps that each run of --verify may take without --max-steps.
+const VERIFY_STEPS: u64 = 100_000_000;
+
+//  Parses the value of a size option, falling back to the default
+//  when it is missing, malformed or zero.
+fn parse_size( arg: Option< String > ) -> usize {
+    arg.and_then( | a | a.parse().ok() ).filter( | &n | n > 0 ).unwrap_or( MEMORY_SIZE )
 }
 
-#[allow(non_snake_case)]
-fn INCR( mut e : &mut Engine ) {
-    e.memory[ e.loc ] += 1;
-    e.pc += 1;
+//  Parses the value of an option such as `--eof=zero`.
+fn parse_choice< T: FromStr< Err = String > >( value: &str ) -> Result< T, BfError > {
+    value.parse().map_err( | message | BfError::InvalidArgument { message } )
 }
 
-#[allow(non_snake_case)]
-fn DECR( mut e : &mut Engine ) {
-    // e.memory[ e.loc ] = e.memory[ e.loc ].saturating_sub( 1 );
-    e.memory[ e.loc ] -= 1;
-    e.pc += 1;
+//  A half-open range of cells written START:END.
+fn parse_range( range: &str ) -> Result< Range< usize >, BfError > {
+    let bounds = range.split_once( ':' ).and_then( | ( start, end ) | Some( start.parse().ok()?..end.parse().ok()? ) );
+    bounds.ok_or_else( || BfError::InvalidArgument { message: format!( "Invalid range '{}' (expected START:END)", range ) } )
 }
 
-#[allow(non_snake_case)]
-fn RIGHT( mut e : &mut Engine ) {
-    e.loc += 1;
-    e.pc += 1;
+//  A list of bytes written 1,2,3.
+fn parse_bytes( list: &str ) -> Result< Vec< u8 >, BfError > {
+    let bytes: Option< Vec< u8 > > = list.split( ',' ).map( | byte | byte.trim().parse().ok() ).collect();
+    bytes.ok_or_else( || BfError::InvalidArgument { message: format!( "Invalid list of bytes '{}' (expected numbers from 0 to 255, such as 1,2,3)", list ) } )
 }
 
-#[allow(non_snake_case)]
-fn LEFT( mut e : &mut Engine ) {
-    e.loc -= 1;
-    e.pc += 1;
+//  The settings that apply to every program run.
+struct Options {
+    overflow: Overflow,
+    cells: usize,
+    tape: Option< TapeMode >,
+    sparse: bool,
+    max_cells: Option< usize >,
+    program_size: usize,
+    max_steps: Option< u64 >,
+    max_output: Option< u64 >,
+    loop_budget: Option< u64 >,
+    protect: Option< Range< usize > >,
+    eof: EofPolicy,
+    trace: bool,
+    //  Shared by every engine, so that the runs of --dir follow on.
+    trace_json: Option< fs::File >,
+    tee: Option< fs::File >,
+    record: Option< fs::File >,
+    profile: bool,
+    profile_loops: bool,
+    coverage: bool,
+    debug: bool,
+    exit_cell: bool,
+    dispatch: Dispatch,
+    time: bool,
+    deterministic: bool,
+    visualize: bool,
+    viz_interval: u64,
+    dump_tape_on_halt: bool,
+    dump_format: Option< DumpFormat >,
+    diff_tape: bool,
+    checksum: bool,
+    repeat: Option< usize >,
+    no_exit: bool,
+    include: bool,
+    progress: bool,
+    json: bool,
+    warn: bool,
+    opt_report: bool,
+    comment_char: Option< char >,
+    strict: bool,
+    enable_rand: bool,
+    enable_ext: bool,
+    enable_assert: bool,
+    doc_comments: bool,
+    multi_tape: bool,
+    heatmap: Option< usize >,
+    report_extent: bool,
+    seed: Option< u64 >,
+    progress_interval: u64,
+    dump_state: Option< String >,
+    load_state: Option< String >,
+    input: Option< String >,
+    strip_cr: bool,
+    null_io: bool,
+    ascii_only: bool,
+    wide_io: bool,
+    endian: Endian,
+    unsigned: bool,
+    bang_input: bool,
+    cache: bool,
+    cell_bits: u32,
+    byte_cells: bool,
+    debug_op: Option< bool >,
+    opt_level: u8,
+    init_tape: Option< Vec< u8 > >,
+    //  Repeated across the tape before --init-tape is copied on.
+    fill: Option< Vec< u8 > >,
+    start_cell: Option< usize >,
+    on_interrupt_dump: bool,
+    out: Option< String >,
 }
 
-#[allow(non_snake_case)]
-fn OPEN( mut e : &mut Engine ) {
-    if e.memory[ e.loc ] == 0 {
-        unsafe {
-            e.pc = e.program[ e.pc + 1 ].operand;
+fn parse_args() -> Result< ( Options, Vec< Action > ), BfError > {
+    let mut options = Options {
+        overflow: Overflow::Wrap,
+        cells: MEMORY_SIZE,
+        tape: None,
+        sparse: false,
+        max_cells: None,
+        program_size: MEMORY_SIZE,
+        max_steps: None,
+        max_output: None,
+        loop_budget: None,
+        protect: None,
+        eof: EofPolicy::NoChange,
+        trace: false,
+        trace_json: None,
+        tee: None,
+        record: None,
+        profile: false,
+        profile_loops: false,
+        coverage: false,
+        debug: false,
+        exit_cell: false,
+        dispatch: Dispatch::Threaded,
+        time: false,
+        deterministic: false,
+        visualize: false,
+        viz_interval: 1,
+        dump_tape_on_halt: false,
+        dump_format: None,
+        diff_tape: false,
+        checksum: false,
+        repeat: None,
+        no_exit: false,
+        include: false,
+        progress: false,
+        json: false,
+        warn: false,
+        opt_report: false,
+        comment_char: None,
+        strict: false,
+        enable_rand: false,
+        enable_ext: false,
+        enable_assert: false,
+        doc_comments: false,
+        multi_tape: false,
+        heatmap: None,
+        report_extent: false,
+        seed: None,
+        progress_interval: 10_000_000,
+        dump_state: None,
+        load_state: None,
+        input: None,
+        strip_cr: false,
+        null_io: false,
+        ascii_only: false,
+        wide_io: false,
+        endian: Endian::Little,
+        unsigned: false,
+        bang_input: false,
+        cache: false,
+        cell_bits: 8,
+        byte_cells: false,
+        debug_op: None,
+        opt_level: 3,
+        init_tape: None,
+        fill: None,
+        start_cell: None,
+        on_interrupt_dump: false,
+        out: None,
+    };
+    let mut actions = Vec::<Action>::new();
+    let mut evals = Vec::<Action>::new();
+    let mut args = env::args().skip( 1 );
+    while let Some( arg ) = args.next() {
+        match arg.as_str() {
+            //  Either stops the command line being read any further.
+            "--help" | "-h" => return Ok( ( options, vec![ Action::Help ] ) ),
+            "--version" => return Ok( ( options, vec![ Action::Version ] ) ),
+            "--wrap" => options.overflow = Overflow::Wrap,
+            "--no-wrap" => options.overflow = Overflow::Saturate,
+            "--cells" => options.cells = parse_size( args.next() ),
+            "--max-cells" => options.max_cells = Some( parse_size( args.next() ) ),
+            "--program-size" => options.program_size = parse_size( args.next() ),
+            "--max-steps" => options.max_steps = args.next().and_then( | a | a.parse().ok() ),
+            "--max-output" => options.max_output = args.next().and_then( | a | a.parse().ok() ),
+            "--loop-budget" => options.loop_budget = args.next().and_then( | a | a.parse().ok() ),
+            "--trace" => options.trace = true,
+            "--trace-json" => if let Some( file ) = args.next() {
+                options.trace_json = Some( fs::File::create( file )? );
+            },
+            "--tee" => if let Some( file ) = args.next() {
+                options.tee = Some( fs::File::create( file )? );
+            },
+            "--profile" => options.profile = true,
+            "--profile-loops" => options.profile_loops = true,
+            "--coverage" => options.coverage = true,
+            "--debug" => options.debug = true,
+            "--exit-cell" => options.exit_cell = true,
+            "--time" => options.time = true,
+            "--deterministic" => options.deterministic = true,
+            "--visualize" => options.visualize = true,
+            "--dump-tape-on-halt" => options.dump_tape_on_halt = true,
+            "--diff-tape" => options.diff_tape = true,
+            "--checksum" => options.checksum = true,
+            "--no-exit" => options.no_exit = true,
+            "--repeat" => options.repeat = args.next().and_then( | a | a.parse().ok() ).filter( | &n | n > 0 ),
+            "--include" => options.include = true,
+            "--progress" => options.progress = true,
+            "--progress-interval" => options.progress_interval = args.next().and_then( | a | a.parse().ok() ).filter( | &n | n > 0 ).unwrap_or( 10_000_000 ),
+            "--viz-interval" => options.viz_interval = args.next().and_then( | a | a.parse().ok() ).filter( | &n | n > 0 ).unwrap_or( 1 ),
+            "--debug-op" => options.debug_op = Some( true ),
+            "--no-debug-op" => options.debug_op = Some( false ),
+            "--out" => options.out = args.next(),
+            "--input" | "--replay" => options.input = args.next(),
+            "--record" => if let Some( file ) = args.next() {
+                options.record = Some( fs::File::create( file )? );
+            },
+            "--strip-cr" => options.strip_cr = true,
+            "--null-io" => options.null_io = true,
+            "--ascii-only" => options.ascii_only = true,
+            "--wide-io" => options.wide_io = true,
+            "--byte-cells" => {
+                options.byte_cells = true;
+                options.overflow = Overflow::Saturate;
+            },
+            "--unsigned" => options.unsigned = true,
+            "--bang-input" => options.bang_input = true,
+            "--cache" => options.cache = true,
+            "--init-tape" => if let Some( file ) = args.next() {
+                options.init_tape = Some( fs::read( file )? );
+            },
+            "--start-cell" => options.start_cell = args.next().and_then( | a | a.parse().ok() ),
+            "--fill" => if let Some( byte ) = args.next() {
+                let byte = byte.parse().map_err( | _ | BfError::InvalidArgument { message: format!( "Invalid byte '{}' (expected 0 to 255)", byte ) } )?;
+                options.fill = Some( vec![ byte ] );
+            },
+            "--fill-pattern" => if let Some( list ) = args.next() {
+                options.fill = Some( parse_bytes( &list )? );
+            },
+            "--protect" => if let Some( range ) = args.next() {
+                options.protect = Some( parse_range( &range )? );
+            },
+            "--on-interrupt-dump" => options.on_interrupt_dump = true,
+            "--dump-state" => options.dump_state = args.next(),
+            "--load-state" => options.load_state = args.next(),
+            "--compile" => actions.extend( args.next().map( Action::Compile ) ),
+            "--run" => actions.extend( args.next().map( Action::RunJson ) ),
+            "--compile-bin" => actions.extend( args.next().map( Action::CompileBin ) ),
+            "--run-bin" => actions.extend( args.next().map( Action::RunBin ) ),
+            "--pipe" => if let ( Some( first ), Some( second ) ) = ( args.next(), args.next() ) {
+                actions.push( Action::Pipe( first, second ) );
+            },
+            "--disasm" => actions.extend( args.next().map( Action::Disasm ) ),
+            "--listing" => actions.extend( args.next().map( Action::Listing ) ),
+            "--dir" => actions.extend( args.next().map( Action::Dir ) ),
+            "--self-test" => actions.push( Action::SelfTest ),
+            "--repl" => actions.push( Action::Repl ),
+            "--decompile" => actions.extend( args.next().map( Action::Decompile ) ),
+            "--emit-rust" => actions.extend( args.next().map( Action::EmitRust ) ),
+            "--minimize" => actions.extend( args.next().map( Action::Minimize ) ),
+            "--verify" => actions.extend( args.next().map( Action::Verify ) ),
+            "--check" => actions.extend( args.next().map( Action::Check ) ),
+            "--count-only" => actions.extend( args.next().map( Action::CountOnly ) ),
+            "--ast" => actions.extend( args.next().map( Action::Ast ) ),
+            "--json" => options.json = true,
+            "--warn" => options.warn = true,
+            "--opt-report" => options.opt_report = true,
+            "--enable-rand" => options.enable_rand = true,
+            "--enable-ext" => options.enable_ext = true,
+            "--enable-assert" => options.enable_assert = true,
+            "--doc-comments" => options.doc_comments = true,
+            "--multi-tape" => options.multi_tape = true,
+            "--report-extent" => options.report_extent = true,
+            "--heatmap" => options.heatmap = Some( args.next().and_then( | a | a.parse().ok() ).filter( | &n | n > 0 ).unwrap_or( 10 ) ),
+            "--seed" => options.seed = args.next().and_then( | a | a.parse().ok() ),
+            "--strict" => options.strict = true,
+            "--comment-char" => options.comment_char = args.next().and_then( | a | a.chars().next() ),
+            "--eval" => evals.extend( args.next().map( Action::Eval ) ),
+            _ => if let Some( policy ) = arg.strip_prefix( "--eof=" ) {
+                options.eof = parse_choice( policy )?;
+            } else if let Some( endian ) = arg.strip_prefix( "--endian=" ) {
+                options.endian = parse_choice( endian )?;
+            } else if let Some( overflow ) = arg.strip_prefix( "--overflow=" ) {
+                options.overflow = parse_choice( overflow )?;
+            } else if let Some( format ) = arg.strip_prefix( "--dump-format=" ) {
+                options.dump_format = Some( parse_choice( format )? );
+            } else if let Some( dispatch ) = arg.strip_prefix( "--dispatch=" ) {
+                options.dispatch = parse_choice( dispatch )?;
+            } else if let Some( bits ) = arg.strip_prefix( "--cell-bits=" ) {
+                options.cell_bits = match bits {
+                    "8" => 8,
+                    "16" => 16,
+                    "32" => 32,
+                    _ => return Err( BfError::InvalidArgument { message: format!( "Unsupported cell size '{}' (expected 8, 16 or 32)", bits ) } ),
+                };
+            } else if let Some( level ) = arg.strip_prefix( "--opt=" ) {
+                options.opt_level = match level.parse() {
+                    Ok( level ) if level <= 3 => level,
+                    _ => return Err( BfError::InvalidArgument { message: format!( "Unknown optimization level '{}' (expected 0 to 3)", level ) } ),
+                };
+            } else if arg == "--tape=sparse" {
+                options.sparse = true;
+            } else if let Some( mode ) = arg.strip_prefix( "--tape=" ) {
+                options.tape = Some( parse_choice( mode )? );
+            } else if arg.len() > 1 && arg.starts_with( '-' ) {
+                return Err( BfError::InvalidArgument { message: format!( "Unknown option '{}' (see --help)", arg ) } );
+            } else {
+                actions.push( Action::Run( arg ) );
+            },
         }
+    }
+    if let Some( cell ) = options.start_cell.filter( | &cell | cell >= options.cells ) {
+        return Err( BfError::InvalidArgument { message: format!( "Start cell {} is off the end of a tape of {} cells", cell, options.cells ) } );
+    }
+    //  Inline programs run before any files.
+    evals.extend( actions );
+    Ok( ( options, evals ) )
+}
+
+//  Set by the SIGINT handler that --on-interrupt-dump installs.
+static INTERRUPTED: AtomicBool = AtomicBool::new( false );
+
+#[cfg(unix)]
+fn catch_interrupt() {
+    extern "C" fn on_interrupt( _signum: i32 ) {
+        INTERRUPTED.store( true, Ordering::Relaxed );
+    }
+    extern "C" {
+        fn signal( signum: i32, handler: extern "C" fn( i32 ) ) -> usize;
+    }
+    const SIGINT: i32 = 2;
+    //  The handler only stores to an atomic, which is safe in a signal
+    //  handler.
+    unsafe {
+        signal( SIGINT, on_interrupt );
+    }
+}
+
+//  Without a portable way to catch Ctrl-C, the flag is never set.
+#[cfg(not(unix))]
+fn catch_interrupt() {}
+
+//  An engine for `program` with the settings from `options`, apart from
+//  its io and initial tape, which change from one run to the next.
+fn new_engine< 'io, C: Cell >( options: &'io Options, program: &Program ) -> Engine< 'io, C > {
+    let mut engine = Engine::< C >::new( program, options.cells ).eof( options.eof ).strip_cr( options.strip_cr ).ascii_only( options.ascii_only ).unsigned_cells( options.unsigned );
+    if options.wide_io {
+        engine = engine.wide_io( options.endian );
+    }
+    if let Some( cell ) = options.start_cell {
+        engine = engine.start_cell( cell );
+    }
+    if let Some( format ) = options.dump_format {
+        engine = engine.dump_format( format );
+    }
+    if options.enable_rand {
+        let clock = || SystemTime::now().duration_since( UNIX_EPOCH ).map_or( 0, | t | t.as_nanos() as u64 );
+        engine = engine.seed( options.seed.unwrap_or_else( clock ) );
+    }
+    let tape = match ( options.tape, options.max_cells ) {
+        ( Some( mode ), _ ) => mode,
+        ( None, Some( _ ) ) => TapeMode::GrowRight,
+        ( None, None ) => TapeMode::Bounded,
+    };
+    engine = engine.tape_mode( tape, options.max_cells.unwrap_or( MAX_CELLS ) );
+    if let Some( limit ) = options.max_steps {
+        engine = engine.max_steps( limit );
+    }
+    if let Some( limit ) = options.max_output {
+        engine = engine.max_output( limit );
+    }
+    if let Some( budget ) = options.loop_budget {
+        engine = engine.loop_budget( budget );
+    }
+    if let Some( range ) = &options.protect {
+        engine = engine.protect( range.clone() );
+    }
+    if options.trace {
+        engine = engine.trace( std::io::stderr() );
+    }
+    if let Some( file ) = &options.trace_json {
+        engine = engine.trace_json( file );
+    }
+    if options.profile || options.profile_loops || options.coverage {
+        engine = engine.profile();
+    }
+    if options.deterministic {
+        engine = engine.cost_model();
+    }
+    if options.on_interrupt_dump {
+        engine = engine.interrupt( &INTERRUPTED );
+    }
+    if options.heatmap.is_some() {
+        engine = engine.heatmap();
+    }
+    if options.report_extent {
+        engine = engine.track_extent();
+    }
+    if options.debug {
+        //  The debugger reads its commands from stdin as well.
+        engine = engine.input_buffer( 0 );
+    }
+    engine
+}
+
+//  Runs `program` on a fresh engine.
+fn run_program< C: Cell >( options: &Options, program: &Program, input: impl Read, output: impl Write ) -> Result< u8, BfError > {
+    let mut engine = prepare( options, new_engine::< C >( options, program ), input, output );
+    run_engine( options, &mut engine )
+}
+
+//  Writes everything to both of its writers, for --tee.
+struct Tee< A, B >( A, B );
+
+impl< A: Write, B: Write > Write for Tee< A, B > {
+    fn write( &mut self, buf: &[ u8 ] ) -> std::io::Result< usize > {
+        self.0.write_all( buf )?;
+        self.1.write_all( buf )?;
+        Ok( buf.len() )
+    }
+
+    fn flush( &mut self ) -> std::io::Result< () > {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+//  Writes everything read from its reader to its writer, for --record.
+struct Record< R, W >( R, W );
+
+impl< R: Read, W: Write > Read for Record< R, W > {
+    fn read( &mut self, buf: &mut [ u8 ] ) -> std::io::Result< usize > {
+        let n = self.0.read( buf )?;
+        self.1.write_all( &buf[ ..n ] )?;
+        Ok( n )
+    }
+}
+
+//  Gives `engine` the settings that are made afresh for each run.
+fn prepare< 'io, C: Cell >( options: &'io Options, mut engine: Engine< 'io, C >, input: impl Read + 'io, output: impl Write + 'io ) -> Engine< 'io, C > {
+    //  Unbuffered, so that only the bytes that `,` takes are recorded.
+    let input: Box< dyn Read + 'io > = match &options.record {
+        Some( file ) => {
+            engine = engine.input_buffer( 0 );
+            Box::new( Record( input, file ) )
+        },
+        None => Box::new( input ),
+    };
+    engine = match &options.tee {
+        Some( file ) => engine.with_io( input, Tee( output, file ) ),
+        None => engine.with_io( input, output ),
+    };
+    if let Some( pattern ) = &options.fill {
+        engine = engine.fill( pattern );
+    }
+    if let Some( bytes ) = &options.init_tape {
+        engine = engine.init_tape( bytes );
+    }
+    //  Once the program has halted and its output is out, report on the
+    //  tape it left.
+    engine = engine.on_halt( move | e | {
+        if options.dump_tape_on_halt {
+            let format = options.dump_format.unwrap_or( DumpFormat::Hex );
+            eprint!( "{}", visualize::dump_tape( e.tape(), e.origin().saturating_add_signed( e.loc() ), format, options.unsigned ) );
+        }
+        if options.diff_tape {
+            for ( loc, old, new ) in e.tape_diff() {
+                eprintln!( "{}: {} -> {}", loc, e.show( old ), e.show( new ) );
+            }
+        }
+        if options.checksum {
+            eprintln!( "Checksum: {:016x}", e.tape_checksum() );
+        }
+    } );
+    if options.progress {
+        let start = Instant::now();
+        engine = engine.progress( options.progress_interval, move | steps, pc | {
+            eprintln!( "Progress: {} steps, pc={}, {:.1}s", steps, pc, start.elapsed().as_secs_f64() );
+        } );
+    }
+    engine
+}
+
+//  The lines of stdin, read one at a time so that `,` can share stdin with
+//  an engine whose input is not buffered.
+fn stdin_lines() -> impl Iterator< Item = String > {
+    std::iter::from_fn( || {
+        let mut line = String::new();
+        match std::io::stdin().read_line( &mut line ) {
+            Ok( 0 ) | Err( _ ) => None,
+            Ok( _ ) => Some( line ),
+        }
+    } )
+}
+
+//  Runs the program already loaded into `engine` and reports on the run,
+//  returning the cell under the data pointer at the end.
+fn run_engine< C: Cell >( options: &Options, engine: &mut Engine< C > ) -> Result< u8, BfError > {
+    if let Some( file ) = &options.load_state {
+        engine.restore( &EngineState::from_json( &read_json( file )? )? )?;
+    }
+    if options.diff_tape {
+        engine.mark_tape();
+    }
+    let start = Instant::now();
+    let result = if options.debug {
+        debugger::debug( engine, stdin_lines(), std::io::stderr() )
+    } else if options.visualize {
+        run_visualized( engine, options.viz_interval )
+    } else if options.dispatch == Dispatch::Switch {
+        engine.run_switch()
+    } else if options.dispatch == Dispatch::Direct {
+        engine.run_direct()
+    } else if options.dispatch == Dispatch::Token {
+        engine.run_token()
     } else {
-        e.pc += 2;
+        engine.run()
+    };
+    let summary = engine.summarize( result );
+    if let Some( cost ) = engine.cost() {
+        eprintln!( "Cost: {}, {} steps", cost, engine.steps() );
+    } else if options.time {
+        let elapsed = start.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { engine.steps() as f64 / elapsed } else { 0.0 };
+        eprintln!( "Elapsed: {:.3}s, {} steps, {:.0} steps/s", elapsed, engine.steps(), rate );
+    }
+    if let Some( counts ) = engine.opcode_counts().filter( | _ | options.profile ) {
+        for ( name, n ) in counts {
+            eprintln!( "{:<14} {:>12}", format!( "{}:", name ), n );
+        }
+    }
+    if let Some( counts ) = engine.loop_counts().filter( | _ | options.profile_loops ) {
+        for ( pc, source, n ) in counts {
+            let at = source.map_or( String::new(), | pos | format!( " at {}", pos ) );
+            eprintln!( "Loop{} (pc={}): {} iterations", at, pc, n );
+        }
+    }
+    if let Some( coverage ) = engine.coverage().filter( | _ | options.coverage ) {
+        let covered = coverage.instructions - coverage.uncovered.len();
+        eprintln!( "Coverage: {} of {} instructions ({:.1}%)", covered, coverage.instructions, coverage.percent() );
+        for ( pc, source ) in coverage.uncovered {
+            let at = source.map_or( String::new(), | pos | format!( " at {}", pos ) );
+            eprintln!( "Never ran{} (pc={})", at, pc );
+        }
+    }
+    if let ( Some( accesses ), Some( top ) ) = ( engine.cell_accesses(), options.heatmap ) {
+        eprintln!( "{:>8} {:>12} {:>12}", "cell", "reads", "writes" );
+        for ( at, reads, writes ) in accesses.into_iter().take( top ) {
+            eprintln!( "{:>8} {:>12} {:>12}", at, reads, writes );
+        }
+    }
+    if options.report_extent {
+        match engine.extent() {
+            Some( extent ) => eprintln!( "Extent: cells {}..={}", extent.start(), extent.end() ),
+            None => eprintln!( "Extent: no cells touched" ),
+        }
+    }
+    let reason = summary.as_ref().ok().map( | summary | summary.reason );
+    if reason == Some( HaltReason::Interrupted ) {
+        eprintln!( "{}", engine.tape_window( 4 ) );
     }
+    if let ( Some( HaltReason::StepLimit { .. } | HaltReason::Interrupted ), Some( file ) ) = ( reason, &options.dump_state ) {
+        fs::write( file, engine.snapshot().to_json() )?;
+    }
+    summary?.into_result()?;
+    Ok( engine.cell( engine.loc() ).map_or( 0, Cell::to_byte ) )
 }
 
-#[allow(non_snake_case)]
-fn CLOSE( mut e : &mut Engine ) {
-    if e.memory[ e.loc ] != 0 {
-        unsafe {
-            e.pc = e.program[ e.pc + 1 ].operand;
+//  Runs the engine a step at a time, drawing it on stderr before the first
+//  step and then every `interval` steps.
+fn run_visualized< C: Cell >( engine: &mut Engine< C >, interval: u64 ) -> Result< (), BfError > {
+    eprint!( "{}", visualize::frame( engine, 16 ) );
+    while engine.step()? == StepResult::Running {
+        if engine.steps().is_multiple_of( interval ) {
+            eprint!( "{}", visualize::frame( engine, 16 ) );
         }
+    }
+    Ok(())
+}
+
+//  Opens a program file, where `-` means stdin.
+fn open_source( file: &str ) -> Result< Box< dyn Read >, BfError > {
+    if file == "-" {
+        return Ok( Box::new( std::io::stdin() ) );
+    }
+    let input = fs::File::open( file )?;
+    if file.ends_with( ".gz" ) {
+        return gunzip( file, input );
+    }
+    Ok( Box::new( input ) )
+}
+
+//  Decompresses a gzipped file as it is read, which needs flate2: build
+//  with `--cfg 'feature="gzip"' --extern flate2=...`.
+#[cfg(feature = "gzip")]
+fn gunzip( _file: &str, input: fs::File ) -> Result< Box< dyn Read >, BfError > {
+    Ok( Box::new( flate2::read::GzDecoder::new( input ) ) )
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gunzip( file: &str, _input: fs::File ) -> Result< Box< dyn Read >, BfError > {
+    Err( BfError::InvalidArgument { message: format!( "{} is gzipped, which needs a build with the gzip feature", file ) } )
+}
+
+//  Reads a source file as it is, since it need not be UTF-8.
+fn read_source( file: &str ) -> Result< Vec< u8 >, BfError > {
+    let mut src = Vec::new();
+    open_source( file )?.read_to_end( &mut src )?;
+    Ok( src )
+}
+
+//  Reads a JSON file, which must be UTF-8.
+fn read_json( file: &str ) -> Result< String, BfError > {
+    String::from_utf8( read_source( file )? ).map_err( | _ | BfError::InvalidJson { message: format!( "{} is not UTF-8", file ) } )
+}
+
+//  Reads a program file, splicing in the files it includes with --include.
+fn read_program( options: &Options, file: &str ) -> Result< Vec< u8 >, BfError > {
+    if options.include && file != "-" {
+        include::expand( Path::new( file ) )
     } else {
-        e.pc += 2;
+        read_source( file )
     }
 }
 
-#[allow(non_snake_case)]
-fn PUT( e : &mut Engine ) {
-    let ch = e.memory[ e.loc ] as u16 as u8;
-    let buf = [ ch; 1 ];
-    match std::io::stdout().write( &buf ) {
-        _ => {}
+//  Prints the warnings for `src`, which comes from `name`, on stderr,
+//  leaving out any input after a `!` with --bang-input.
+fn warn( options: &Options, compiler: &Compiler, name: &str, src: &[ u8 ] ) -> Result< (), BfError > {
+    let code = if options.bang_input { split_bang( src ).0 } else { src };
+    for warning in compiler.lint( code )? {
+        eprintln!( "{}:{}", name, warning );
     }
-    e.pc += 1;
+    Ok(())
+}
+
+//  Prints what each optimization pass did to `src`, which comes from `name`,
+//  on stderr.
+fn report_passes( options: &Options, compiler: &Compiler, name: &str, src: &[ u8 ] ) -> Result< (), BfError > {
+    let code = if options.bang_input { split_bang( src ).0 } else { src };
+    eprint!( "{}:\n{}", name, compiler.opt_report( code )? );
+    Ok(())
 }
 
-#[allow(non_snake_case)]
-fn GET( e : &mut Engine ) {
-    let mut buf = [0; 1];
-    match std::io::stdin().read_exact(&mut buf) {
-        Ok(_) => e.memory[ e.loc ] = buf[ 0 ] as i8,
-        _ => {}
+//  Splits `src` at its first `!` into the code and the input after it,
+//  which is passed on byte for byte. There is no input if there is no `!`.
+fn split_bang( src: &[ u8 ] ) -> ( &[ u8 ], Option< &[ u8 ] > ) {
+    match src.iter().position( | &byte | byte == b'!' ) {
+        Some( at ) => ( &src[ ..at ], Some( &src[ at + 1.. ] ) ),
+        None => ( src, None ),
     }
-    e.pc += 1;
 }
 
-#[allow(non_snake_case)]
-fn HALT( _e : &mut Engine ) {
-    match std::io::stdout().flush() { _ => {} }; 
-    std::process::exit( 0 );
+//  Compiles the code before the first `!` in `src` and returns what follows
+//  as the program's input, as is conventional for self-contained test
+//  programs.
+fn compile_with_input( compiler: &Compiler, src: &[ u8 ] ) -> Result< ( Program, Option< Vec< u8 > > ), BfError > {
+    let ( code, data ) = split_bang( src );
+    Ok( ( compiler.compile( code )?, data.map( < [ u8 ] >::to_vec ) ) )
 }
 
-fn load_program_from_file( filename: &String, program: &mut [ InstructionField; MEMORY_SIZE ], opcode_map: &BTreeMap< char, OpCode > ) -> Result<(), std::io::Error> {
-    let input = File::open( filename )?;
-    let reader = BufReader::new( input );
-    let mut top: usize = 0;
-    let mut indexes = Vec::<usize>::new();
-    for line in reader.lines() {
-        for ch in line?.chars() {
-            match opcode_map.get( &ch ) {
-                Some( opc ) => { 
-                    program[ top ].opcode = *opc; 
-                    top += 1 
-                },
-                None => (),
-            };
-            match &ch {
-                '[' => { 
-                    indexes.push( top );
-                    top += 1;
-                },
-                ']' => {
-                    let start = indexes.pop().expect("Unmatched closing bracket");
-                    program[ start ].operand = top + 1;
-                    program[ top ].operand = start + 1;
-                    top += 1;
-                },
-                _ => {},
+//  Compiles `file`, or loads it from FILE.cache if that was saved from the
+//  same source with the same settings and is no older than the source. The
+//  cache starts with a hash of both, and is only an optimization, so any
+//  problem reading or writing it falls back to compiling.
+fn load_cached( compiler: &Compiler, options: &Options, file: &str ) -> Result< Program, BfError > {
+    let src = read_program( options, file )?;
+    let mut hasher = DefaultHasher::new();
+    let debug_op = options.debug_op.unwrap_or( cfg!( debug_assertions ) );
+    ( &src, options.overflow, options.opt_level, debug_op, options.program_size, options.comment_char, options.strict, options.enable_rand, options.enable_ext, options.enable_assert, options.multi_tape ).hash( &mut hasher );
+    let key = hasher.finish().to_le_bytes();
+    let cache = format!( "{}.cache", file );
+    let modified = | path: &str | fs::metadata( path ).and_then( | m | m.modified() ).ok();
+    if let ( Some( source ), Some( cached ) ) = ( modified( file ), modified( &cache ) ) {
+        if cached >= source {
+            let saved = fs::read( &cache ).ok();
+            let program = saved.as_deref().and_then( | bytes | bytes.strip_prefix( key.as_slice() ) ).and_then( | bytes | Program::load( bytes ).ok() );
+            if let Some( program ) = program {
+                return Ok( program );
             }
         }
     }
-    program[ top ].opcode = HALT;
-    return Ok(())
+    let program = compiler.compile( &src )?;
+    let mut bytes = key.to_vec();
+    program.save( &mut bytes )?;
+    let _ = fs::write( &cache, bytes );
+    Ok( program )
+}
+
+//  Where `,` reads from, unless the program brings its own input.
+fn open_input( options: &Options ) -> Result< Box< dyn Read >, BfError > {
+    Ok( match &options.input {
+        _ if options.null_io => Box::new( std::io::repeat( 0 ) ),
+        Some( file ) => Box::new( fs::File::open( file )? ),
+        None => Box::new( std::io::stdin() ),
+    } )
+}
+
+//  Where a program's output goes, which is nowhere with --null-io.
+fn open_output( options: &Options ) -> Box< dyn Write > {
+    if options.null_io {
+        Box::new( std::io::sink() )
+    } else {
+        Box::new( std::io::stdout() )
+    }
 }
 
-fn run_program( e : &mut Engine ) {
-    loop {
-        unsafe {
-            let opc: OpCode = e.program[ e.pc ].opcode;
-            opc( e );
+//  Runs each `.bf` file in `dir` in order of name on a fresh engine,
+//  reporting on each and carrying on past failures. Returns how many
+//  succeeded and how many there were.
+fn run_dir< C: Cell >( options: &Options, compiler: &Compiler, dir: &str ) -> Result< ( usize, usize ), BfError > {
+    let mut files: Vec< _ > = fs::read_dir( dir )?
+        .filter_map( | entry | entry.ok().map( | entry | entry.path() ) )
+        .filter( | path | path.extension().is_some_and( | ext | ext == "bf" ) )
+        .collect();
+    files.sort();
+    let mut passed = 0;
+    for file in &files {
+        let run = || {
+            let program = compiler.load( fs::File::open( file )? )?;
+            run_program::< C >( options, &program, open_input( options )?, open_output( options ) )
+        };
+        match run() {
+            Ok( _ ) => {
+                passed += 1;
+                eprintln!( "ok     {}", file.display() );
+            },
+            Err( e ) => eprintln!( "FAILED {}: {}", file.display(), e ),
         }
     }
+    Ok( ( passed, files.len() ) )
 }
 
-fn main() -> Result< (), std::io::Error > {
-    let opcode_map: BTreeMap< char, OpCode > = BTreeMap::from( [ 
-        ( '+', INCR as OpCode ), 
-        ( '-', DECR as OpCode ), 
-        ( '>', RIGHT as OpCode ),
-        ( '<', LEFT as OpCode ),
-        ( '[', OPEN as OpCode ),
-        ( ']', CLOSE as OpCode ),
-        ( '.', PUT as OpCode ),
-        ( ',', GET as OpCode )
-    ] );
-    let args: Vec<String> = env::args().collect();
-    for arg in &args[ 1.. ] {
-        let mut e = Engine {
-            program: [ InstructionField { operand: 0 }; MEMORY_SIZE ],
-            pc: 0,
-            memory : [ 0; 30000 ],
-            loc: 0
+//  Carries out the actions with cells of type `C`, returning the exit status.
+fn run_actions< C: Cell >( options: &Options, actions: &[ Action ] ) -> Result< u8, BfError > {
+    let mut status = 0;
+    let mut compiler = Compiler::new().overflow( options.overflow ).opt_level( options.opt_level ).program_size( options.program_size );
+    if let Some( enabled ) = options.debug_op {
+        compiler = compiler.debug_op( enabled );
+    }
+    if let Some( ch ) = options.comment_char {
+        compiler = compiler.comment_char( ch );
+    }
+    compiler = compiler.strict( options.strict ).doc_comments( options.doc_comments );
+    compiler = compiler.rand_op( options.enable_rand ).num_op( options.enable_ext ).halt_op( options.enable_ext ).tape_op( options.multi_tape ).assert_op( options.enable_assert );
+    //  One engine runs every file in turn, reset between them so that each
+    //  starts on a clean tape.
+    let mut reusable: Option< Engine< C > > = None;
+    let mut programs = 0;
+    for action in actions {
+        if options.warn {
+            match action {
+                Action::Run( file ) if file != "-" => warn( options, &compiler, file, &read_program( options, file )? )?,
+                Action::Eval( src ) => warn( options, &compiler, "--eval", src.as_bytes() )?,
+                _ => {},
+            }
+        }
+        if options.opt_report {
+            match action {
+                Action::Run( file ) if file != "-" => report_passes( options, &compiler, file, &read_program( options, file )? )?,
+                Action::Eval( src ) => report_passes( options, &compiler, "--eval", src.as_bytes() )?,
+                _ => {},
+            }
+        }
+        let mut data = None;
+        let program = match action {
+            Action::Run( file ) if options.bang_input => {
+                let ( program, rest ) = compile_with_input( &compiler, &read_program( options, file )? )?;
+                data = rest;
+                program
+            },
+            Action::Eval( src ) if options.bang_input => {
+                let ( program, rest ) = compile_with_input( &compiler, src.as_bytes() )?;
+                data = rest;
+                program
+            },
+            Action::Run( file ) if options.cache && file != "-" => load_cached( &compiler, options, file )?,
+            Action::Run( file ) if options.include && file != "-" => compiler.compile( &read_program( options, file )? )?,
+            Action::Run( file ) => compiler.load( open_source( file )? )?,
+            Action::Eval( src ) => compiler.load( Cursor::new( src ) )?,
+            Action::RunJson( file ) => compiler.thread( &ir::from_json( &read_json( file )? )? )?,
+            Action::Compile( file ) => {
+                let json = ir::to_json( &compiler.optimize( &read_program( options, file )? )? );
+                match &options.out {
+                    Some( path ) => fs::write( path, json )?,
+                    None => print!( "{}", json ),
+                }
+                continue;
+            },
+            Action::EmitRust( file ) => {
+                let rust = ir::to_rust( &compiler.optimize( &read_program( options, file )? )? );
+                match &options.out {
+                    Some( path ) => fs::write( path, rust )?,
+                    None => print!( "{}", rust ),
+                }
+                continue;
+            },
+            Action::RunBin( file ) => Program::from_bytes( &fs::read( file )? )?,
+            Action::Pipe( first, second ) => {
+                let stage = | n, e | BfError::Pipeline { stage: n, error: Box::new( e ) };
+                let first = compiler.load( open_source( first )? ).map_err( | e | stage( 1, e ) )?;
+                let second = compiler.load( open_source( second )? ).map_err( | e | stage( 2, e ) )?;
+                let mut piped = Vec::new();
+                run_program::< C >( options, &first, open_input( options )?, &mut piped ).map_err( | e | stage( 1, e ) )?;
+                let cell = run_program::< C >( options, &second, Cursor::new( piped ), open_output( options ) ).map_err( | e | stage( 2, e ) )?;
+                if options.exit_cell {
+                    status = cell;
+                }
+                continue;
+            },
+            Action::CompileBin( file ) => {
+                let bytes = compiler.compile( &read_program( options, file )? )?.to_bytes();
+                match &options.out {
+                    Some( path ) => fs::write( path, bytes )?,
+                    None => std::io::stdout().write_all( &bytes )?,
+                }
+                continue;
+            },
+            Action::Check( file ) => {
+                let result = compiler.check( &read_program( options, file )? );
+                match &result {
+                    _ if options.json => print!( "{}", check::to_json( &result ) ),
+                    Ok( summary ) => print!( "{}", summary ),
+                    Err( _ ) => {},
+                }
+                result?;
+                continue;
+            },
+            Action::Help => {
+                print!( "{}", USAGE );
+                continue;
+            },
+            Action::Version => {
+                println!( "rust_threading_demo {}", VERSION );
+                continue;
+            },
+            Action::Repl => {
+                let engine = new_engine::< C >( options, &compiler.compile( "" )? ).input_buffer( 0 );
+                let mut engine = prepare( options, engine, open_input( options )?, open_output( options ) );
+                repl::repl( &compiler, &mut engine, stdin_lines(), std::io::stderr() )?;
+                continue;
+            },
+            Action::SelfTest => {
+                let results = selftest::run_all();
+                for ( name, result ) in &results {
+                    match result {
+                        Ok(()) => eprintln!( "ok     {}", name ),
+                        Err( why ) => eprintln!( "FAILED {}: {}", name, why ),
+                    }
+                }
+                let passed = results.iter().filter( | ( _, result ) | result.is_ok() ).count();
+                eprintln!( "{} of {} self tests passed", passed, results.len() );
+                if passed < results.len() {
+                    status = 1;
+                }
+                continue;
+            },
+            Action::CountOnly( file ) => {
+                let stats = compiler.statistics( &read_program( options, file )? )?;
+                if options.json {
+                    print!( "{}", check::statistics_json( &stats ) );
+                } else {
+                    print!( "{}", stats );
+                }
+                continue;
+            },
+            Action::Ast( file ) => {
+                let nodes = ir::tree( &compiler.parse( &read_program( options, file )? )? )?;
+                if options.json {
+                    print!( "{}", ir::tree_json( &nodes ) );
+                } else {
+                    print!( "{}", ir::tree_text( &nodes ) );
+                }
+                continue;
+            },
+            Action::Dir( dir ) => {
+                let ( passed, total ) = run_dir::< C >( options, &compiler, dir )?;
+                eprintln!( "{} of {} programs succeeded", passed, total );
+                if passed < total {
+                    status = 1;
+                }
+                continue;
+            },
+            Action::Decompile( file ) => {
+                let src = ir::to_source( &ir::from_json( &read_json( file )? )? );
+                match &options.out {
+                    Some( path ) => fs::write( path, src + "\n" )?,
+                    None => println!( "{}", src ),
+                }
+                continue;
+            },
+            Action::Disasm( file ) => {
+                print!( "{}", compiler.compile( &read_program( options, file )? )?.disassemble() );
+                continue;
+            },
+            Action::Listing( file ) => {
+                print!( "{}", compiler.listing( &read_program( options, file )? )? );
+                continue;
+            },
+            Action::Minimize( file ) => {
+                let data = match &options.input {
+                    Some( path ) => fs::read( path )?,
+                    None => Vec::new(),
+                };
+                let run = | program: &Program | {
+                    let engine = new_engine::< C >( options, program ).max_steps( options.max_steps.unwrap_or( MINIMIZE_STEPS ) );
+                    engine.with_io( Cursor::new( &data ), std::io::sink() ).debug_output( std::io::sink() ).run()
+                };
+                println!( "{}", minimize::minimize( &compiler, &read_program( options, file )?, run )? );
+                continue;
+            },
+            Action::Verify( file ) => {
+                let data = match &options.input {
+                    Some( path ) => fs::read( path )?,
+                    None => Vec::new(),
+                };
+                let run = | program: &Program | {
+                    let mut output = Vec::new();
+                    let mut engine = new_engine::< C >( options, program ).max_steps( options.max_steps.unwrap_or( VERIFY_STEPS ) );
+                    engine = engine.with_io( Cursor::new( &data ), &mut output ).debug_output( std::io::sink() );
+                    engine.run()?;
+                    let checksum = engine.tape_checksum();
+                    drop( engine );
+                    Ok( ( output, checksum ) )
+                };
+                verify::verify( &compiler.clone().opt_level( 0 ), &compiler, &read_program( options, file )?, run )?;
+                println!( "{}: the unoptimized and optimized runs agree", file );
+                continue;
+            },
+        };
+        if options.no_exit && programs > 0 {
+            print!( "{}", SEPARATOR );
+        }
+        programs += 1;
+        //  The engine has no sparse tape, so such a run goes around it.
+        if options.sparse {
+            let input: Box< dyn Read > = match &data {
+                Some( data ) => Box::new( Cursor::new( data.clone() ) ),
+                None => open_input( options )?,
+            };
+            let tape = sparse::run_sparse::< C >( &program, input, open_output( options ) )?;
+            if options.exit_cell {
+                status = tape.get( tape.head() ).to_byte();
+            }
+            continue;
+        }
+        let mut engine = match reusable.take() {
+            Some( mut engine ) => {
+                engine.load( &program );
+                engine
+            },
+            None => new_engine( options, &program ),
         };
-        load_program_from_file( &arg, &mut e.program, &opcode_map )?;
-        run_program( &mut e );
+        //  Each run after the first starts afresh on the same program, and
+        //  only the first gets what stdin has to give.
+        let mut times = Vec::new();
+        let mut steps = 0;
+        for _ in 0..options.repeat.unwrap_or( 1 ) {
+            let input: Box< dyn Read > = match &data {
+                Some( data ) => Box::new( Cursor::new( data.clone() ) ),
+                None => open_input( options )?,
+            };
+            engine.reset();
+            engine = prepare( options, engine, input, open_output( options ) );
+            let start = Instant::now();
+            let cell = run_engine( options, &mut engine )?;
+            times.push( start.elapsed().as_secs_f64() );
+            steps += engine.steps();
+            if options.exit_cell {
+                status = cell;
+            }
+        }
+        if options.repeat.is_some() {
+            times.sort_by( f64::total_cmp );
+            eprintln!( "Runs: {}, min {:.3}s, median {:.3}s, max {:.3}s, {} steps in all", times.len(), times[ 0 ], times[ times.len() / 2 ], times[ times.len() - 1 ], steps );
+        }
+        reusable = Some( engine );
+    }
+    Ok( status )
+}
+
+fn try_main() -> Result< ExitCode, BfError > {
+    let ( options, actions ) = parse_args()?;
+    if options.on_interrupt_dump {
+        catch_interrupt();
+    }
+    let status = match options.cell_bits {
+        _ if options.byte_cells => run_actions::< u8 >( &options, &actions )?,
+        16 => run_actions::< i16 >( &options, &actions )?,
+        32 => run_actions::< i32 >( &options, &actions )?,
+        _ => run_actions::< i8 >( &options, &actions )?,
+    };
+    Ok( ExitCode::from( status ) )
+}
+
+fn main() -> ExitCode {
+    match try_main() {
+        Ok( status ) => status,
+        //  As when `head` has read all it wants, which needs no message.
+        Err( BfError::OutputClosed { .. } ) => ExitCode::FAILURE,
+        Err( e ) => {
+            eprintln!( "Error: {}", e );
+            ExitCode::FAILURE
+        },
     }
-    return Ok(())
 }
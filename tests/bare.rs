@@ -0,0 +1,26 @@
+/*
+    Runs programs on the engine in `bare`, which only needs `alloc`,
+    through the closures that stand in for its input and output.
+*/
+
+use std::fs;
+
+use brainfuck::bare::{self, BareError};
+
+#[test]
+fn hello_runs_through_closures() {
+    let src = fs::read_to_string( "tests/programs/hello.bf" ).unwrap();
+    let program = bare::compile( &src ).unwrap();
+    let mut output = Vec::new();
+    bare::run( &program, 30000, || None, | byte | output.push( byte ) ).unwrap();
+    assert_eq!( output, fs::read( "tests/programs/hello.out" ).unwrap() );
+}
+
+#[test]
+fn the_pointer_stays_on_the_tape() {
+    let program = bare::compile( ">>+<<<" ).unwrap();
+    assert_eq!( bare::run( &program, 4, || None, | _ | {} ), Err( BareError::PointerUnderflow { pc: 2 } ) );
+    let program = bare::compile( ">>>>" ).unwrap();
+    assert_eq!( bare::run( &program, 4, || None, | _ | {} ), Err( BareError::PointerOverflow { pc: 0 } ) );
+    assert!( matches!( bare::compile( "[[]" ), Err( BareError::UnmatchedOpen { position: 0 } ) ) );
+}
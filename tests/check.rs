@@ -0,0 +1,81 @@
+/*
+    Checks the summaries that `Compiler::check` makes for --check, with
+    the description that --doc-comments adds, and the JSON form of them
+    that --check --json prints, the quicker test of `check_balanced`, the
+    warnings that `Compiler::lint` gives for --warn and the counts of
+    --count-only.
+*/
+
+use brainfuck::{check, BfError, Compiler, SourcePos};
+
+#[test]
+fn a_balanced_program_is_summarized() {
+    let result = Compiler::new().check( ",[.,]" );
+    assert_eq!( check::to_json( &result ), "{\"balanced\":true,\"depth\":1,\"description\":null,\"instructions\":5,\"reads_input\":true,\"unreachable\":[],\"writes_output\":true}\n" );
+}
+
+//  The prose stops at the first command, here the `,` of its second line.
+#[test]
+fn doc_comments_put_the_leading_prose_in_the_summary() {
+    let compiler = Compiler::new().doc_comments( true );
+    assert_eq!( compiler.compile( "Adds two numbers\n++" ).unwrap().description(), Some( "Adds two numbers" ) );
+    let summary = compiler.check( "  Echoes its input\n  one byte at a time, until the end\n,[.,]" ).unwrap();
+    assert_eq!( summary.description.as_deref(), Some( "Echoes its input\n  one byte at a time" ) );
+    assert!( summary.to_string().starts_with( "Echoes its input\n  one byte at a time\nBrackets balance\n" ) );
+    assert_eq!( Compiler::new().compile( "Adds two numbers\n++" ).unwrap().description(), None );
+    assert_eq!( compiler.compile( "\n++" ).unwrap().description(), None );
+}
+
+//  The driver exits with a failure when `check` fails, as for any error.
+#[test]
+fn an_unbalanced_program_reports_where() {
+    let result = Compiler::new().check( "+\n+]" );
+    assert!( matches!( result, Err( BfError::UnmatchedClose { source: Some( SourcePos { line: 2, column: 2 } ), .. } ) ) );
+    assert_eq!( check::to_json( &result ), "{\"balanced\":false,\"column\":2,\"error\":\"Unmatched closing bracket at line 2, column 2\",\"line\":2}\n" );
+}
+
+//  An open bracket is reported where the outermost unclosed loop starts.
+#[test]
+fn check_balanced_reports_the_first_unmatched_bracket() {
+    assert!( check::check_balanced( "+[>[-]<]." ).is_ok() );
+    assert!( check::check_balanced( "" ).is_ok() );
+    let open = check::check_balanced( "+[\n[-]\n[" );
+    assert!( matches!( open, Err( BfError::UnmatchedOpen { position: 1, source: Some( SourcePos { line: 1, column: 2 } ) } ) ), "{:?}", open );
+    let close = check::check_balanced( "[]\n-]]" );
+    assert!( matches!( close, Err( BfError::UnmatchedClose { position: 4, source: Some( SourcePos { line: 2, column: 2 } ) } ) ), "{:?}", close );
+    assert_eq!( close.unwrap_err().to_string(), Compiler::new().check( "[]\n-]]" ).unwrap_err().to_string() );
+}
+
+#[test]
+fn a_loop_that_cannot_end_is_an_infinite_loop() {
+    let messages = | src | Compiler::new().lint( src ).unwrap().into_iter().map( | w | w.to_string() ).collect::< Vec< _ > >();
+    assert_eq!( messages( "+[]" ), [ "1:2: warning: empty loop, an infinite loop if entered" ] );
+    assert_eq!( messages( "+\n[>+<]" ), [ "2:1: warning: loop leaves its cell unchanged, an infinite loop if entered" ] );
+    assert_eq!( messages( "><<[-]" ), [ "1:3: warning: moves left of cell 0, which always fails" ] );
+    assert!( messages( "+[-]>[<]" ).is_empty() );
+}
+
+//  What follows an `@` is unreachable up to the end of its loop, from
+//  where the loop's `[` can still skip to the rest of the program.
+#[test]
+fn commands_after_an_explicit_halt_are_unreachable() {
+    let compiler = Compiler::new().halt_op( true );
+    let summary = compiler.check( "+@>+[-]\n[+@-]." ).unwrap();
+    let at = | line, column | SourcePos { line, column };
+    assert_eq!( summary.unreachable, [ ( at( 1, 3 ), at( 2, 6 ) ) ] );
+    let summary = compiler.check( "+[@-]." ).unwrap();
+    assert_eq!( summary.unreachable, [ ( at( 1, 4 ), at( 1, 4 ) ) ] );
+    assert!( summary.to_string().ends_with( "Unreachable: line 1, column 4 to line 1, column 4\n" ), "{}", summary );
+    assert!( check::to_json( &Ok( summary ) ).contains( "\"unreachable\":[{\"column\":4,\"end_column\":4,\"end_line\":1,\"line\":1}]" ) );
+    let warnings: Vec< String > = compiler.lint( "+@." ).unwrap().into_iter().map( | w | w.to_string() ).collect();
+    assert_eq!( warnings, [ "1:3: warning: unreachable, after an explicit HALT" ] );
+    assert!( compiler.check( "+@" ).unwrap().unreachable.is_empty() );
+}
+
+#[test]
+fn statistics_count_each_command_and_loop() {
+    let stats = Compiler::new().statistics( "++[>+[-<]>,]." ).unwrap();
+    let commands: Vec< ( char, usize ) > = stats.commands.into_iter().collect();
+    assert_eq!( commands, [ ( '+', 3 ), ( ',', 1 ), ( '-', 1 ), ( '.', 1 ), ( '<', 1 ), ( '>', 2 ), ( '[', 2 ), ( ']', 2 ) ] );
+    assert_eq!( ( stats.instructions, stats.loops, stats.depth ), ( 13, 2, 2 ) );
+}
@@ -0,0 +1,58 @@
+/*
+    Drives the debugger with scripted commands and checks its responses.
+*/
+
+use brainfuck::{debugger, Compiler, Engine};
+
+//  Runs the debugger on `src`, compiled without optimization, and returns
+//  what it wrote.
+fn debug( src: &str, commands: &[ &str ] ) -> String {
+    let program = Compiler::new().opt_level( 0 ).debug_op( false ).compile( src ).unwrap();
+    let mut engine = Engine::< i8 >::new( &program, 8 ).with_io( std::io::empty(), std::io::sink() );
+    let mut out = Vec::new();
+    debugger::debug( &mut engine, commands.iter().map( | c | c.to_string() ), &mut out ).unwrap();
+    String::from_utf8( out ).unwrap()
+}
+
+#[test]
+fn continue_stops_at_a_breakpoint_in_a_loop_each_time_round() {
+    //  The loop body starts with the `>` at column 5.
+    let out = debug( "+++[>+<-]", &[ "break 1:5", "continue", "continue", "continue", "continue" ] );
+    assert_eq!( out.matches( "Breakpoint: " ).count(), 3 );
+    assert!( out.contains( "Breakpoint: pc=4 RIGHT (line 1, column 5) loc=0\nloc=0: [3] 0 0 0 0\n" ) );
+    assert!( out.ends_with( "Program halted\n(bf) " ) );
+}
+
+#[test]
+fn continue_stops_when_a_watched_cell_changes() {
+    let out = debug( "+>+<-", &[ "watch 0", "continue", "continue", "continue" ] );
+    assert_eq!( out.matches( "Watchpoint " ).count(), 2 );
+    assert!( out.contains( "Watchpoint [0]: 0 -> 1 at pc=0 INCR\n" ) );
+    assert!( out.contains( "Watchpoint [0]: 1 -> 0 at pc=4 DECR\n" ) );
+    assert!( out.ends_with( "Program halted\n(bf) " ) );
+}
+
+#[test]
+fn mem_shows_cells_as_signed_unless_asked() {
+    let program = Compiler::new().compile( "-" ).unwrap();
+    for ( unsigned, shown ) in [ ( false, "-1" ), ( true, "255" ) ] {
+        let mut engine = Engine::< i8 >::new( &program, 8 ).unsigned_cells( unsigned ).with_io( std::io::empty(), std::io::sink() );
+        let mut out = Vec::new();
+        debugger::debug( &mut engine, [ "step", "mem 0" ].map( String::from ).into_iter(), &mut out ).unwrap();
+        assert!( String::from_utf8( out ).unwrap().contains( &format!( "[0] = {}\n", shown ) ) );
+    }
+}
+
+#[test]
+fn break_takes_a_pc() {
+    let out = debug( "+++[>+<-]", &[ "break 5", "continue", "pc" ] );
+    assert!( out.contains( "Breakpoint at pc=5\n" ) );
+    assert!( out.contains( "Breakpoint: pc=5 INCR (line 1, column 6) loc=1\n" ) );
+}
+
+#[test]
+fn break_rejects_a_position_with_no_instruction() {
+    let out = debug( "+ +", &[ "break 1:2", "break 30000" ] );
+    assert!( out.contains( "No instruction at 1:2\n" ) );
+    assert!( out.contains( "No instruction at 30000\n" ) );
+}
@@ -0,0 +1,492 @@
+/*
+    Runs the driver, rust_threading_demo, to check the options that only it
+    has. It is run from the top of the repository, where `make test` has
+    built it.
+*/
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn driver( args: &[ &str ] ) -> Output {
+    Command::new( "./rust_threading_demo" ).args( args ).output().unwrap()
+}
+
+#[test]
+fn dir_runs_every_program_and_carries_on_past_failures() {
+    let dir = std::env::temp_dir().join( format!( "bf-dir-{}", std::process::id() ) );
+    fs::create_dir_all( &dir ).unwrap();
+    fs::write( dir.join( "a.bf" ), "++++++++[>++++++++<-]>+." ).unwrap();
+    fs::write( dir.join( "b.bf" ), "++++++++[>++++++++<-]>++." ).unwrap();
+    fs::write( dir.join( "c.bf" ), "<" ).unwrap();
+    fs::write( dir.join( "notes.txt" ), "+." ).unwrap();
+    let output = driver( &[ "--dir", dir.to_str().unwrap() ] );
+    fs::remove_dir_all( &dir ).unwrap();
+    assert_eq!( output.stdout, b"AB" );
+    let report = String::from_utf8_lossy( &output.stderr );
+    assert!( report.contains( "FAILED" ) && report.ends_with( "2 of 3 programs succeeded\n" ), "{}", report );
+    assert!( !output.status.success() );
+}
+
+//  Each program is written in the binary form and run back from it, with
+//  its input if it has any, which must do just as running its source does.
+#[test]
+fn a_binary_program_runs_as_the_source_does() {
+    let bin = std::env::temp_dir().join( format!( "bf-compile-{}.bfc", std::process::id() ) );
+    for name in [ "add", "echo", "hello", "multiply", "sierpinski" ] {
+        let src = format!( "tests/programs/{}.bf", name );
+        let input = format!( "tests/programs/{}.in", name );
+        let input: &[ &str ] = if fs::metadata( &input ).is_ok() { &[ "--input", &input ] } else { &[] };
+        let compiled = driver( &[ "--out", bin.to_str().unwrap(), "--compile-bin", &src ] );
+        assert!( compiled.status.success(), "{}", String::from_utf8_lossy( &compiled.stderr ) );
+        let ran = driver( &[ input, &[ "--run-bin", bin.to_str().unwrap() ] ].concat() );
+        fs::remove_file( &bin ).unwrap();
+        let expected = driver( &[ input, &[ src.as_str() ] ].concat() );
+        assert!( ran.status.success() && expected.status.success(), "{}", name );
+        assert_eq!( ran.stdout, expected.stdout, "{}", name );
+        assert_eq!( ran.stdout, fs::read( format!( "tests/programs/{}.out", name ) ).unwrap(), "{}", name );
+    }
+}
+
+//  Without optimization, so that the loops are not folded away. A loop is
+//  counted afresh each time it is entered, so ten runs of ten iterations
+//  fit in a budget of ten.
+#[test]
+fn loop_budget_stops_a_loop_that_runs_too_long() {
+    let nested = driver( &[ "--opt=0", "--loop-budget", "10", "--eval", "++++++++++[>++++++++++[-]<-]" ] );
+    assert!( nested.status.success(), "{}", String::from_utf8_lossy( &nested.stderr ) );
+    let long = driver( &[ "--opt=0", "--cell-bits=16", "--loop-budget", "100", "--eval", "++++++++++[>++++++++++<-]>[>++++++++++<-]>[-]" ] );
+    assert_eq!( String::from_utf8_lossy( &long.stderr ), "Error: Loop at line 1, column 43 ran more than 100 iterations (pc=42)\n" );
+    assert!( !long.status.success() );
+}
+
+//  At --opt=3 the JSON holds folded runs and MULADDs, which --run must
+//  read back as they were.
+#[test]
+fn an_optimized_json_program_runs_as_the_source_does() {
+    let json = std::env::temp_dir().join( format!( "bf-compile-{}.json", std::process::id() ) );
+    for name in [ "hello", "multiply", "sierpinski" ] {
+        let src = format!( "tests/programs/{}.bf", name );
+        let compiled = driver( &[ "--opt=3", "--out", json.to_str().unwrap(), "--compile", &src ] );
+        assert!( compiled.status.success(), "{}", String::from_utf8_lossy( &compiled.stderr ) );
+        let text = fs::read_to_string( &json ).unwrap();
+        let ran = driver( &[ "--run", json.to_str().unwrap() ] );
+        fs::remove_file( &json ).unwrap();
+        assert_eq!( ran.stdout, driver( &[ "--opt=3", &src ] ).stdout, "{}", name );
+        assert!( ran.status.success(), "{}", name );
+        if name == "multiply" {
+            assert!( text.contains( "{\"op\":\"MULADD\",\"targets\":[[1,7]]}" ), "{}", text );
+        }
+    }
+}
+
+//  The cost takes no account of the machine, so it is the same every run.
+#[test]
+fn deterministic_reports_the_same_cost_every_run() {
+    let small = driver( &[ "--opt=0", "--deterministic", "--eval", "++[>+<-]" ] );
+    assert_eq!( String::from_utf8_lossy( &small.stderr ), "Cost: 17, 14 steps\n" );
+    let run = || driver( &[ "--deterministic", "--time", "tests/programs/sierpinski.bf" ] ).stderr;
+    let first = run();
+    assert!( String::from_utf8_lossy( &first ).starts_with( "Cost: " ) );
+    assert_eq!( run(), first );
+}
+
+#[test]
+fn strict_rejects_characters_that_are_not_commands() {
+    assert!( driver( &[ "--eval", "++x" ] ).status.success() );
+    let strict = driver( &[ "--strict", "--eval", "++ \n+x" ] );
+    assert_eq!( String::from_utf8_lossy( &strict.stderr ), "Error: Unexpected character 'x' at line 2, column 2\n" );
+    assert!( !strict.status.success() );
+}
+
+#[test]
+fn trace_json_writes_a_line_for_each_step() {
+    let path = std::env::temp_dir().join( format!( "bf-trace-{}.jsonl", std::process::id() ) );
+    let output = driver( &[ "--opt=0", "--trace-json", path.to_str().unwrap(), "--eval", "++." ] );
+    let trace = fs::read_to_string( &path ).unwrap();
+    fs::remove_file( &path ).unwrap();
+    assert!( output.status.success(), "{}", String::from_utf8_lossy( &output.stderr ) );
+    let lines: Vec< &str > = trace.lines().take( 3 ).collect();
+    for ( line, ( pc, op ) ) in lines.iter().zip( [ ( 0, "INCR" ), ( 1, "INCR" ), ( 2, "PUT" ) ] ) {
+        assert!( line.contains( &format!( "\"op\":\"{}\",\"pc\":{},", op, pc ) ), "{}", line );
+    }
+    assert_eq!( lines.len(), 3 );
+}
+
+//  The same final tape, reached two ways and with the tape grown to
+//  different lengths, gives the same checksum.
+#[test]
+fn checksum_depends_only_on_the_final_tape() {
+    let checksum = | args: &[ &str ] | String::from_utf8( driver( args ).stderr ).unwrap();
+    let direct = checksum( &[ "--opt=0", "--checksum", "--eval", ">++++" ] );
+    assert!( direct.starts_with( "Checksum: " ) && direct.len() == 27, "{}", direct );
+    assert_eq!( checksum( &[ "--checksum", "--max-cells", "1000", "--eval", "++[->++<]>>>>>>>>>" ] ), direct );
+    assert_ne!( checksum( &[ "--checksum", "--eval", "++++" ] ), direct );
+}
+
+#[test]
+fn diff_tape_lists_only_the_changed_cells() {
+    let output = driver( &[ "--diff-tape", "--fill", "7", "--eval", "+>>>>>+>+-" ] );
+    assert_eq!( String::from_utf8( output.stderr ).unwrap(), "0: 7 -> 8\n5: 7 -> 8\n" );
+}
+
+#[test]
+fn start_cell_moves_the_first_increment_and_must_be_on_the_tape() {
+    let output = driver( &[ "--diff-tape", "--start-cell", "5", "--eval", "+" ] );
+    assert_eq!( String::from_utf8( output.stderr ).unwrap(), "5: 0 -> 1\n" );
+    let output = driver( &[ "--cells", "4", "--start-cell", "4", "--eval", "+" ] );
+    assert!( !output.status.success() );
+    assert!( String::from_utf8_lossy( &output.stderr ).contains( "Start cell 4 is off the end of a tape of 4 cells" ) );
+}
+
+#[test]
+fn minimize_writes_the_commands_that_still_fail() {
+    let path = std::env::temp_dir().join( format!( "bf-minimize-{}.bf", std::process::id() ) );
+    fs::write( &path, "Counts up ++>+++.[-]\n then goes too far <<< and stops +++[>++<-]>." ).unwrap();
+    let output = driver( &[ "--minimize", path.to_str().unwrap() ] );
+    fs::remove_file( &path ).unwrap();
+    assert_eq!( output.stdout, b"<\n" );
+    assert!( !driver( &[ "--minimize", "/dev/null" ] ).status.success() );
+}
+
+//  Each file runs in turn once the one before it halts.
+#[test]
+fn every_file_given_is_run_in_turn() {
+    let dir = std::env::temp_dir().join( format!( "bf-files-{}", std::process::id() ) );
+    fs::create_dir_all( &dir ).unwrap();
+    fs::write( dir.join( "a.bf" ), "++++++++[>++++++++<-]>+." ).unwrap();
+    fs::write( dir.join( "b.bf" ), "++++++++[>++++++++<-]>++." ).unwrap();
+    let ( a, b ) = ( dir.join( "a.bf" ), dir.join( "b.bf" ) );
+    let output = driver( &[ a.to_str().unwrap(), b.to_str().unwrap() ] );
+    fs::remove_dir_all( &dir ).unwrap();
+    assert!( output.status.success(), "{}", String::from_utf8_lossy( &output.stderr ) );
+    assert_eq!( output.stdout, b"AB" );
+}
+
+//  The `@` ends the first program, but not the run as a whole.
+#[test]
+fn no_exit_separates_the_output_of_each_program() {
+    let dir = std::env::temp_dir().join( format!( "bf-no-exit-{}", std::process::id() ) );
+    fs::create_dir_all( &dir ).unwrap();
+    fs::write( dir.join( "a.bf" ), "++++++++[>++++++++<-]>+.@+." ).unwrap();
+    fs::write( dir.join( "b.bf" ), "++++++++[>++++++++<-]>++." ).unwrap();
+    let ( a, b ) = ( dir.join( "a.bf" ), dir.join( "b.bf" ) );
+    let output = driver( &[ "--no-exit", "--enable-ext", a.to_str().unwrap(), b.to_str().unwrap() ] );
+    fs::remove_dir_all( &dir ).unwrap();
+    assert!( output.status.success() );
+    assert_eq!( String::from_utf8( output.stdout ).unwrap(), "A\n--------\nB" );
+}
+
+//  Two `+` before the loop and three in each of its two iterations.
+#[test]
+fn profile_counts_each_opcode_most_frequent_first() {
+    let output = driver( &[ "--opt=0", "--profile", "--eval", "++[>+++<-]" ] );
+    let report = String::from_utf8( output.stderr ).unwrap();
+    let first = report.lines().next().map( | line | line.split_whitespace().collect::< Vec< _ > >() );
+    assert_eq!( first, Some( vec![ "INCR:", "8" ] ), "{}", report );
+}
+
+#[test]
+fn profile_loops_puts_the_inner_loop_first() {
+    let output = driver( &[ "--opt=0", "--profile-loops", "--eval", "++[>+++[>+<-]<-]" ] );
+    assert_eq!( String::from_utf8( output.stderr ).unwrap(), "Loop at line 1, column 8 (pc=7): 6 iterations\nLoop at line 1, column 3 (pc=2): 2 iterations\n" );
+}
+
+//  The second loop is never entered, so nothing after its `[` runs, up to
+//  and including its `]`.
+#[test]
+fn coverage_lists_the_body_of_a_loop_never_entered() {
+    let output = driver( &[ "--opt=0", "--coverage", "--eval", "+[-]>[+++.]" ] );
+    let report = String::from_utf8( output.stderr ).unwrap();
+    let mut lines = report.lines();
+    assert_eq!( lines.next(), Some( "Coverage: 7 of 12 instructions (58.3%)" ) );
+    assert_eq!( lines.collect::< Vec< _ > >(), [
+        "Never ran at line 1, column 7 (pc=6)",
+        "Never ran at line 1, column 8 (pc=7)",
+        "Never ran at line 1, column 9 (pc=8)",
+        "Never ran at line 1, column 10 (pc=9)",
+        "Never ran at line 1, column 11 (pc=10)",
+    ] );
+}
+
+#[test]
+fn tee_copies_the_output_to_a_file() {
+    let file = std::env::temp_dir().join( format!( "bf-tee-{}.out", std::process::id() ) );
+    let output = driver( &[ "--tee", file.to_str().unwrap(), "tests/programs/hello.bf" ] );
+    let teed = fs::read( &file ).unwrap();
+    fs::remove_file( &file ).unwrap();
+    assert_eq!( output.stdout, fs::read( "tests/programs/hello.out" ).unwrap() );
+    assert_eq!( teed, output.stdout );
+}
+
+//  The included file is found relative to the one that names it. Without
+//  --include the line is just text, whose `.` writes the empty cell.
+#[test]
+fn include_splices_in_another_file() {
+    let dir = std::env::temp_dir().join( format!( "bf-include-{}", std::process::id() ) );
+    fs::create_dir_all( dir.join( "lib" ) ).unwrap();
+    fs::write( dir.join( "main.bf" ), "#include lib/sixtyfour.bf\n+.\n" ).unwrap();
+    fs::write( dir.join( "lib/sixtyfour.bf" ), "++++++++[>++++++++<-]>" ).unwrap();
+    fs::write( dir.join( "lib/loop.bf" ), "  #include ../loop.bf\n" ).unwrap();
+    fs::write( dir.join( "loop.bf" ), "#include lib/loop.bf\n" ).unwrap();
+    let main = dir.join( "main.bf" );
+    let included = driver( &[ "--include", main.to_str().unwrap() ] );
+    let ignored = driver( &[ main.to_str().unwrap() ] );
+    let cycle = driver( &[ "--include", dir.join( "loop.bf" ).to_str().unwrap() ] );
+    fs::remove_dir_all( &dir ).unwrap();
+    assert_eq!( included.stdout, b"A" );
+    assert_eq!( ignored.stdout, b"\0\x01" );
+    let report = String::from_utf8_lossy( &cycle.stderr );
+    assert!( report.starts_with( "Error: Includes form a cycle" ) && report.ends_with( "loop.bf\n" ), "{}", report );
+}
+
+#[test]
+fn fill_starts_the_tape_at_other_bytes() {
+    let far = ">".repeat( 1000 );
+    assert_eq!( driver( &[ "--fill", "5", "--exit-cell", "--eval", &far ] ).status.code(), Some( 5 ) );
+    assert_eq!( driver( &[ "--fill-pattern", "1,2,3", "--exit-cell", "--eval", ">>>>+" ] ).status.code(), Some( 3 ) );
+    assert_eq!( driver( &[ "--exit-cell", "--eval", &far ] ).status.code(), Some( 0 ) );
+    let bad = driver( &[ "--fill-pattern", "1,x", "--eval", "" ] );
+    assert_eq!( String::from_utf8_lossy( &bad.stderr ), "Error: Invalid list of bytes '1,x' (expected numbers from 0 to 255, such as 1,2,3)\n" );
+}
+
+//  Optimized, the program takes 6 steps each time.
+#[test]
+fn repeat_runs_the_program_again_on_a_fresh_tape() {
+    let output = driver( &[ "--opt=3", "--repeat", "3", "--eval", "++++++++[>++++++++<-]>+." ] );
+    assert_eq!( output.stdout, b"AAA" );
+    let report = String::from_utf8_lossy( &output.stderr );
+    assert!( report.starts_with( "Runs: 3, min " ) && report.ends_with( ", 18 steps in all\n" ), "{}", report );
+}
+
+#[test]
+fn max_output_stops_a_program_that_writes_too_much() {
+    let src = format!( "++++++++[>++++++++<-]>+{}", ".".repeat( 100 ) );
+    let output = driver( &[ "--max-output", "10", "--eval", &src ] );
+    assert_eq!( output.stdout, b"AAAAAAAAAA" );
+    let report = String::from_utf8_lossy( &output.stderr );
+    assert!( report.starts_with( "Error: Output limit of 10 bytes exceeded at line 1, column 34 " ), "{}", report );
+    assert!( !output.status.success() );
+}
+
+#[test]
+fn overflow_error_stops_a_cell_going_below_its_range() {
+    let src = "-".repeat( 129 );
+    assert!( driver( &[ "--overflow=wrap", "--eval", &src ] ).status.success() );
+    let output = driver( &[ "--overflow=error", "--eval", &src ] );
+    let report = String::from_utf8_lossy( &output.stderr );
+    assert!( report.starts_with( "Error: Cell overflowed at line 1, column 1 " ), "{}", report );
+    assert!( !output.status.success() );
+}
+
+#[test]
+fn ast_shows_the_loops_as_a_tree() {
+    let file = std::env::temp_dir().join( format!( "bf-ast-{}.bf", std::process::id() ) );
+    fs::write( &file, "+[>+]-" ).unwrap();
+    let json = driver( &[ "--ast", file.to_str().unwrap(), "--json" ] );
+    let text = driver( &[ "--ast", file.to_str().unwrap() ] );
+    fs::remove_file( &file ).unwrap();
+    assert_eq!( String::from_utf8_lossy( &json.stdout ), "[\"+\",{\"loop\":[\">+\"]},\"-\"]\n" );
+    assert_eq!( String::from_utf8_lossy( &text.stdout ), "+\n[\n    >+\n]\n-\n" );
+}
+
+#[test]
+fn help_lists_the_options_and_an_unknown_option_is_an_error() {
+    let help = driver( &[ "--help" ] );
+    assert!( help.status.success() );
+    let text = String::from_utf8_lossy( &help.stdout );
+    assert!( text.starts_with( "Usage: " ) );
+    assert!( text.contains( "--tee FILE" ) );
+    assert!( driver( &[ "--version" ] ).status.success() );
+    let unknown = driver( &[ "--unknown" ] );
+    assert!( !unknown.status.success() );
+    assert_eq!( String::from_utf8_lossy( &unknown.stderr ), "Error: Unknown option '--unknown' (see --help)\n" );
+}
+
+#[test]
+fn a_sparse_tape_reaches_far_past_the_default_size() {
+    let src = format!( "{}++++++++[>++++++++<-]>+.", ">".repeat( 100_000 ) );
+    let output = driver( &[ "--tape=sparse", "--eval", &src ] );
+    assert_eq!( output.stdout, b"A" );
+    assert!( !driver( &[ "--eval", &src ] ).status.success() );
+}
+
+//  Only the bytes that `,` takes are recorded, not the rest of stdin.
+#[test]
+fn a_recorded_run_replays_with_the_same_input() {
+    let file = std::env::temp_dir().join( format!( "bf-record-{}.in", std::process::id() ) );
+    let mut child = Command::new( "./rust_threading_demo" )
+        .args( [ "--record", file.to_str().unwrap(), "--eval", ",+.,+." ] )
+        .stdin( Stdio::piped() ).stdout( Stdio::piped() ).spawn().unwrap();
+    child.stdin.take().unwrap().write_all( b"HAL and more" ).unwrap();
+    let recorded = child.wait_with_output().unwrap();
+    let replayed = driver( &[ "--replay", file.to_str().unwrap(), "--eval", ",+.,+." ] );
+    let bytes = fs::read( &file ).unwrap();
+    fs::remove_file( &file ).unwrap();
+    assert_eq!( recorded.stdout, b"IB" );
+    assert_eq!( bytes, b"HA" );
+    assert_eq!( replayed.stdout, recorded.stdout );
+}
+
+#[test]
+fn byte_cells_stop_at_0_and_255() {
+    assert_eq!( driver( &[ "--byte-cells", "--eval", "-.+." ] ).stdout, [ 0, 1 ] );
+    let src = format!( "{}.", "+".repeat( 300 ) );
+    assert_eq!( driver( &[ "--byte-cells", "--eval", &src ] ).stdout, [ 255 ] );
+    assert_eq!( driver( &[ "--eval", "-." ] ).stdout, [ 255 ] );
+}
+
+//  The generated source is compiled with rustc and run, as a user would.
+#[test]
+fn emitted_rust_compiles_and_runs_as_the_program_does() {
+    let dir = std::env::temp_dir().join( format!( "bf-emit-{}", std::process::id() ) );
+    fs::create_dir_all( &dir ).unwrap();
+    fs::write( dir.join( "clear.bf" ), "+[-]" ).unwrap();
+    let clear = String::from_utf8( driver( &[ "--emit-rust", dir.join( "clear.bf" ).to_str().unwrap() ] ).stdout ).unwrap();
+    assert!( clear.contains( "    memory[ loc ] = 0;\n" ) );
+    let source = dir.join( "hello.rs" );
+    let binary = dir.join( "hello" );
+    assert!( driver( &[ "--emit-rust", "tests/programs/hello.bf", "--out", source.to_str().unwrap() ] ).status.success() );
+    let compiled = Command::new( "rustc" ).args( [ "-o", binary.to_str().unwrap(), source.to_str().unwrap() ] ).output().unwrap();
+    assert!( compiled.status.success(), "{}", String::from_utf8_lossy( &compiled.stderr ) );
+    assert!( compiled.stderr.is_empty(), "{}", String::from_utf8_lossy( &compiled.stderr ) );
+    let ran = Command::new( &binary ).output().unwrap();
+    fs::remove_dir_all( &dir ).unwrap();
+    assert_eq!( ran.stdout, fs::read( "tests/programs/hello.out" ).unwrap() );
+}
+
+//  Every loop of this program clears a cell, so clear_loops should be the
+//  pass that shrinks it.
+#[test]
+fn opt_report_shows_the_clear_loops_shrinking_the_program() {
+    let output = driver( &[ "--opt-report", "--eval", "+[-]>++[-]>+++[-]" ] );
+    assert!( output.status.success() );
+    let report = String::from_utf8_lossy( &output.stderr );
+    let line = report.lines().find( | line | line.starts_with( "clear_loops" ) ).unwrap_or_else( || panic!( "{}", report ) );
+    assert_eq!( line.split_whitespace().collect::< Vec< _ > >(), [ "clear_loops", "17", "11" ] );
+    assert!( report.starts_with( "--eval:\nPass " ) && report.contains( "\nTotal " ), "{}", report );
+}
+
+#[test]
+fn report_extent_shows_the_cells_that_were_touched() {
+    let src = format!( "+{}", ">+".repeat( 100 ) );
+    let output = driver( &[ "--report-extent", "--eval", &src ] );
+    assert!( output.status.success() );
+    assert_eq!( String::from_utf8_lossy( &output.stderr ), "Extent: cells 0..=100\n" );
+    let moved = driver( &[ "--report-extent", "--eval", ">>>>" ] );
+    assert_eq!( String::from_utf8_lossy( &moved.stderr ), "Extent: no cells touched\n" );
+}
+
+#[test]
+fn verify_passes_on_a_program_that_optimizing_leaves_alone() {
+    let output = driver( &[ "--verify", "tests/programs/hello.bf" ] );
+    assert!( output.status.success(), "{}", String::from_utf8_lossy( &output.stderr ) );
+    assert_eq!( String::from_utf8_lossy( &output.stdout ), "tests/programs/hello.bf: the unoptimized and optimized runs agree\n" );
+}
+
+//  The commands are all ASCII, so bytes that are not UTF-8 are as good as
+//  comments, in a program file and in one that it includes.
+#[test]
+fn a_program_file_may_hold_bytes_that_are_not_utf8() {
+    let dir = std::env::temp_dir().join( format!( "bf-bytes-{}", std::process::id() ) );
+    fs::create_dir_all( &dir ).unwrap();
+    fs::write( dir.join( "noise.bf" ), b"\xff++++++++\xfe[>++++++++<\xc3-]>\x80+.\n" ).unwrap();
+    fs::write( dir.join( "main.bf" ), b"#include noise.bf\n\xe2\x28+.\n" ).unwrap();
+    let plain = driver( &[ dir.join( "noise.bf" ).to_str().unwrap() ] );
+    let included = driver( &[ "--include", dir.join( "main.bf" ).to_str().unwrap() ] );
+    fs::remove_dir_all( &dir ).unwrap();
+    assert_eq!( plain.stdout, b"A", "{}", String::from_utf8_lossy( &plain.stderr ) );
+    assert_eq!( included.stdout, b"AB", "{}", String::from_utf8_lossy( &included.stderr ) );
+}
+
+//  The input after `!` is passed on as it is, and --strict names the byte
+//  that it rejects.
+#[test]
+fn bang_input_and_strict_keep_the_bytes_that_are_not_utf8() {
+    let dir = std::env::temp_dir().join( format!( "bf-bang-bytes-{}", std::process::id() ) );
+    fs::create_dir_all( &dir ).unwrap();
+    fs::write( dir.join( "echo.bf" ), b",.,.!\xff\x41" ).unwrap();
+    fs::write( dir.join( "noise.bf" ), b"+\xff." ).unwrap();
+    let echoed = driver( &[ "--bang-input", dir.join( "echo.bf" ).to_str().unwrap() ] );
+    let strict = driver( &[ "--strict", dir.join( "noise.bf" ).to_str().unwrap() ] );
+    fs::remove_dir_all( &dir ).unwrap();
+    assert_eq!( echoed.stdout, b"\xff\x41", "{}", String::from_utf8_lossy( &echoed.stderr ) );
+    assert_eq!( String::from_utf8_lossy( &strict.stderr ), "Error: Unexpected byte 0xff at line 1, column 2\n" );
+}
+
+//  Each of the 255 passes of the outer loop reads a zero and then writes
+//  the 255 bytes counting down from it.
+#[test]
+fn null_io_discards_the_output_and_reads_zeros() {
+    let output = driver( &[ "--null-io", "--eval", "-[>,-[.-]<-]" ] );
+    assert!( output.status.success(), "{}", String::from_utf8_lossy( &output.stderr ) );
+    assert!( output.stdout.is_empty() && output.stderr.is_empty() );
+    assert_eq!( driver( &[ "--input", "/dev/null", "--eval", "-[>,-[.-]<-]" ] ).stdout.len(), 255 * 255 );
+}
+
+//  The first stage reads --input, not stdin, and a failure names the stage
+//  it came from.
+#[test]
+fn pipe_feeds_the_output_of_the_first_program_to_the_second() {
+    let dir = std::env::temp_dir().join( format!( "bf-pipe-{}", std::process::id() ) );
+    fs::create_dir_all( &dir ).unwrap();
+    fs::write( dir.join( "in" ), "AB" ).unwrap();
+    fs::write( dir.join( "next.bf" ), ",+.,+." ).unwrap();
+    fs::write( dir.join( "echo.bf" ), ",.,." ).unwrap();
+    fs::write( dir.join( "bad.bf" ), "<" ).unwrap();
+    let path = | name: &str | dir.join( name ).to_str().unwrap().to_string();
+    let piped = driver( &[ "--input", &path( "in" ), "--pipe", &path( "next.bf" ), &path( "echo.bf" ) ] );
+    let failed = driver( &[ "--input", &path( "in" ), "--pipe", &path( "next.bf" ), &path( "bad.bf" ) ] );
+    fs::remove_dir_all( &dir ).unwrap();
+    assert_eq!( piped.stdout, b"BC", "{}", String::from_utf8_lossy( &piped.stderr ) );
+    let report = String::from_utf8_lossy( &failed.stderr );
+    assert!( report.starts_with( "Error: Stage 2 of the pipeline failed: " ), "{}", report );
+    assert!( !failed.status.success() );
+}
+
+//  The cache is keyed on the source as well as its time, so a change made
+//  within the same second is still seen.
+#[test]
+fn cache_is_remade_when_the_source_changes() {
+    let dir = std::env::temp_dir().join( format!( "bf-cache-{}", std::process::id() ) );
+    fs::create_dir_all( &dir ).unwrap();
+    let file = dir.join( "a.bf" );
+    let path = file.to_str().unwrap();
+    fs::write( &file, "++++++++[>++++++++<-]>+." ).unwrap();
+    let first = driver( &[ "--cache", path ] );
+    let cached = dir.join( "a.bf.cache" ).exists();
+    fs::write( &file, "++++++++[>++++++++<-]>++." ).unwrap();
+    let second = driver( &[ "--cache", path ] );
+    let third = driver( &[ "--cache", path ] );
+    fs::remove_dir_all( &dir ).unwrap();
+    assert!( cached );
+    assert_eq!( ( first.stdout, second.stdout, third.stdout ), ( b"A".to_vec(), b"B".to_vec(), b"B".to_vec() ) );
+}
+
+#[test]
+fn decompile_writes_to_the_file_named_by_out() {
+    let json = std::env::temp_dir().join( format!( "bf-decompile-{}.json", std::process::id() ) );
+    let bf = std::env::temp_dir().join( format!( "bf-decompile-{}.bf", std::process::id() ) );
+    let compiled = driver( &[ "--opt=0", "--out", json.to_str().unwrap(), "--compile", "tests/programs/hello.bf" ] );
+    assert!( compiled.status.success(), "{}", String::from_utf8_lossy( &compiled.stderr ) );
+    let printed = driver( &[ "--decompile", json.to_str().unwrap() ] );
+    let written = driver( &[ "--out", bf.to_str().unwrap(), "--decompile", json.to_str().unwrap() ] );
+    let text = fs::read( &bf ).unwrap();
+    fs::remove_file( &json ).unwrap();
+    fs::remove_file( &bf ).unwrap();
+    assert!( written.stdout.is_empty() && written.status.success() );
+    assert_eq!( text, printed.stdout );
+}
+
+//  Three hundred fits in a 16-bit cell, but wraps to 44 in an 8-bit one.
+#[test]
+fn cell_bits_16_holds_values_past_255() {
+    let src = "+".repeat( 300 );
+    let wide = driver( &[ "--cell-bits=16", "--dump-tape-on-halt", "--eval", &src ] );
+    let narrow = driver( &[ "--dump-tape-on-halt", "--eval", &src ] );
+    let dump = | output: &Output | String::from_utf8_lossy( &output.stderr ).lines().nth( 1 ).map( | line | line.split_whitespace().nth( 1 ).unwrap_or( "" ).to_string() );
+    assert_eq!( dump( &wide ), Some( "[012c]".to_string() ), "{}", String::from_utf8_lossy( &wide.stderr ) );
+    assert_eq!( dump( &narrow ), Some( "[2c]".to_string() ), "{}", String::from_utf8_lossy( &narrow.stderr ) );
+    assert!( wide.status.success() && narrow.status.success() );
+    assert_eq!( driver( &[ "--exit-cell", "--eval", &src ] ).status.code(), Some( 44 ) );
+}
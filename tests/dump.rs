@@ -0,0 +1,46 @@
+/*
+    Checks each format of --dump-format over the same small tape, as
+    `format_tape` gives it and as `#`, the debugger and a dump of the whole
+    tape show it.
+*/
+
+use brainfuck::visualize::{dump_tape, format_tape};
+use brainfuck::{debugger, Compiler, DumpFormat, Engine};
+
+const TAPE: [ i8; 4 ] = [ 72, 105, -1, 0 ];
+
+#[test]
+fn each_format_shows_the_same_tape() {
+    assert_eq!( format_tape( &TAPE, 0..4, DumpFormat::Hex, 1, false ), "48 [69] ff 00" );
+    assert_eq!( format_tape( &TAPE, 0..4, DumpFormat::Dec, 1, false ), "72 [105] -1 0" );
+    assert_eq!( format_tape( &TAPE, 0..4, DumpFormat::Dec, 1, true ), "72 [105] 255 0" );
+    assert_eq!( format_tape( &TAPE, 0..4, DumpFormat::Ascii, 1, false ), "H [i] . ." );
+    assert_eq!( format_tape( &TAPE, 2..4, DumpFormat::Hex, 1, false ), "ff 00" );
+}
+
+#[test]
+fn a_wide_cell_has_a_digit_for_every_four_bits() {
+    assert_eq!( format_tape( &[ 1i16, -1 ], 0..2, DumpFormat::Hex, 0, false ), "[0001] ffff" );
+    assert_eq!( format_tape( &[ 0x141i16 ], 0..1, DumpFormat::Ascii, 0, false ), "[A]" );
+}
+
+#[test]
+fn a_dump_of_the_tape_uses_the_format() {
+    assert_eq!( dump_tape( &TAPE, 3, DumpFormat::Hex, false ), "head=3\n00000000  48 69 ff [00]\n" );
+    assert_eq!( dump_tape( &TAPE, 3, DumpFormat::Dec, false ), "head=3\n00000000  72 105 -1 [0]\n" );
+    assert_eq!( dump_tape( &TAPE, 3, DumpFormat::Ascii, false ), "head=3\n00000000  H i . [.]\n" );
+}
+
+#[test]
+fn hash_and_the_debugger_use_the_engine_format() {
+    let program = Compiler::new().debug_op( true ).compile( ">#" ).unwrap();
+    for ( format, window, cell ) in [ ( DumpFormat::Hex, "loc=1: 48 [69] ff 00\n", "ff" ), ( DumpFormat::Dec, "loc=1: 72 [105] -1 0\n", "-1" ), ( DumpFormat::Ascii, "loc=1: H [i] . .\n", "." ) ] {
+        let mut dump = Vec::new();
+        let mut engine = Engine::< i8 >::new( &program, 4 ).init_tape( &TAPE.map( | cell | cell as u8 ) ).dump_format( format ).with_io( std::io::empty(), std::io::sink() ).debug_output( &mut dump );
+        let mut out = Vec::new();
+        debugger::debug( &mut engine, [ "continue", "mem 2" ].map( String::from ).into_iter(), &mut out ).unwrap();
+        drop( engine );
+        assert_eq!( String::from_utf8( dump ).unwrap(), window );
+        assert!( String::from_utf8( out ).unwrap().contains( &format!( "[2] = {}\n", cell ) ) );
+    }
+}
@@ -0,0 +1,149 @@
+/*
+    Runs each program in tests/programs at every level of optimization,
+    with threaded, direct, switch and token dispatch, and compares its
+    output with the golden copy in the matching `.out` file.
+    A program's input, if it reads any, is in the matching `.in` file. The
+    paths are relative, so run the tests from the top of the repository.
+*/
+
+use std::fs;
+use std::path::Path;
+
+use brainfuck::optimize::Optimizer;
+use brainfuck::{Compiler, Engine, Instr, MEMORY_SIZE};
+
+const PROGRAMS: &str = "tests/programs";
+
+fn check( name: &str ) {
+    let path = Path::new( PROGRAMS ).join( name );
+    let src = fs::read_to_string( path.with_extension( "bf" ) ).unwrap();
+    let input = fs::read( path.with_extension( "in" ) ).unwrap_or_default();
+    let expected = fs::read( path.with_extension( "out" ) ).unwrap();
+    for level in 0..=3 {
+        let program = Compiler::new().opt_level( level ).debug_op( false ).compile( &src ).unwrap();
+        let mut output = Vec::new();
+        brainfuck::run( &program, input.as_slice(), &mut output ).unwrap();
+        assert!( output == expected, "{} at --opt={} wrote {:?}", name, level, String::from_utf8_lossy( &output ) );
+        let mut output = Vec::new();
+        Engine::< i8 >::new( &program, MEMORY_SIZE ).with_io( input.as_slice(), &mut output ).run_direct().unwrap();
+        assert!( output == expected, "{} at --opt={} with direct dispatch wrote {:?}", name, level, String::from_utf8_lossy( &output ) );
+        let mut output = Vec::new();
+        Engine::< i8 >::new( &program, MEMORY_SIZE ).with_io( input.as_slice(), &mut output ).run_switch().unwrap();
+        assert!( output == expected, "{} at --opt={} with switch dispatch wrote {:?}", name, level, String::from_utf8_lossy( &output ) );
+        let mut output = Vec::new();
+        Engine::< i8 >::new( &program, MEMORY_SIZE ).with_io( input.as_slice(), &mut output ).run_token().unwrap();
+        assert!( output == expected, "{} at --opt={} with token dispatch wrote {:?}", name, level, String::from_utf8_lossy( &output ) );
+    }
+}
+
+#[test]
+fn hello() {
+    check( "hello" );
+}
+
+#[test]
+fn add() {
+    check( "add" );
+}
+
+#[test]
+fn echo() {
+    check( "echo" );
+}
+
+#[test]
+fn multiply() {
+    check( "multiply" );
+}
+
+#[test]
+fn sierpinski() {
+    check( "sierpinski" );
+}
+
+//  Needs flate2: build with `--cfg 'feature="gzip"' --extern flate2=...`.
+#[cfg(feature = "gzip")]
+#[test]
+fn gzipped_source_compiles_the_same() {
+    use std::io::Write;
+    let src = fs::read( Path::new( PROGRAMS ).join( "hello.bf" ) ).unwrap();
+    let mut encoder = flate2::write::GzEncoder::new( Vec::new(), flate2::Compression::default() );
+    encoder.write_all( &src ).unwrap();
+    let gzipped = encoder.finish().unwrap();
+    let compiler = Compiler::new();
+    let plain = compiler.load( src.as_slice() ).unwrap();
+    let unzipped = compiler.load( flate2::read::GzDecoder::new( gzipped.as_slice() ) ).unwrap();
+    assert_eq!( unzipped.disassemble(), plain.disassemble() );
+}
+
+//  Catches a program added to tests/programs without a test of its own.
+#[test]
+fn every_program_is_checked() {
+    let mut names: Vec< String > = fs::read_dir( PROGRAMS ).unwrap()
+        .map( | entry | entry.unwrap().path() )
+        .filter( | path | path.extension().is_some_and( | ext | ext == "bf" ) )
+        .map( | path | path.file_stem().unwrap().to_string_lossy().into_owned() )
+        .collect();
+    names.sort();
+    assert_eq!( names, [ "add", "echo", "hello", "multiply", "sierpinski" ] );
+}
+
+//  The same programs that --self-test runs from inside the binary.
+#[test]
+fn self_test_passes() {
+    for ( name, result ) in brainfuck::selftest::run_all() {
+        assert!( result.is_ok(), "{}: {:?}", name, result );
+    }
+}
+
+//  Decompiling keeps every command, so the source it gives compiles to the
+//  same program, whether it is decompiled before or after optimization.
+#[test]
+fn decompiled_programs_compile_the_same() {
+    for name in [ "add", "echo", "hello", "multiply", "sierpinski" ] {
+        let src = fs::read_to_string( Path::new( PROGRAMS ).join( name ).with_extension( "bf" ) ).unwrap();
+        let compiler = Compiler::new().debug_op( false );
+        let expected = compiler.compile( &src ).unwrap().disassemble();
+        let parsed = compiler.parse( &src ).unwrap();
+        assert_eq!( compiler.compile( brainfuck::ir::to_source( &parsed ) ).unwrap().disassemble(), expected, "{}", name );
+        let located = parsed.into_iter().map( | instr | brainfuck::ir::Located { instr, pos: None } ).collect();
+        let optimized: Vec< Instr > = Optimizer::level( 3, true ).run( located ).into_iter().map( | l | l.instr ).collect();
+        assert_eq!( compiler.compile( brainfuck::ir::to_source( &optimized ) ).unwrap().disassemble(), expected, "{}", name );
+    }
+}
+
+//  The JSON form gives each bracket its partner, and reading it back must
+//  thread to the same slots and operands as compiling the source.
+#[test]
+fn json_programs_thread_the_same_and_reject_tampered_jumps() {
+    for name in [ "add", "echo", "hello", "multiply", "sierpinski" ] {
+        let src = fs::read_to_string( Path::new( PROGRAMS ).join( name ).with_extension( "bf" ) ).unwrap();
+        let compiler = Compiler::new().debug_op( false );
+        let json = brainfuck::ir::to_json( &compiler.optimize( &src ).unwrap() );
+        let loaded = compiler.thread( &brainfuck::ir::from_json( &json ).unwrap() ).unwrap();
+        assert_eq!( loaded.to_bytes(), compiler.compile( &src ).unwrap().to_bytes(), "{}", name );
+    }
+    let json = brainfuck::ir::to_json( &Compiler::new().opt_level( 0 ).parse( "[[-]>]" ).unwrap() );
+    assert!( json.contains( "{\"op\":\"OPEN\",\"operand\":5}" ) && json.contains( "{\"op\":\"CLOSE\",\"operand\":1}" ), "{}", json );
+    for ( from, to ) in [ ( "\"OPEN\",\"operand\":5", "\"OPEN\",\"operand\":4" ), ( "\"CLOSE\",\"operand\":1", "\"CLOSE\",\"operand\":0" ), ( "\"operand\":5", "\"operand\":9" ) ] {
+        let tampered = json.replacen( from, to, 1 );
+        assert!( matches!( brainfuck::ir::from_json( &tampered ), Err( brainfuck::BfError::InconsistentJsonProgram { .. } ) ), "{}", tampered );
+    }
+}
+
+//  A program is compiled once and can then run on any number of engines,
+//  which each keep their own tape.
+#[test]
+fn one_program_runs_the_same_on_separate_engines() {
+    let src = fs::read_to_string( Path::new( PROGRAMS ).join( "echo.bf" ) ).unwrap();
+    let input = fs::read( Path::new( PROGRAMS ).join( "echo.in" ) ).unwrap();
+    let program = Compiler::new().debug_op( false ).compile( &src ).unwrap();
+    assert!( program.reads_input() && program.writes_output() );
+    let outputs: Vec< Vec< u8 > > = ( 0..2 ).map( | _ | {
+        let mut output = Vec::new();
+        Engine::< i8 >::new( &program, MEMORY_SIZE ).with_io( input.as_slice(), &mut output ).run().unwrap();
+        output
+    } ).collect();
+    assert_eq!( outputs[ 0 ], input );
+    assert_eq!( outputs[ 1 ], outputs[ 0 ] );
+}
@@ -0,0 +1,43 @@
+/*
+    Checks what the library logs, using a logger that keeps each message.
+    Needs the log crate: build with `--cfg 'feature="log"' --extern log=...`.
+    The logger is global, so these tests have a file of their own.
+*/
+
+#[cfg(feature = "log")]
+mod capture {
+
+    use std::sync::Mutex;
+
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+
+    struct Capture( Mutex< Vec< ( Level, String ) > > );
+
+    impl Log for Capture {
+
+        fn enabled( &self, metadata: &Metadata ) -> bool {
+            metadata.level() <= Level::Debug
+        }
+
+        fn log( &self, record: &Record ) {
+            if self.enabled( record.metadata() ) {
+                self.0.lock().unwrap().push( ( record.level(), record.args().to_string() ) );
+            }
+        }
+
+        fn flush( &self ) {}
+
+    }
+
+    static LOGGER: Capture = Capture( Mutex::new( Vec::new() ) );
+
+    #[test]
+    fn each_optimization_pass_is_logged() {
+        log::set_logger( &LOGGER ).unwrap();
+        log::set_max_level( LevelFilter::Debug );
+        brainfuck::Compiler::new().opt_level( 1 ).compile( "+++[-]" ).unwrap();
+        let logged = LOGGER.0.lock().unwrap();
+        assert!( logged.contains( &( Level::Debug, "optimization pass 1 of 1: 6 instructions before, 4 after".to_string() ) ), "{:?}", logged );
+    }
+
+}
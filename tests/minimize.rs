@@ -0,0 +1,32 @@
+/*
+    Checks that `minimize::minimize` shrinks a failing program to the few
+    commands that fail in the same way, keeping the brackets balanced.
+*/
+
+use brainfuck::{minimize, BfError, Compiler, Engine, Program};
+
+fn run( program: &Program ) -> Result< (), BfError > {
+    Engine::< i8 >::new( program, 16 ).max_steps( 10_000 ).with_io( std::io::empty(), std::io::sink() ).run()
+}
+
+#[test]
+fn noise_around_an_underflow_is_removed() {
+    let src = "Some noise ++>+++.[-]>>.<<< then <<< and more +++[>++<-]>.";
+    assert_eq!( minimize::minimize( &Compiler::new(), src, run ).unwrap(), "<" );
+}
+
+//  The overflow needs a loop that keeps moving right, and each attempt
+//  that would leave its brackets unbalanced is skipped.
+#[test]
+fn a_loop_that_the_error_needs_is_kept_whole() {
+    let output = minimize::minimize( &Compiler::new(), "+.>-[>-]++", run ).unwrap();
+    assert!( output.contains( '[' ) && output.contains( ']' ), "{}", output );
+    let program = Compiler::new().compile( &output ).unwrap();
+    assert!( matches!( run( &program ), Err( BfError::PointerOverflow { .. } ) ) );
+}
+
+#[test]
+fn a_program_that_runs_cannot_be_minimized() {
+    assert!( matches!( minimize::minimize( &Compiler::new(), "+++.", run ), Err( BfError::InvalidArgument { .. } ) ) );
+    assert!( matches!( minimize::minimize( &Compiler::new(), "[", run ), Err( BfError::UnmatchedOpen { .. } ) ) );
+}
@@ -0,0 +1,821 @@
+/*
+    Checks the effect of each of the basic opcodes on the program counter,
+    the data pointer and the tape, by compiling single commands without
+    optimization and stepping through them.
+*/
+
+use std::cell::RefCell;
+use std::io::{Cursor, Read};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use brainfuck::{opcode_name, BfError, Compiler, Endian, EngineState, EofPolicy, Engine, HaltReason, Instr, Overflow, Program, StepResult, TapeMode};
+
+fn compile( src: &str ) -> Program {
+    Compiler::new().opt_level( 0 ).debug_op( false ).compile( src ).unwrap()
+}
+
+//  An engine for `program` with a four-cell tape that starts with `tape`.
+fn engine< 'io >( program: &Program, tape: &[ u8 ] ) -> Engine< 'io, i8 > {
+    Engine::< i8 >::new( program, 4 ).init_tape( tape ).with_io( std::io::empty(), std::io::sink() )
+}
+
+#[test]
+fn incr_adds_one_and_wraps() {
+    let program = compile( "+" );
+    let mut e = engine( &program, &[ 0 ] );
+    e.step().unwrap();
+    assert_eq!( ( e.pc(), e.loc(), e.tape() ), ( 1, 0, &[ 1, 0, 0, 0 ][ .. ] ) );
+    let mut e = engine( &program, &[ 127 ] );
+    e.step().unwrap();
+    assert_eq!( e.tape()[ 0 ], -128 );
+}
+
+//  Sixteen passes of the loop add sixteen each to cell 1, which wraps back
+//  to where it started after 256, or stops at the top without wrapping.
+#[test]
+fn incrementing_a_cell_256_times_wraps_it_back_to_its_start() {
+    let src = format!( "{}[>{}<-]", "+".repeat( 16 ), "+".repeat( 16 ) );
+    let mut e = engine( &compile( &src ), &[] );
+    e.run().unwrap();
+    assert_eq!( e.tape(), [ 0, 0, 0, 0 ] );
+    let mut e = engine( &Compiler::new().opt_level( 0 ).wrap( false ).compile( &src ).unwrap(), &[] );
+    e.run().unwrap();
+    assert_eq!( e.tape(), [ 0, 127, 0, 0 ] );
+}
+
+#[test]
+fn decr_subtracts_one_and_wraps() {
+    let program = compile( "-" );
+    let mut e = engine( &program, &[ 1 ] );
+    e.step().unwrap();
+    assert_eq!( ( e.pc(), e.loc(), e.tape() ), ( 1, 0, &[ 0, 0, 0, 0 ][ .. ] ) );
+    let mut e = engine( &program, &[ 0 ] );
+    e.step().unwrap();
+    assert_eq!( e.tape()[ 0 ], -1 );
+}
+
+//  Cells are signed, so the ends of an 8-bit cell's range are at 127 and
+//  -128, which are the bytes 0x7f and 0x80. Both single and folded counts
+//  are checked, since they compile to different opcodes.
+#[test]
+fn overflow_policies_differ_at_the_ends_of_a_cell() {
+    let run = | overflow, src: &str, byte | {
+        let program = Compiler::new().opt_level( 1 ).overflow( overflow ).compile( src ).unwrap();
+        let mut e = engine( &program, &[ byte ] );
+        e.run().map( | () | e.tape()[ 0 ] )
+    };
+    for ( up, down ) in [ ( "+", "-" ), ( "+++", "---" ) ] {
+        assert_eq!( run( Overflow::Wrap, up, 0x7f ).unwrap(), -128 + up.len() as i8 - 1 );
+        assert_eq!( run( Overflow::Wrap, down, 0x80 ).unwrap(), 127 - down.len() as i8 + 1 );
+        assert_eq!( run( Overflow::Saturate, up, 0x7f ).unwrap(), 127 );
+        assert_eq!( run( Overflow::Saturate, down, 0x80 ).unwrap(), -128 );
+        assert!( matches!( run( Overflow::Error, up, 0x7f ), Err( BfError::CellOverflow { pc: 0, loc: 0, .. } ) ) );
+        assert!( matches!( run( Overflow::Error, down, 0x80 ), Err( BfError::CellOverflow { pc: 0, loc: 0, .. } ) ) );
+        //  Short of the ends, every policy agrees.
+        for overflow in [ Overflow::Wrap, Overflow::Saturate, Overflow::Error ] {
+            assert_eq!( run( overflow, up, 0x7c ).unwrap(), 124 + up.len() as i8 );
+            assert_eq!( run( overflow, down, 0x83 ).unwrap(), -125 - down.len() as i8 );
+        }
+    }
+}
+
+//  A 16-bit cell read as two bytes and written back gives the same two
+//  bytes, in either order, and the order decides what the cell holds.
+#[test]
+fn a_wide_cell_round_trips_in_either_byte_order() {
+    let program = compile( ",.+" );
+    for ( endian, cell ) in [ ( Endian::Little, 0x3412 ), ( Endian::Big, 0x1234 ) ] {
+        let mut output = Vec::new();
+        let mut e = Engine::< i16 >::new( &program, 4 ).wide_io( endian ).with_io( &[ 0x12, 0x34 ][ .. ], &mut output );
+        e.step().unwrap();
+        assert_eq!( e.tape()[ 0 ], cell );
+        e.run().unwrap();
+        drop( e );
+        assert_eq!( output, [ 0x12, 0x34 ] );
+    }
+}
+
+#[test]
+fn right_moves_up_to_the_last_cell() {
+    let program = compile( ">>>>" );
+    let mut e = engine( &program, &[] );
+    for loc in 1..4 {
+        e.step().unwrap();
+        assert_eq!( ( e.pc(), e.loc() ), ( loc as usize, loc ) );
+    }
+    assert!( matches!( e.step(), Err( BfError::PointerOverflow { pc: 3, .. } ) ) );
+}
+
+#[test]
+fn left_moves_down_to_cell_zero() {
+    let program = compile( "><<" );
+    let mut e = engine( &program, &[] );
+    e.step().unwrap();
+    e.step().unwrap();
+    assert_eq!( ( e.pc(), e.loc() ), ( 2, 0 ) );
+    assert!( matches!( e.step(), Err( BfError::PointerUnderflow { pc: 2, .. } ) ) );
+}
+
+//  `[>]` and `[<]` compile to single scans, which stop on the first zero
+//  cell and fail like a move if they run off the end of the tape first.
+#[test]
+fn scans_stop_at_the_first_zero_or_fail_off_the_end() {
+    let compiler = Compiler::new().debug_op( false );
+    let right = compiler.compile( "[>]" ).unwrap();
+    let left = compiler.compile( ">>>[<]" ).unwrap();
+    assert_eq!( right.disassemble(), "     0  SCAN_RIGHT\n     1  HALT\n" );
+    let mut e = engine( &right, &[ 1, 1, 1, 0 ] );
+    e.run().unwrap();
+    assert_eq!( e.loc(), 3 );
+    assert!( matches!( engine( &right, &[ 1, 1, 1, 1 ] ).run(), Err( BfError::PointerOverflow { pc: 0, .. } ) ) );
+    let mut e = engine( &left, &[ 0, 1, 1, 1 ] );
+    e.run().unwrap();
+    assert_eq!( e.loc(), 0 );
+    assert!( matches!( engine( &left, &[ 1, 1, 1, 1 ] ).run(), Err( BfError::PointerUnderflow { .. } ) ) );
+}
+
+#[test]
+fn moves_wrap_around_a_wrapping_tape() {
+    let program = compile( "<" );
+    let mut e = engine( &program, &[] ).tape_mode( TapeMode::Wrap, 0 );
+    e.step().unwrap();
+    assert_eq!( e.loc(), 3 );
+    let wrapped = | src, tape: &[ u8 ] | {
+        let mut e = Engine::< i8 >::new( &Compiler::new().compile( src ).unwrap(), 4 ).init_tape( tape ).tape_mode( TapeMode::Wrap, 0 );
+        e.run().unwrap();
+        ( e.loc(), e.tape().to_vec() )
+    };
+    assert_eq!( wrapped( ">>>>>>>[>]", &[ 1, 1, 0, 1 ] ), ( 2, vec![ 1, 1, 0, 1 ] ) );
+    assert_eq!( wrapped( "<<<<[<]", &[ 1, 1, 0, 1 ] ), ( 2, vec![ 1, 1, 0, 1 ] ) );
+    assert_eq!( wrapped( "+[-<++>]", &[] ), ( 0, vec![ 0, 0, 0, 2 ] ) );
+}
+
+//  The tape starts with four cells and must grow past 50000 to hold the
+//  cell written, which then reads back with `.`.
+#[test]
+fn a_growing_tape_grows_to_the_right_as_far_as_needed() {
+    let src = format!( "{}+++.", ">".repeat( 50_000 ) );
+    let program = Compiler::new().opt_level( 0 ).program_size( 60_000 ).compile( &src ).unwrap();
+    let mut output = Vec::new();
+    let mut e = Engine::< i8 >::new( &program, 4 ).tape_mode( TapeMode::GrowRight, 1 << 20 ).with_io( std::io::empty(), &mut output );
+    e.run().unwrap();
+    assert_eq!( ( e.loc(), e.cell( 50_000 ) ), ( 50_000, Some( 3 ) ) );
+    assert!( e.tape().len() > 50_000 );
+    drop( e );
+    assert_eq!( output, [ 3 ] );
+}
+
+#[test]
+fn a_bidirectional_tape_grows_to_the_left_of_the_origin() {
+    let program = compile( "<<<+++++>>><<<." );
+    let mut output = Vec::new();
+    let mut e = Engine::< i8 >::new( &program, 4 ).tape_mode( TapeMode::Bidirectional, 1 << 10 ).with_io( std::io::empty(), &mut output );
+    e.run().unwrap();
+    assert_eq!( ( e.loc(), e.cell( -3 ), e.cell( 0 ) ), ( -3, Some( 5 ), Some( 0 ) ) );
+    drop( e );
+    assert_eq!( output, [ 5 ] );
+}
+
+//  `[+]` is laid out as OPEN, INCR, CLOSE, HALT.
+#[test]
+fn open_jumps_past_the_loop_on_zero() {
+    let program = compile( "[+]" );
+    let mut e = engine( &program, &[ 0 ] );
+    e.step().unwrap();
+    assert_eq!( e.pc(), 3 );
+    let mut e = engine( &program, &[ 1 ] );
+    e.step().unwrap();
+    assert_eq!( e.pc(), 1 );
+}
+
+#[test]
+fn close_jumps_back_into_the_loop_on_nonzero() {
+    let program = compile( "[+]" );
+    let mut e = engine( &program, &[ 255 ] );
+    e.step().unwrap();
+    e.step().unwrap();
+    assert_eq!( ( e.pc(), e.tape()[ 0 ] ), ( 2, 0 ) );
+    e.step().unwrap();
+    assert_eq!( e.pc(), 3 );
+    let mut e = engine( &program, &[ 1 ] );
+    e.step().unwrap();
+    e.step().unwrap();
+    e.step().unwrap();
+    assert_eq!( e.pc(), 1 );
+}
+
+//  Each bracket jumps to the instruction just after its partner, as they
+//  did when the jump targets had slots of their own.
+#[test]
+fn nested_jumps_land_after_the_matching_bracket() {
+    let listing = compile( "[[-]>]" ).disassemble();
+    assert_eq!( listing, concat!(
+        "     0  OPEN         -> 6\n",
+        "     1  OPEN         -> 4\n",
+        "     2  DECR\n",
+        "     3  CLOSE        -> 2\n",
+        "     4  RIGHT\n",
+        "     5  CLOSE        -> 1\n",
+        "     6  HALT\n",
+    ) );
+}
+
+//  Six loops deep, each doubling the count of the one inside it, so that a
+//  jump to the wrong place would show up in the final cell.
+#[test]
+fn deeply_nested_jumps_pair_up() {
+    let depth = 6;
+    let src = format!( "{}+{}", "++[>".repeat( depth ), "<-]".repeat( depth ) );
+    let listing = compile( &src ).disassemble();
+    let lines: Vec< Vec< &str > > = listing.lines().map( | line | line.split_whitespace().collect() ).collect();
+    for ( pc, line ) in lines.iter().enumerate() {
+        if line[ 1 ] == "OPEN" {
+            let after = line[ 3 ].parse::< usize >().unwrap();
+            assert_eq!( lines[ after - 1 ][ 1..3 ], [ "CLOSE", "->" ], "{}", listing );
+            assert_eq!( lines[ after - 1 ][ 3 ], ( pc + 1 ).to_string(), "{}", listing );
+        }
+    }
+    for level in 0..=3 {
+        let program = Compiler::new().opt_level( level ).compile( &src ).unwrap();
+        let mut e = Engine::< i8 >::new( &program, 8 ).with_io( std::io::empty(), std::io::sink() );
+        e.run().unwrap();
+        assert_eq!( e.tape()[ depth ], 1 << depth, "--opt={}", level );
+    }
+}
+
+//  With optimization, `[-]` is a single SET_ZERO that the `-` and `]` are
+//  folded into.
+#[test]
+fn listing_shows_each_bracket_with_its_partner() {
+    let listing = Compiler::new().opt_level( 0 ).listing( "[+]" ).unwrap();
+    let partners: Vec< &str > = listing.lines().filter_map( | line | line.split( "matches " ).nth( 1 ) ).collect();
+    assert_eq!( partners, [ "2", "0" ] );
+    assert_eq!( Compiler::new().listing( "+[-]" ).unwrap(), concat!(
+        "   1:1    +     0  INCR\n",
+        "   1:2    [     1  SET_ZERO\n",
+        "   1:3    -   (1)\n",
+        "   1:4    ]   (1)\n",
+    ) );
+}
+
+//  The moves are gone at --opt=3, but the cell they reach must still be on
+//  the tape.
+#[test]
+fn add_at_changes_the_cell_that_the_moves_reach() {
+    let run = | program: &Program, cells | {
+        let mut e = Engine::< i8 >::new( program, cells ).init_tape( &[ 5, 0, 7 ] ).with_io( std::io::empty(), std::io::sink() );
+        e.run().map( | () | ( e.tape().to_vec(), e.loc() ) )
+    };
+    let fused = Compiler::new().debug_op( false ).compile( ">>+<<" ).unwrap();
+    assert_eq!( fused.disassemble(), "     0  ADD_AT       +2 +1\n     1  HALT\n" );
+    let threaded = Compiler::new().thread( &[ Instr::AddAt( 2, 1 ) ] ).unwrap();
+    let unfused = compile( ">>+<<" );
+    for program in [ &fused, &threaded, &unfused ] {
+        assert_eq!( run( program, 4 ).unwrap(), ( vec![ 5, 0, 8, 0 ], 0 ) );
+        assert!( matches!( run( program, 2 ), Err( BfError::PointerOverflow { .. } ) ) );
+    }
+    assert!( !Compiler::new().debug_op( false ).compile( ">>>++<+>--<<<" ).unwrap().disassemble().contains( "ADD_AT" ) );
+}
+
+//  A move onto a cell off the tape only fails if there is something to
+//  move, as for MULADD.
+#[test]
+fn move_adds_the_cell_to_its_neighbour_and_clears_it() {
+    let program = Compiler::new().debug_op( false ).compile( "[->+<]" ).unwrap();
+    assert_eq!( program.disassemble(), "     0  MOVE         +1
+     1  HALT
+" );
+    let mut e = engine( &program, &[ 7, 0 ] );
+    e.run().unwrap();
+    assert_eq!( ( e.tape(), e.loc() ), ( &[ 0, 7, 0, 0 ][ .. ], 0 ) );
+    let mut e = engine( &compile( "[-<<+>>]" ), &[ 0, 0, 5 ] );
+    e.run().unwrap();
+    assert_eq!( e.tape(), [ 0, 0, 5, 0 ] );
+    let mut e = engine( &Compiler::new().compile( "[-<+>]" ).unwrap(), &[ 5 ] );
+    assert!( matches!( e.run(), Err( BfError::PointerUnderflow { pc: 0, .. } ) ) );
+    assert!( Compiler::new().compile( "[->++<]" ).unwrap().disassemble().contains( "MULADD" ) );
+}
+
+//  A clear loop is one step however large the cell, but a loop that moves
+//  the cell elsewhere is not a clear loop, even though it leaves a zero.
+#[test]
+fn a_clear_loop_zeroes_the_cell_in_one_step() {
+    let compiler = Compiler::new().debug_op( false );
+    let mut e = engine( &compiler.compile( "[-]" ).unwrap(), &[ 200 ] );
+    e.step().unwrap();
+    assert_eq!( ( e.tape()[ 0 ], e.pc() ), ( 0, 1 ) );
+    for level in 0..=3 {
+        let moved = compiler.clone().opt_level( level ).compile( "[->+<]" ).unwrap().disassemble();
+        assert!( !moved.contains( "SET_ZERO" ), "--opt={}: {}", level, moved );
+    }
+}
+
+//  A run after a clear loop stores its count, but the first cell is not
+//  known to be zero, since the tape may have been loaded with data.
+#[test]
+fn a_run_after_a_clear_loop_sets_a_constant() {
+    let compiler = Compiler::new().debug_op( false );
+    assert_eq!( compiler.compile( "[-]+++++" ).unwrap().disassemble(), "     0  SET_CONST    5\n     1  HALT\n" );
+    assert_eq!( compiler.compile( "+++++" ).unwrap().disassemble(), "     0  INCR_N       5\n     1  HALT\n" );
+    assert_eq!( Compiler::new().debug_op( false ).wrap( false ).compile( "[-]+" ).unwrap().disassemble(), "     0  OPEN         -> 3\n     1  DECR_SAT\n     2  CLOSE        -> 1\n     3  INCR_SAT\n     4  HALT\n" );
+    let mut e = engine( &compiler.compile( "[-]+++>[-]-" ).unwrap(), &[ 9 ] );
+    e.run().unwrap();
+    assert_eq!( e.tape(), [ 3, -1, 0, 0 ] );
+}
+
+//  Eight commands and a HALT need nine slots, one more than there are.
+#[test]
+fn a_program_that_does_not_fit_is_too_large() {
+    let compiler = Compiler::new().opt_level( 0 ).program_size( 8 );
+    assert!( matches!( compiler.compile( "++++++++" ), Err( BfError::ProgramTooLarge { needed: 9, capacity: 8 } ) ) );
+    assert!( compiler.compile( "+++++++" ).is_ok() );
+}
+
+//  Output that can be looked at while an engine is still writing to it.
+#[derive(Clone, Default)]
+struct Shared( Rc< RefCell< Vec< u8 > > > );
+
+impl std::io::Write for Shared {
+    fn write( &mut self, buf: &[ u8 ] ) -> std::io::Result< usize > {
+        self.0.borrow_mut().extend_from_slice( buf );
+        Ok( buf.len() )
+    }
+
+    fn flush( &mut self ) -> std::io::Result< () > {
+        Ok(())
+    }
+}
+
+//  The loop draws random bytes from `?` and counts on the second tape of
+//  `%`, so the run only goes on the same way if the state holds both, as
+//  well as the counts kept against the limits.
+#[test]
+fn a_restored_snapshot_runs_on_as_the_original_did() {
+    let program = Compiler::new().opt_level( 0 ).rand_op( true ).tape_op( true ).compile( "+++[>?.%+.%<-]?." ).unwrap();
+    let output = Shared::default();
+    let mut e = Engine::< i8 >::new( &program, 4 ).loop_budget( 10 ).max_output( 100 ).with_io( std::io::empty(), output.clone() );
+    for _ in 0..9 {
+        e.step().unwrap();
+    }
+    let state = e.snapshot();
+    assert_eq!( ( state.tape_index, state.written, state.loop_iterations.iter().sum::< u64 >() ), ( 1, 1, 1 ) );
+    assert_eq!( EngineState::from_json( &state.to_json() ).unwrap(), state );
+    let written = output.0.borrow().len();
+    e.run().unwrap();
+    let ( first, tape ) = ( output.0.borrow_mut().split_off( written ), e.tape().to_vec() );
+    e.restore( &state ).unwrap();
+    e.run().unwrap();
+    assert_eq!( ( output.0.borrow_mut().split_off( written ), e.tape().to_vec() ), ( first.clone(), tape ) );
+    assert_eq!( first.len(), 6 );
+}
+
+//  The slots after HALT are never reached by a program's own jumps, but a
+//  saved state could put the pc there.
+#[test]
+fn an_unused_slot_traps() {
+    let program = Compiler::new().program_size( 8 ).compile( "+" ).unwrap();
+    let mut e = engine( &program, &[] );
+    let mut state = e.snapshot();
+    state.pc = 5;
+    e.restore( &state ).unwrap();
+    assert!( matches!( e.run(), Err( BfError::IllegalInstruction { pc: 5 } ) ) );
+    assert!( matches!( e.run_switch(), Err( BfError::IllegalInstruction { pc: 5 } ) ) );
+    assert!( matches!( e.run_direct(), Err( BfError::IllegalInstruction { pc: 5 } ) ) );
+    assert!( matches!( e.run_token(), Err( BfError::IllegalInstruction { pc: 5 } ) ) );
+}
+
+#[test]
+fn protected_cells_can_be_read_but_not_written() {
+    let program = compile( ">[>+>+<<-]" );
+    let mut e = engine( &program, &[ 0, 3 ] ).protect( 1..2 );
+    assert!( matches!( e.run(), Err( BfError::WriteProtected { addr: 1, pc: 8, .. } ) ) );
+    assert_eq!( e.tape(), [ 0, 3, 1, 1 ] );
+    let program = compile( ">[>+>+<<-]" );
+    let mut e = engine( &program, &[ 0, 3 ] ).protect( 0..1 );
+    e.run().unwrap();
+    assert_eq!( e.tape(), [ 0, 0, 3, 3 ] );
+}
+
+#[test]
+fn put_writes_the_low_byte_of_the_cell() {
+    let program = compile( "." );
+    let mut output = Vec::new();
+    let mut e = Engine::< i8 >::new( &program, 4 ).init_tape( b"A" ).with_io( std::io::empty(), &mut output );
+    e.step().unwrap();
+    assert_eq!( ( e.pc(), e.loc() ), ( 1, 0 ) );
+    drop( e );
+    assert_eq!( output, b"A" );
+}
+
+#[test]
+fn put_output_arrives_whole_and_in_order() {
+    let program = compile( "+[.+]" );
+    let mut output = Vec::new();
+    Engine::< i8 >::new( &program, 4 ).with_io( std::io::empty(), &mut output ).run().unwrap();
+    assert_eq!( output, ( 1..=255 ).collect::< Vec< u8 > >() );
+}
+
+#[test]
+fn put_escapes_bytes_that_are_not_ascii_when_asked() {
+    let program = compile( "." );
+    for ( ascii_only, expected ) in [ ( true, &b"\\xC8"[ .. ] ), ( false, &[ 200 ][ .. ] ) ] {
+        let mut output = Vec::new();
+        Engine::< i8 >::new( &program, 4 ).init_tape( &[ 200 ] ).ascii_only( ascii_only ).with_io( std::io::empty(), &mut output ).run().unwrap();
+        assert_eq!( output, expected );
+    }
+}
+
+#[test]
+fn get_reads_a_byte() {
+    let program = compile( ",," );
+    let mut e = Engine::< i8 >::new( &program, 4 ).with_io( Cursor::new( b"Z" ), std::io::sink() );
+    e.step().unwrap();
+    assert_eq!( ( e.pc(), e.tape() ), ( 1, &[ 90, 0, 0, 0 ][ .. ] ) );
+    e.step().unwrap();
+    assert_eq!( ( e.pc(), e.tape()[ 0 ] ), ( 2, 90 ) );
+}
+
+#[test]
+fn get_can_skip_carriage_returns() {
+    let program = compile( ",>,>," );
+    let mut e = Engine::< i8 >::new( &program, 4 ).strip_cr( true ).with_io( Cursor::new( b"a\r\nb" ), std::io::sink() );
+    for _ in 0..5 {
+        e.step().unwrap();
+    }
+    assert_eq!( e.tape(), b"a\nb\0".map( | b | b as i8 ) );
+}
+
+//  The input comes in two pieces, to see that the buffer reads across them.
+#[test]
+fn get_reads_through_a_buffer_in_order() {
+    let program = compile( ",>,>,>," );
+    let input = Cursor::new( b"ab" ).chain( Cursor::new( b"c" ) );
+    let mut e = Engine::< i8 >::new( &program, 4 ).eof( EofPolicy::NegOne ).with_io( input, std::io::sink() );
+    e.run().unwrap();
+    assert_eq!( e.tape(), [ 97, 98, 99, -1 ] );
+}
+
+#[test]
+fn get_without_a_buffer_leaves_the_rest_of_the_input() {
+    let program = compile( "," );
+    let mut input = Cursor::new( b"xyz" );
+    let mut e = Engine::< i8 >::new( &program, 4 ).input_buffer( 0 ).with_io( &mut input, std::io::sink() );
+    e.run().unwrap();
+    assert_eq!( e.tape()[ 0 ], 120 );
+    drop( e );
+    assert_eq!( input.position(), 1 );
+}
+
+#[test]
+fn get_at_end_of_input_follows_the_eof_policy() {
+    let program = compile( "," );
+    for ( policy, cell ) in [ ( EofPolicy::NoChange, 7 ), ( EofPolicy::Zero, 0 ), ( EofPolicy::NegOne, -1 ) ] {
+        let mut e = engine( &program, &[ 7 ] ).eof( policy );
+        e.step().unwrap();
+        assert_eq!( ( e.pc(), e.tape()[ 0 ] ), ( 1, cell ) );
+    }
+}
+
+//  `+[-]` at -O0 is INCR, OPEN, DECR, CLOSE, HALT, and from a cell of 1
+//  goes round the loop twice.
+#[test]
+fn run_iter_yields_each_step_until_stopped() {
+    let program = compile( "+[-]" );
+    let mut e = engine( &program, &[ 1 ] );
+    let steps: Vec< _ > = e.run_iter().take( 5 ).map( | step | step.unwrap() ).collect();
+    assert_eq!( steps.iter().map( | s | s.pc ).collect::< Vec< _ > >(), [ 0, 1, 2, 3, 2 ] );
+    assert_eq!( ( steps[ 4 ].opcode, steps[ 4 ].loc, steps[ 4 ].cell ), ( "DECR", 0, 0 ) );
+    assert_eq!( e.pc(), 3 );
+    assert_eq!( e.run_iter().count(), 2 );
+}
+
+//  Only a sketch of `opcode_name`, which cannot promise to find every
+//  pointer: with -O, this crate's HALT is not the one the engine holds.
+#[test]
+fn opcode_name_names_a_function_pointer() {
+    let program = compile( "+" );
+    let e = engine( &program, &[] );
+    assert_eq!( e.opcode_at( 0 ).and_then( opcode_name ), Some( "INCR" ) );
+    assert_eq!( e.opcode_name_at( 0 ), Some( "INCR" ) );
+}
+
+//  The sequence is pinned, so that a change to the generator is noticed.
+#[test]
+fn rand_stores_bytes_that_follow_from_the_seed() {
+    let program = Compiler::new().opt_level( 0 ).rand_op( true ).compile( "?>?>?>?" ).unwrap();
+    let mut e = Engine::< i8 >::new( &program, 4 ).seed( 1 ).with_io( std::io::empty(), std::io::sink() );
+    e.run().unwrap();
+    assert_eq!( e.tape(), [ 0x72, 0xd2, 0x47, 0xeb ].map( | b: u8 | b as i8 ) );
+    e.reset();
+    e.run().unwrap();
+    assert_eq!( e.tape(), [ 0x72, 0xd2, 0x47, 0xeb ].map( | b: u8 | b as i8 ) );
+}
+
+#[test]
+fn rand_is_ignored_unless_enabled() {
+    assert_eq!( compile( "?" ).disassemble(), "     0  HALT\n" );
+}
+
+#[test]
+fn put_num_writes_the_cell_in_decimal() {
+    let run = | src: &str, unsigned: bool | {
+        let program = Compiler::new().num_op( true ).compile( src ).unwrap();
+        let mut output = Vec::new();
+        Engine::< i8 >::new( &program, 4 ).unsigned_cells( unsigned ).with_io( std::io::empty(), &mut output ).run().unwrap();
+        String::from_utf8( output ).unwrap()
+    };
+    assert_eq!( run( "++++++[>+++++++<-]>:", false ), "42 " );
+    assert_eq!( run( ":-:", false ), "0 -1 " );
+    assert_eq!( run( "-:", true ), "255 " );
+}
+
+#[test]
+fn assert_checks_the_cell_against_its_number() {
+    let program = Compiler::new().assert_op( true ).compile( "=++++++[>++++++++<-]>=48<=" ).unwrap();
+    assert!( engine( &program, &[] ).run().is_ok() );
+    let program = Compiler::new().assert_op( true ).compile( "+++\n--=2" ).unwrap();
+    match engine( &program, &[] ).run() {
+        Err( BfError::AssertionFailed { expected: 2, actual: 1, source: Some( pos ), .. } ) => assert_eq!( ( pos.line, pos.column ), ( 2, 3 ) ),
+        other => panic!( "expected a failed assertion, got {:?}", other ),
+    }
+    let program = Compiler::new().assert_op( true ).compile( "-=255" ).unwrap();
+    assert!( engine( &program, &[] ).run().is_ok() );
+    assert_eq!( compile( "=3" ).disassemble(), "     0  HALT\n" );
+}
+
+#[test]
+fn switch_tape_keeps_each_tape_and_its_pointer() {
+    let program = Compiler::new().opt_level( 0 ).tape_op( true ).compile( "+++>%++%+" ).unwrap();
+    let mut e = engine( &program, &[] );
+    e.run().unwrap();
+    assert_eq!( ( e.active_tape(), e.tape(), e.loc() ), ( 0, &[ 3, 1, 0, 0 ][ .. ], 1 ) );
+    let program = Compiler::new().opt_level( 0 ).tape_op( true ).compile( "+++>%++" ).unwrap();
+    let mut e = engine( &program, &[] );
+    e.run().unwrap();
+    assert_eq!( ( e.active_tape(), e.tape(), e.loc() ), ( 1, &[ 2, 0, 0, 0 ][ .. ], 0 ) );
+    assert_eq!( compile( "%" ).disassemble(), "     0  HALT\n" );
+}
+
+#[test]
+fn halt_stops_before_the_rest_of_the_program() {
+    let program = Compiler::new().opt_level( 0 ).halt_op( true ).compile( "+@+" ).unwrap();
+    let mut e = engine( &program, &[] );
+    e.run().unwrap();
+    assert_eq!( ( e.tape(), e.pc(), e.steps() ), ( &[ 1, 0, 0, 0 ][ .. ], 1, 2 ) );
+    assert_eq!( program.disassemble(), "     0  INCR\n     1  HALT\n     2  INCR\n     3  HALT\n" );
+    let bytes = program.to_bytes();
+    assert_eq!( brainfuck::Program::from_bytes( &bytes ).unwrap().to_bytes(), bytes );
+    assert_eq!( compile( "@" ).disassemble(), "     0  HALT\n" );
+}
+
+//  The binary form starts with the magic number and version, and then each
+//  slot's opcode id and operand, so the operand of the OPEN is byte 6 and
+//  that of the CLOSE byte 10.
+#[test]
+fn a_jump_out_of_the_program_is_a_corrupt_jump_table() {
+    let bytes = compile( "[-]" ).to_bytes();
+    assert_eq!( ( &bytes[ 5..7 ], &bytes[ 9..11 ] ), ( &[ 18, 3 ][ .. ], &[ 19, 1 ][ .. ] ) );
+    for at in [ 6, 10 ] {
+        let mut corrupt = bytes.clone();
+        corrupt[ at ] = 100;
+        let pc = if at == 6 { 0 } else { 2 };
+        assert!( matches!( brainfuck::Program::from_bytes( &corrupt ), Err( BfError::CorruptJumpTable { pc: p } ) if p == pc ) );
+    }
+}
+
+//  At the default level of optimization.
+#[test]
+fn opposing_pairs_cancel_out() {
+    let optimized = | src: &str | Compiler::new().compile( src ).unwrap().disassemble();
+    assert_eq!( optimized( "+-" ), "     0  HALT\n" );
+    assert_eq!( optimized( ">< >" ), "     0  RIGHT\n     1  HALT\n" );
+    assert_eq!( optimized( "+>-+<-" ), "     0  HALT\n" );
+    assert_eq!( optimized( ">[<]" ), "     0  RIGHT\n     1  SCAN_LEFT\n     2  HALT\n" );
+    assert_eq!( Compiler::new().wrap( false ).compile( "+-" ).unwrap().disassemble(), "     0  INCR_SAT\n     1  DECR_SAT\n     2  HALT\n" );
+}
+
+//  A comment loop at the very start is kept, since the tape may not start
+//  out zero.
+#[test]
+fn a_comment_loop_where_the_cell_is_zero_compiles_to_nothing() {
+    let optimized = | src: &str | Compiler::new().opt_level( 1 ).compile( src ).unwrap().disassemble();
+    assert_eq!( optimized( ",[.,][Echoes its input, [nested] or not.]+" ), optimized( ",[.,]+" ) );
+    assert_eq!( optimized( "[-][-][Clears a cell.]" ), "     0  OPEN         -> 3\n     1  DECR\n     2  CLOSE        -> 1\n     3  HALT\n" );
+    assert_eq!( Compiler::new().compile( "[-][Clears a cell.]" ).unwrap().disassemble(), "     0  SET_ZERO\n     1  HALT\n" );
+    assert_eq!( optimized( "[A comment.]" ), "     0  OPEN         -> 3\n     1  PUT\n     2  CLOSE        -> 1\n     3  HALT\n" );
+    assert!( matches!( Compiler::new().compile( "[-][" ), Err( BfError::UnmatchedOpen { .. } ) ) );
+}
+
+#[test]
+fn put_num_is_ignored_unless_enabled() {
+    assert_eq!( compile( ":" ).disassemble(), "     0  HALT\n" );
+}
+
+#[test]
+fn on_write_sees_each_cell_written() {
+    let run = | program: &Program | {
+        let mut writes = Vec::new();
+        Engine::< i8 >::new( program, 4 ).with_io( std::io::empty(), std::io::sink() ).on_write( | at, cell | writes.push( ( at, cell ) ) ).run().unwrap();
+        writes
+    };
+    assert_eq!( run( &compile( "+>++" ) ), [ ( 0, 1 ), ( 1, 1 ), ( 1, 2 ) ] );
+    let muladd = Compiler::new().debug_op( false ).compile( "++[->+++>+<<]" ).unwrap();
+    assert_eq!( run( &muladd ), [ ( 0, 2 ), ( 1, 6 ), ( 2, 2 ), ( 0, 0 ) ] );
+}
+
+#[test]
+fn an_unsigned_byte_cell_saturates_at_0_and_255() {
+    let run = | src: &str | {
+        let program = Compiler::new().overflow( Overflow::Saturate ).compile( src ).unwrap();
+        let mut e = Engine::< u8 >::new( &program, 2 ).with_io( std::io::empty(), std::io::sink() );
+        e.run().unwrap();
+        e.tape().to_vec()
+    };
+    assert_eq!( run( "->--" ), [ 0, 0 ] );
+    assert_eq!( run( &format!( "{}>+[-]", "+".repeat( 256 ) ) ), [ 255, 0 ] );
+}
+
+#[test]
+fn on_halt_is_called_once_with_the_final_cell() {
+    let program = Compiler::new().halt_op( true ).compile( "++++++[>+++++++<-]>@+" ).unwrap();
+    let mut halts = Vec::new();
+    let mut e = Engine::< i8 >::new( &program, 4 ).with_io( std::io::empty(), std::io::sink() ).on_halt( | e | halts.push( ( e.pc(), e.cell( e.loc() ) ) ) );
+    e.run().unwrap();
+    e.reset();
+    e.run().unwrap();
+    assert!( matches!( Engine::< i8 >::new( &compile( "<" ), 4 ).on_halt( | _ | panic!( "never halts" ) ).run(), Err( BfError::PointerUnderflow { .. } ) ) );
+    drop( e );
+    assert_eq!( halts, [ ( 3, Some( 42 ) ) ] );
+}
+
+//  Every style of dispatch leaves the engine in the same state, having
+//  taken the same number of steps.
+#[test]
+fn dispatch_styles_agree_on_nested_loops() {
+    let program = Compiler::new().opt_level( 0 ).compile( "++++[>+++[>++<-]<-]>>[<+>-]" ).unwrap();
+    let runs: Vec< _ > = [ Engine::run, Engine::run_direct, Engine::run_switch, Engine::run_token ].into_iter().map( | run | {
+        let mut e = engine( &program, &[] );
+        run( &mut e ).unwrap();
+        ( e.tape().to_vec(), e.loc(), e.steps() )
+    } ).collect();
+    assert_eq!( ( &runs[ 0 ].0[ .. ], runs[ 0 ].1 ), ( &[ 0, 24, 0, 0 ][ .. ], 2 ) );
+    assert_eq!( runs[ 1 ], runs[ 0 ] );
+    assert_eq!( runs[ 2 ], runs[ 0 ] );
+    assert_eq!( runs[ 3 ], runs[ 0 ] );
+}
+
+#[test]
+fn a_budgeted_run_resumes_where_it_stopped() {
+    let program = Compiler::new().compile( "++++++++[>++++++++<-]>+.+." ).unwrap();
+    let mut output = Vec::new();
+    let mut e = Engine::< i8 >::new( &program, 4 ).with_io( std::io::empty(), &mut output );
+    let mut calls = 0;
+    loop {
+        let before = e.steps();
+        let state = e.run_budgeted( 5 ).unwrap();
+        assert!( e.steps() - before <= 5 );
+        calls += 1;
+        if state == StepResult::Halted {
+            break;
+        }
+    }
+    let steps = e.steps();
+    assert_eq!( calls, steps.div_ceil( 5 ) );
+    assert_eq!( e.run_budgeted( 5 ).unwrap(), StepResult::Halted );
+    assert_eq!( e.steps(), steps );
+    drop( e );
+    assert_eq!( output, b"AB" );
+}
+
+//  The flag is set partway through the loop, which would otherwise run
+//  forever, and is seen at the next check, every 4096 steps.
+#[test]
+fn setting_the_interrupt_flag_stops_the_run() {
+    let interrupted = AtomicBool::new( false );
+    let program = compile( "+[>+<]" );
+    let mut e = Engine::< i8 >::new( &program, 4 ).interrupt( &interrupted ).on_write( | at, cell | if at == 1 && cell == 100 {
+        interrupted.store( true, Ordering::Relaxed );
+    } );
+    let summary = e.run_summary().unwrap();
+    assert_eq!( ( summary.reason, summary.steps ), ( HaltReason::Interrupted, 4096 ) );
+    assert!( !e.is_halted() );
+}
+
+//  A reader that has gone away, as when the driver's output is piped into
+//  `head`.
+struct ClosedPipe;
+
+impl std::io::Write for ClosedPipe {
+    fn write( &mut self, _buf: &[ u8 ] ) -> std::io::Result< usize > {
+        Err( std::io::ErrorKind::BrokenPipe.into() )
+    }
+
+    fn flush( &mut self ) -> std::io::Result< () > {
+        Ok(())
+    }
+}
+
+//  The loop would print forever, so only the broken pipe stops it.
+#[test]
+fn a_broken_pipe_stops_the_run() {
+    let program = compile( "+[.]" );
+    let mut e = Engine::< i8 >::new( &program, 4 ).with_io( std::io::empty(), ClosedPipe ).max_steps( 1_000_000 );
+    assert!( matches!( e.run(), Err( BfError::OutputClosed { pc: 2, .. } ) ) );
+    let mut e = Engine::< i8 >::new( &compile( "+." ), 4 ).with_io( std::io::empty(), ClosedPipe );
+    assert_eq!( e.run().unwrap_err().to_string(), "Output closed (pc=2)" );
+}
+
+//  A device that fails every read, rather than reaching end of input.
+struct FailingInput;
+
+impl Read for FailingInput {
+    fn read( &mut self, _buf: &mut [ u8 ] ) -> std::io::Result< usize > {
+        Err( std::io::ErrorKind::PermissionDenied.into() )
+    }
+}
+
+//  Only end of input comes under the EOF policy; any other failure to
+//  read stops the run where it is.
+#[test]
+fn a_failing_read_stops_the_run_with_an_io_error() {
+    let program = compile( "+,+" );
+    let mut e = Engine::< i8 >::new( &program, 4 ).with_io( FailingInput, std::io::sink() ).eof( EofPolicy::Zero );
+    assert!( matches!( e.run(), Err( BfError::Io( err ) ) if err.kind() == std::io::ErrorKind::PermissionDenied ) );
+    assert_eq!( ( e.tape()[ 0 ], e.pc() ), ( 1, 1 ) );
+}
+
+fn double< C: brainfuck::Cell >( e: &mut Engine< C > ) -> Result< (), BfError > {
+    e.set_current_cell( e.current_cell().wrapping_add_cell( e.current_cell() ) );
+    e.advance();
+    Ok(())
+}
+
+//  A registered command runs under every style of dispatch, and survives
+//  the optimizer as an instruction of its own.
+#[test]
+fn a_registered_command_runs_its_own_opcode() {
+    let compiler = Compiler::new().register_op::< i8 >( '*', double ).unwrap();
+    let program = compiler.compile( "+++*>++*<[->+<]>*." ).unwrap();
+    assert!( program.disassemble().contains( "CUSTOM       '*'" ), "{}", program.disassemble() );
+    for run in [ Engine::run, Engine::run_direct, Engine::run_switch, Engine::run_token ] {
+        let mut e = engine( &program, &[] );
+        run( &mut e ).unwrap();
+        assert_eq!( e.tape(), [ 0, 20, 0, 0 ] );
+    }
+    let mut e = engine( &compiler.opt_level( 0 ).compile( "*" ).unwrap(), &[ 5 ] );
+    e.step().unwrap();
+    assert_eq!( ( e.pc(), e.tape()[ 0 ] ), ( 1, 10 ) );
+}
+
+//  A registered command writes the current cell, as far as protection and
+//  the hook on writes can tell.
+#[test]
+fn a_registered_command_is_a_write_to_the_current_cell() {
+    let program = Compiler::new().opt_level( 0 ).register_op::< i8 >( '*', double ).unwrap().compile( ">+*<*" ).unwrap();
+    let mut e = engine( &program, &[ 1 ] ).protect( 0..1 );
+    assert!( matches!( e.run(), Err( BfError::WriteProtected { addr: 0, pc: 4, .. } ) ) );
+    assert_eq!( e.tape(), [ 1, 2, 0, 0 ] );
+    let mut writes = Vec::new();
+    engine( &program, &[ 1 ] ).on_write( | at, cell | writes.push( ( at, cell ) ) ).run().unwrap();
+    assert_eq!( writes, [ ( 1, 1 ), ( 1, 2 ), ( 0, 2 ) ] );
+}
+
+#[test]
+fn a_registered_command_cannot_replace_a_core_one_or_run_on_other_cells() {
+    assert!( matches!( Compiler::new().register_op::< i8 >( '+', double ), Err( BfError::InvalidArgument { .. } ) ) );
+    let program = Compiler::new().register_op::< i8 >( '*', double ).unwrap().compile( "+*" ).unwrap();
+    let result = Engine::< i16 >::new( &program, 4 ).with_io( std::io::empty(), std::io::sink() ).run();
+    assert!( matches!( result, Err( BfError::IllegalInstruction { pc: 1 } ) ), "{:?}", result );
+    assert!( Program::from_bytes( &program.to_bytes() ).is_err() );
+}
+
+//  A program with no commands is a lone HALT, which every style of
+//  dispatch and the sparse tape run to the end at once.
+fn runs_to_completion_with_no_output( src: &str ) {
+    let program = Compiler::new().compile( src ).unwrap();
+    assert_eq!( program.disassemble(), "     0  HALT\n" );
+    for run in [ Engine::run, Engine::run_direct, Engine::run_switch, Engine::run_token ] {
+        let mut e = engine( &program, &[] );
+        run( &mut e ).unwrap();
+        assert_eq!( ( e.is_halted(), e.steps(), e.tape() ), ( true, 1, &[ 0; 4 ][ .. ] ) );
+    }
+    let mut output = Vec::new();
+    assert!( brainfuck::sparse::run_sparse::< i8 >( &program, std::io::empty(), &mut output ).unwrap().is_empty() );
+    assert!( output.is_empty() );
+}
+
+#[test]
+fn an_empty_program_halts_at_once() {
+    runs_to_completion_with_no_output( "" );
+}
+
+#[test]
+fn a_program_of_whitespace_halts_at_once() {
+    runs_to_completion_with_no_output( "   \n\n" );
+}
+
+#[test]
+fn a_program_of_prose_halts_at_once() {
+    runs_to_completion_with_no_output( "just prose" );
+}
@@ -0,0 +1,5 @@
+[add.bf -- reads two digits and prints their sum and then a newline; the
+sum must be a single digit too]
+,>,[<+>-]
+++++++[<-------->-]<.
+[-]++++++++++.
@@ -0,0 +1,4 @@
+[echo.bf -- copies its input to its output. Each cell is cleared before
+the next read so that the loop ends at end of input, whatever the EOF
+policy.]
+,[.[-],]
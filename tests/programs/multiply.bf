@@ -0,0 +1,4 @@
+[multiply.bf -- prints the product 2 * 3 * 7 = 42 which is an asterisk
+using a loop nested in a loop and then a newline]
+++[>+++[>+++++++<-]<-]>>.
+[-]++++++++++.
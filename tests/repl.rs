@@ -0,0 +1,31 @@
+/*
+    Drives the REPL with scripted lines and checks its responses.
+*/
+
+use brainfuck::{repl, Compiler, Engine};
+
+fn repl( lines: &[ &str ] ) -> String {
+    let compiler = Compiler::new();
+    let mut engine = Engine::< i8 >::new( &compiler.compile( "" ).unwrap(), 8 ).with_io( std::io::empty(), std::io::sink() );
+    let mut out = Vec::new();
+    repl::repl( &compiler, &mut engine, lines.iter().map( | l | l.to_string() ), &mut out ).unwrap();
+    String::from_utf8( out ).unwrap()
+}
+
+#[test]
+fn a_loop_opened_on_one_line_runs_when_the_next_closes_it() {
+    let out = repl( &[ "++[>+++", "<-]>", "+" ] );
+    assert_eq!( out, concat!(
+        "bf> waiting for ]> loc=1: 0 [6] 0 0 0 0\n",
+        "bf> loc=1: 0 [7] 0 0 0 0\n",
+        "bf> ",
+    ) );
+}
+
+#[test]
+fn an_error_leaves_the_tape_and_carries_on() {
+    let out = repl( &[ "+", "]", "<", "+" ] );
+    assert!( out.contains( "bf> Error: Unmatched closing bracket at line 1, column 1\n" ), "{}", out );
+    assert!( out.contains( "bf> Error: Data pointer moved left of cell 0 at line 1, column 1 (pc=0, loc=0)\n" ), "{}", out );
+    assert!( out.ends_with( "bf> loc=0: [2] 0 0 0 0\nbf> " ), "{}", out );
+}
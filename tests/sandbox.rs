@@ -0,0 +1,51 @@
+/*
+    Tests of `Engine::run_sandboxed`, which puts limits on the steps, the
+    output and the tape of a run all at once, and fails with an error that
+    names whichever of them the program reaches first.
+*/
+
+use brainfuck::{BfError, Engine, SandboxConfig};
+
+fn sandboxed( src: &str, config: SandboxConfig ) -> ( Result< u64, BfError >, Vec< u8 > ) {
+    let program = brainfuck::compile( src ).unwrap();
+    let mut output = Vec::new();
+    let result = Engine::< i8 >::new( &program, 8 ).with_io( std::io::empty(), &mut output ).run_sandboxed( &program, &config ).map( | summary | summary.steps );
+    ( result, output )
+}
+
+#[test]
+fn a_program_within_every_limit_runs_to_the_end() {
+    let ( result, output ) = sandboxed( "++++++[>++++++++<-]>.", SandboxConfig::default() );
+    assert!( result.is_ok() );
+    assert_eq!( output, b"0" );
+}
+
+#[test]
+fn the_step_limit_binds_on_a_loop_that_never_ends() {
+    let config = SandboxConfig { max_steps: 1000, max_output: 1000, max_cells: 1000 };
+    let ( result, _ ) = sandboxed( "+[]", config );
+    assert!( matches!( result, Err( BfError::StepLimitExceeded { limit: 1000, .. } ) ), "{:?}", result );
+}
+
+#[test]
+fn the_output_limit_binds_on_a_loop_that_keeps_writing() {
+    let config = SandboxConfig { max_steps: 1000, max_output: 10, max_cells: 1000 };
+    let ( result, output ) = sandboxed( "+[.]", config );
+    assert!( matches!( result, Err( BfError::OutputLimitExceeded { limit: 10, .. } ) ), "{:?}", result );
+    assert_eq!( output.len(), 10 );
+}
+
+#[test]
+fn the_tape_limit_binds_on_a_loop_that_keeps_moving_right() {
+    let config = SandboxConfig { max_steps: 1000, max_output: 1000, max_cells: 20 };
+    let ( result, _ ) = sandboxed( "+[>+]", config );
+    assert!( matches!( result, Err( BfError::TapeLimitExceeded { limit: 20, .. } ) ), "{:?}", result );
+}
+
+#[test]
+fn the_tape_grows_past_the_engine_size_up_to_the_limit() {
+    let config = SandboxConfig { max_cells: 20, ..SandboxConfig::default() };
+    let ( result, output ) = sandboxed( ">>>>>>>>>>>>>>>>>>>+.", config );
+    assert!( result.is_ok(), "{:?}", result );
+    assert_eq!( output, b"\x01" );
+}
@@ -0,0 +1,46 @@
+/*
+    Runs programs on the sparse tape, which stores only the cells that are
+    not zero, however far apart they are.
+*/
+
+use std::fs;
+
+use brainfuck::sparse::{run_sparse, SparseTape};
+use brainfuck::{BfError, Compiler};
+
+#[test]
+fn a_far_cell_is_stored_without_the_cells_before_it() {
+    //  Moves a million cells right, in runs that fold into RIGHT_N.
+    let src = format!( "{}++++[-.+++++]+", ">".repeat( 1_000_000 ) );
+    let program = Compiler::new().compile( &src ).unwrap();
+    let mut output = Vec::new();
+    let tape = run_sparse::< i8 >( &program, std::io::empty(), &mut output ).unwrap();
+    assert_eq!( tape.head(), 1_000_000 );
+    assert_eq!( tape.iter().collect::< Vec< _ > >(), [ ( 1_000_000, 1 ) ] );
+    assert_eq!( output.len(), 63 );
+    assert_eq!( tape.get( 999_999 ), 0 );
+}
+
+#[test]
+fn writing_zero_removes_a_cell() {
+    let mut tape = SparseTape::< i16 >::new();
+    tape.set( 7, 300 );
+    assert_eq!( ( tape.len(), tape.get( 7 ) ), ( 1, 300 ) );
+    tape.set( 7, 0 );
+    assert!( tape.is_empty() );
+}
+
+#[test]
+fn hello_runs_on_a_sparse_tape() {
+    let src = fs::read_to_string( "tests/programs/hello.bf" ).unwrap();
+    let program = Compiler::new().debug_op( false ).compile( &src ).unwrap();
+    let mut output = Vec::new();
+    run_sparse::< i8 >( &program, std::io::empty(), &mut output ).unwrap();
+    assert_eq!( output, fs::read( "tests/programs/hello.out" ).unwrap() );
+}
+
+#[test]
+fn the_pointer_cannot_move_left_of_cell_0() {
+    let program = Compiler::new().compile( ">+<<" ).unwrap();
+    assert!( matches!( run_sparse::< i8 >( &program, std::io::empty(), std::io::sink() ), Err( BfError::PointerUnderflow { pc: 2, loc: 1, .. } ) ) );
+}
@@ -0,0 +1,28 @@
+/*
+    Runs programs with `run_async` against in-memory streams. This needs
+    the library built with tokio; see the tokio target of rust.makefile.
+    Without it, the file compiles to no tests at all.
+*/
+
+#![cfg(feature = "tokio")]
+
+use std::fs;
+
+fn run_async( name: &str, input: &[ u8 ] ) -> Vec< u8 > {
+    let src = fs::read_to_string( format!( "tests/programs/{}.bf", name ) ).unwrap();
+    let program = brainfuck::Compiler::new().debug_op( false ).compile( &src ).unwrap();
+    let mut output = Vec::new();
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on( brainfuck::run_async( &program, input, &mut output ) ).unwrap();
+    output
+}
+
+#[test]
+fn hello_world_runs_over_async_streams() {
+    assert_eq!( run_async( "hello", b"" ), fs::read( "tests/programs/hello.out" ).unwrap() );
+}
+
+#[test]
+fn get_awaits_the_input_stream() {
+    assert_eq!( run_async( "echo", b"tokio\n" ), b"tokio\n" );
+}
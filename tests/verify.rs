@@ -0,0 +1,60 @@
+/*
+    Checks that `verify::verify` accepts a program that the optimizer
+    leaves doing the same, and reports where the runs differ when a pass
+    is broken.
+*/
+
+use brainfuck::ir::{Instr, Located};
+use brainfuck::optimize::Optimizer;
+use brainfuck::{verify, BfError, Compiler, Engine, Program};
+
+fn run( program: &Program ) -> Result< ( Vec< u8 >, u64 ), BfError > {
+    let mut output = Vec::new();
+    let mut engine = Engine::< i8 >::new( program, 64 ).max_steps( 100_000 ).with_io( std::io::empty(), &mut output );
+    engine.run()?;
+    let checksum = engine.tape_checksum();
+    drop( engine );
+    Ok( ( output, checksum ) )
+}
+
+//  A pass with a bug that no real one should have: it turns every `-`
+//  into a `+`.
+fn broken_pass( instrs: Vec< Located > ) -> Vec< Located > {
+    instrs.into_iter().map( | located | match located.instr {
+        Instr::Decr => Located { instr: Instr::Incr, ..located },
+        _ => located,
+    } ).collect()
+}
+
+const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.";
+
+#[test]
+fn a_correct_optimizer_agrees_with_the_unoptimized_program() {
+    for level in 1..=3 {
+        assert!( verify::verify( &Compiler::new().opt_level( 0 ), &Compiler::new().opt_level( level ), HELLO, run ).is_ok(), "--opt={}", level );
+    }
+}
+
+#[test]
+fn a_broken_pass_is_caught_with_where_the_output_differs() {
+    let broken = Compiler::new().optimizer( Optimizer::level( 3, true ).pass( "broken", broken_pass ) );
+    let result = verify::verify( &Compiler::new().opt_level( 0 ), &broken, "+++.-.", run );
+    match result {
+        Err( BfError::Diverged { message } ) => assert_eq!( message, "the output differs from byte 1, with \"\\u{4}\" rather than \"\\u{2}\"" ),
+        other => panic!( "{:?}", other ),
+    }
+}
+
+#[test]
+fn a_broken_pass_that_only_changes_the_tape_is_caught_by_its_checksum() {
+    let broken = Compiler::new().optimizer( Optimizer::new().pass( "broken", broken_pass ) );
+    let result = verify::verify( &Compiler::new().opt_level( 0 ), &broken, "+++-", run );
+    assert!( matches!( &result, Err( BfError::Diverged { message } ) if message.starts_with( "the tape checksum is " ) ), "{:?}", result );
+}
+
+#[test]
+fn runs_that_both_fail_in_the_same_way_agree() {
+    assert!( verify::verify( &Compiler::new().opt_level( 0 ), &Compiler::new(), "++<", run ).is_ok() );
+    let step_limit = verify::verify( &Compiler::new().opt_level( 0 ), &Compiler::new(), "+[]", run );
+    assert!( matches!( step_limit, Err( BfError::StepLimitExceeded { .. } ) ), "{:?}", step_limit );
+}